@@ -0,0 +1,158 @@
+use crate::{
+    aws::{self, LambdaClient},
+    error::Error,
+};
+use structopt::StructOpt;
+
+/// Relates a change of a function's $LATEST env to its published versions
+#[derive(StructOpt, PartialEq, Debug, Default, Clone)]
+pub struct Release {
+    /// Alias or version the change is intended for
+    #[structopt(long = "qualifier")]
+    pub qualifier: Option<String>,
+    /// Publishes a new version once updated
+    #[structopt(long = "publish")]
+    pub publish: bool,
+    /// Points the qualifier's alias at the newly published version
+    #[structopt(long = "repoint", requires = "publish")]
+    pub repoint: bool,
+}
+
+/// An alias and the version it currently points at
+#[derive(Debug, PartialEq)]
+pub struct Resolved {
+    pub alias: String,
+    pub version: String,
+}
+
+/// Qualifiers which directly name a version rather than an alias
+fn is_version(qualifier: &str) -> bool {
+    qualifier == "$LATEST" || qualifier.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Resolves a qualifier to the version it refers to. Only aliases
+/// yield a resolution, versions are used as is
+pub async fn resolve(
+    lambda: LambdaClient,
+    function: String,
+    qualifier: String,
+) -> Result<Option<Resolved>, Error> {
+    if is_version(&qualifier) {
+        return Ok(None);
+    }
+    let request = lambda
+        .get_alias()
+        .function_name(function)
+        .name(qualifier.clone());
+    let alias = aws::retry(|| request.clone().send()).await?;
+    Ok(Some(Resolved {
+        alias: qualifier,
+        version: alias.function_version.unwrap_or_default(),
+    }))
+}
+
+/// `function:qualifier` naming the version a qualifier refers to, along
+/// with the alias resolution if there was one
+pub async fn qualified(
+    lambda: LambdaClient,
+    function: String,
+    qualifier: Option<String>,
+) -> Result<(String, Option<Resolved>), Error> {
+    let qualifier = match qualifier {
+        None => return Ok((function, None)),
+        Some(qualifier) => qualifier,
+    };
+    let resolved = resolve(lambda, function.clone(), qualifier.clone()).await?;
+    let version = resolved
+        .as_ref()
+        .map(|r| r.version.clone())
+        .unwrap_or(qualifier);
+    Ok((format!("{}:{}", function, version), resolved))
+}
+
+/// Publishes the function's current configuration as a new version
+pub async fn publish(
+    lambda: LambdaClient,
+    function: String,
+) -> Result<String, Error> {
+    let request = lambda.publish_version().function_name(function);
+    let conf = aws::retry(|| request.clone().send()).await?;
+    Ok(conf.version.unwrap_or_default())
+}
+
+/// Points an alias at a version
+pub async fn repoint(
+    lambda: LambdaClient,
+    function: String,
+    alias: String,
+    version: String,
+) -> Result<(), Error> {
+    let request = lambda
+        .update_alias()
+        .function_name(function)
+        .name(alias)
+        .function_version(version);
+    aws::retry(|| request.clone().send()).await?;
+    Ok(())
+}
+
+/// Publishes and repoints after an update of $LATEST as requested. Yields
+/// notes on what was done, or what is still required for the change to
+/// reach the qualified version
+pub async fn release(
+    lambda: LambdaClient,
+    function: String,
+    release: Release,
+) -> Result<Vec<String>, Error> {
+    let resolved = match release.qualifier.clone() {
+        Some(qualifier) => resolve(lambda.clone(), function.clone(), qualifier).await?,
+        None => None,
+    };
+    if !release.publish {
+        return Ok(match (resolved, release.qualifier) {
+            (Some(r), _) => vec![format!(
+                "updated $LATEST but alias {} still points at version {}. Publish a version and repoint {} to it, or pass --publish --repoint",
+                r.alias, r.version, r.alias
+            )],
+            (None, Some(version)) => vec![format!(
+                "updated $LATEST but published version {} is immutable. Publish a new version to release the change",
+                version
+            )],
+            _ => Vec::new(),
+        });
+    }
+    let version = publish(lambda.clone(), function.clone()).await?;
+    let published = format!("published version {}", version);
+    Ok(match resolved {
+        Some(r) if release.repoint => {
+            repoint(lambda, function, r.alias.clone(), version.clone()).await?;
+            vec![
+                published,
+                format!(
+                    "repointed alias {} from version {} to {}",
+                    r.alias, r.version, version
+                ),
+            ]
+        }
+        Some(r) => vec![
+            published,
+            format!(
+                "alias {} still points at version {}, pass --repoint to move it",
+                r.alias, r.version
+            ),
+        ],
+        None => vec![published],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_version_distinguishes_aliases() {
+        assert!(is_version("$LATEST"));
+        assert!(is_version("12"));
+        assert!(!is_version("live"));
+    }
+}
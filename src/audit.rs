@@ -0,0 +1,307 @@
+use crate::{aws, diff::Change, error::Error};
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Table mutations are recorded in when auditing is enabled
+static TABLE: Mutex<Option<String>> = Mutex::new(None);
+
+/// File mutations are appended to as JSON lines when auditing is enabled
+static LOG: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Records every mutation in a dynamodb table keyed by a `function` string
+/// partition key and a `timestamp` string sort key
+pub fn set_table(table: Option<String>) {
+    *TABLE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = table
+}
+
+fn table() -> Option<String> {
+    TABLE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Appends a JSON line per mutation to a local file, for those without a table
+pub fn set_log(log: Option<PathBuf>) {
+    *LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = log
+}
+
+fn log() -> Option<PathBuf> {
+    LOG.lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// A recorded mutation of a function's env
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Entry {
+    pub function: String,
+    pub timestamp: DateTime<Utc>,
+    /// Arn of the identity which made the change
+    pub who: String,
+    pub revision_id: Option<String>,
+    /// Key-level changes, without their values
+    pub changes: Vec<String>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.who,
+            self.changes.join(", ")
+        )
+    }
+}
+
+fn string(value: &str) -> AttributeValue {
+    AttributeValue::S(value.into())
+}
+
+/// The string an attribute holds, unless it holds something else
+fn text(value: AttributeValue) -> Option<String> {
+    match value {
+        AttributeValue::S(text) => Some(text),
+        _ => None,
+    }
+}
+
+impl Entry {
+    fn item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+        item.insert("function".into(), string(&self.function));
+        item.insert(
+            "timestamp".into(),
+            string(&self.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        );
+        item.insert("who".into(), string(&self.who));
+        if let Some(revision_id) = &self.revision_id {
+            item.insert("revision_id".into(), string(revision_id));
+        }
+        item.insert(
+            "changes".into(),
+            AttributeValue::L(self.changes.iter().map(|change| string(change)).collect()),
+        );
+        item
+    }
+
+    /// The entry an item holds, unless it was not written by lev
+    fn from_item(mut item: HashMap<String, AttributeValue>) -> Option<Self> {
+        let mut take = |name: &str| item.remove(name);
+        Some(Entry {
+            function: text(take("function")?)?,
+            timestamp: DateTime::parse_from_rfc3339(&text(take("timestamp")?)?)
+                .ok()?
+                .with_timezone(&Utc),
+            who: text(take("who")?)?,
+            revision_id: take("revision_id").and_then(text),
+            changes: match take("changes")? {
+                AttributeValue::L(changes) => changes.into_iter().filter_map(text).collect(),
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// Arn of the identity requests are made as, asked of sts once per process
+async fn who() -> Result<String, Error> {
+    static WHO: Mutex<Option<String>> = Mutex::new(None);
+    if let Some(who) = WHO.lock().ok().and_then(|who| who.clone()) {
+        return Ok(who);
+    }
+    let request = aws::sts_client().await.get_caller_identity();
+    let identity = aws::retry(|| request.clone().send()).await?;
+    let who = identity.arn.unwrap_or_default();
+    if let Ok(mut cached) = WHO.lock() {
+        *cached = Some(who.clone());
+    }
+    Ok(who)
+}
+
+fn append(
+    log: &Path,
+    entry: &Entry,
+) -> Result<(), Error> {
+    if let Some(parent) = log.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(log)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+async fn put(
+    table: String,
+    entry: &Entry,
+) -> Result<(), Error> {
+    let request = aws::dynamodb_client()
+        .await
+        .put_item()
+        .table_name(table)
+        .set_item(Some(entry.item()))
+        .condition_expression("attribute_not_exists(#timestamp)")
+        .expression_attribute_names("#timestamp", "timestamp");
+    aws::retry(|| request.clone().send()).await?;
+    Ok(())
+}
+
+/// Records a mutation of a function's env in the table and log, whichever
+/// auditing is enabled with. Entries are only ever added, never overwritten
+pub async fn record(
+    function: String,
+    revision_id: Option<String>,
+    changes: &[Change],
+) -> Result<(), Error> {
+    let (table, log) = (table(), log());
+    if table.is_none() && log.is_none() {
+        return Ok(());
+    }
+    let changes = changes.iter().map(Change::summary).collect::<Vec<_>>();
+    let entry = Entry {
+        function,
+        timestamp: Utc::now(),
+        who: who().await?,
+        revision_id,
+        changes,
+    };
+    if let Some(log) = log {
+        append(&log, &entry)?;
+    }
+    match table {
+        Some(table) => put(table, &entry).await,
+        None => Ok(()),
+    }
+}
+
+/// A function's entries in a log, newest first
+fn logged(
+    log: &Path,
+    function: &str,
+    limit: Option<usize>,
+) -> Result<Vec<Entry>, Error> {
+    let mut entries = fs::read_to_string(log)?
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        .filter(|entry| entry.function == function)
+        .collect::<Vec<_>>();
+    entries.reverse();
+    entries.truncate(limit.unwrap_or(entries.len()));
+    Ok(entries)
+}
+
+/// A function's recorded mutations, newest first. They are read from the
+/// table when there is one, otherwise the log
+pub async fn history(
+    function: String,
+    limit: Option<usize>,
+) -> Result<Vec<Entry>, Error> {
+    let table = match (table(), log()) {
+        (Some(table), _) => table,
+        (None, Some(log)) => return logged(&log, &function, limit),
+        (None, None) => return Err(Error::AuditDisabled),
+    };
+    let request = aws::dynamodb_client()
+        .await
+        .query()
+        .table_name(table)
+        .key_condition_expression("#function = :function")
+        .expression_attribute_names("#function", "function")
+        .expression_attribute_values(":function", string(&function))
+        .scan_index_forward(false);
+    let mut entries = Vec::new();
+    // dynamodb continues pages from the last key read rather than a token
+    let mut exclusive_start_key = None;
+    loop {
+        let request = request.clone().set_exclusive_start_key(exclusive_start_key);
+        let page = aws::retry(|| request.clone().send()).await?;
+        entries.extend(
+            page.items
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(Entry::from_item),
+        );
+        match page.last_evaluated_key {
+            Some(key) if limit.map(|limit| entries.len() < limit).unwrap_or(true) => {
+                exclusive_start_key = Some(key)
+            }
+            _ => {
+                entries.truncate(limit.unwrap_or(entries.len()));
+                return Ok(entries);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_round_trip_through_items() {
+        let entry = Entry {
+            function: "foo".into(),
+            timestamp: "2019-07-01T12:00:00.000001Z".parse().unwrap(),
+            who: "arn:aws:iam::123456789012:user/bob".into(),
+            revision_id: Some("abc".into()),
+            changes: vec!["+ A".into(), "~ B".into()],
+        };
+        assert_eq!(Entry::from_item(entry.item()), Some(entry))
+    }
+
+    #[test]
+    fn logged_reads_newest_entries_first() {
+        let log = std::env::temp_dir().join(format!("lev-audit-test-{}.jsonl", std::process::id()));
+        let entry = |function: &str, change: &str| Entry {
+            function: function.into(),
+            timestamp: Utc::now(),
+            who: "arn:aws:iam::123456789012:user/ci".into(),
+            revision_id: None,
+            changes: vec![change.into()],
+        };
+        for entry in &[
+            entry("foo", "+ A"),
+            entry("bar", "+ B"),
+            entry("foo", "~ A"),
+        ] {
+            append(&log, entry).unwrap();
+        }
+        let changes = |entries: Vec<Entry>| {
+            entries
+                .into_iter()
+                .flat_map(|entry| entry.changes)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            logged(&log, "foo", None).map(changes).ok(),
+            Some(vec!["~ A".to_string(), "+ A".to_string()])
+        );
+        assert_eq!(
+            logged(&log, "foo", Some(1)).map(changes).ok(),
+            Some(vec!["~ A".to_string()])
+        );
+        let _ = fs::remove_file(log);
+    }
+
+    #[test]
+    fn from_item_skips_foreign_items() {
+        let mut item = HashMap::new();
+        item.insert("function".to_string(), string("foo"));
+        assert_eq!(Entry::from_item(item), None)
+    }
+}
@@ -0,0 +1,782 @@
+use crate::{error::Error, trace};
+use aws_config::{
+    ecs::EcsCredentialsProvider,
+    environment::EnvironmentVariableCredentialsProvider,
+    identity::IdentityCache,
+    imds::{self, credentials::ImdsCredentialsProvider},
+    meta::{credentials::CredentialsProviderChain, region::RegionProviderChain},
+    profile::ProfileFileCredentialsProvider,
+    provider_config::ProviderConfig,
+    retry::RetryConfig,
+    web_identity_token::WebIdentityTokenCredentialsProvider,
+    BehaviorVersion, Region, SdkConfig,
+};
+use aws_credential_types::provider::{self, error::CredentialsError, ProvideCredentials};
+use aws_runtime::env_config::file::EnvConfigFiles;
+use aws_sdk_lambda::types::FunctionConfiguration;
+use aws_smithy_http_client::{tls, Builder as HttpClientBuilder};
+use aws_smithy_runtime_api::{
+    client::{http::SharedHttpClient, orchestrator::HttpResponse, result},
+    http::StatusCode,
+};
+use aws_smithy_types::{
+    body::SdkBody,
+    error::{display::DisplayErrorContext, metadata::ProvideErrorMetadata},
+};
+use aws_types::os_shim_internal;
+use futures::{stream, Stream};
+use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    env,
+    error::Error as StdError,
+    fmt,
+    future::Future,
+    iter,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::OnceCell, time};
+
+pub use aws_sdk_dynamodb::Client as DynamoDbClient;
+pub use aws_sdk_ecs::Client as EcsClient;
+pub use aws_sdk_kms::Client as KmsClient;
+pub use aws_sdk_lambda::Client as LambdaClient;
+pub use aws_sdk_resourcegroupstagging::Client as TaggingClient;
+pub use aws_sdk_s3::Client as S3Client;
+pub use aws_sdk_secretsmanager::Client as SecretsManagerClient;
+pub use aws_sdk_ssm::Client as SsmClient;
+pub use aws_sdk_sts::Client as StsClient;
+
+/// A failed request, along with the response it failed with when there was one
+pub type SdkError<E> = result::SdkError<E, HttpResponse>;
+
+/// Retries attempted for throttled or undelivered requests
+static MAX_RETRIES: AtomicUsize = AtomicUsize::new(3);
+
+/// Milliseconds credentials may take to resolve, instance metadata in
+/// particular being slow to answer on some EC2 hosts and CI runners
+static CREDENTIAL_TIMEOUT: AtomicU64 = AtomicU64::new(10_000);
+
+/// Upper bound on the delay before any single retry
+const MAX_BACKOFF: Duration = Duration::from_secs(20);
+
+/// Delay before the first retry, doubling with each attempt
+const BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+pub fn set_max_retries(retries: usize) {
+    MAX_RETRIES.store(retries, Ordering::SeqCst)
+}
+
+/// Gives up on resolving credentials after `timeout`, rather than the
+/// second or two instance and container metadata are otherwise given. Only
+/// takes effect before the first client is made, credentials being resolved
+/// once
+pub fn set_credential_timeout(timeout: Duration) {
+    CREDENTIAL_TIMEOUT.store(timeout.as_millis() as u64, Ordering::SeqCst)
+}
+
+/// Region clients talk to in place of the one lev is configured for
+static REGION: Mutex<Option<Region>> = Mutex::new(None);
+
+/// Has clients talk to a region other than the one lev is configured for
+pub fn set_region(region: Option<Region>) {
+    *REGION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = region
+}
+
+/// A region by its name, such as `eu-west-1`
+pub fn parse_region(name: &str) -> Result<Region, Error> {
+    // names are a partition's prefix, a direction and a number, such as
+    // us-gov-west-1 or ap-southeast-2
+    let parts = name.split('-').collect::<Vec<_>>();
+    let valid = parts.len() >= 3
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+        && parts[parts.len() - 1].chars().all(|c| c.is_ascii_digit());
+    if valid {
+        Ok(Region::new(name.to_string()))
+    } else {
+        Err(Error::InvalidRegion(name.to_string()))
+    }
+}
+
+/// Holds back every request to a partition while one of them is throttled,
+/// so the requests sharing it back off together rather than each finding
+/// out for itself
+#[derive(Default)]
+struct Limiter {
+    until: Mutex<Option<time::Instant>>,
+}
+
+impl Limiter {
+    /// Holds back requests for at least `delay`
+    fn hold(
+        &self,
+        delay: Duration,
+    ) {
+        let until = time::Instant::now() + delay;
+        let mut held = self
+            .until
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if held.is_none_or(|held| held < until) {
+            *held = Some(until);
+        }
+    }
+
+    /// Waits out any hold
+    async fn ready(&self) {
+        let until = *self
+            .until
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(until) = until {
+            time::sleep_until(until).await;
+        }
+    }
+}
+
+/// The account and region requests are sent to, and what limits them
+#[derive(Clone)]
+struct Partition {
+    region: Option<Region>,
+    limiter: Arc<Limiter>,
+}
+
+tokio::task_local! {
+    /// The partition the requests of a future `in_partition` runs are sent to
+    static PARTITION: Partition;
+}
+
+/// Runs `future` with the clients it makes talking to `region`, and its
+/// requests limited along with every other request to the same account and
+/// region. Either left out is the one lev is configured for
+pub async fn in_partition<F>(
+    account: Option<String>,
+    region: Option<String>,
+    future: F,
+) -> F::Output
+where
+    F: Future,
+{
+    type Limiters = BTreeMap<(Option<String>, Option<String>), Arc<Limiter>>;
+    static LIMITERS: Mutex<Limiters> = Mutex::new(BTreeMap::new());
+    let limiter = LIMITERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry((account, region.clone()))
+        .or_default()
+        .clone();
+    let region = region.map(Region::new);
+    PARTITION.scope(Partition { region, limiter }, future).await
+}
+
+/// The region clients talk to: the one of the partition they are made in,
+/// else the one set, else the one lev is configured for, else us-east-1
+pub async fn region() -> Region {
+    if let Ok(Some(region)) = PARTITION.try_with(|partition| partition.region.clone()) {
+        return region;
+    }
+    let set = REGION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    match set {
+        Some(region) => region,
+        None => shared()
+            .await
+            .region()
+            .cloned()
+            .unwrap_or_else(|| Region::new("us-east-1")),
+    }
+}
+
+/// Error codes aws answers throttled requests with, whichever the service
+const THROTTLING: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "RequestLimitExceeded",
+    "SlowDown",
+    "Throttling",
+    "ThrottlingException",
+    "TooManyRequestsException",
+    "TooManyUpdates",
+];
+
+/// Whether aws refused a request for being one too many
+pub fn is_throttling<E>(err: &SdkError<E>) -> bool
+where
+    E: ProvideErrorMetadata,
+{
+    err.code()
+        .map(|code| THROTTLING.contains(&code))
+        .unwrap_or(false)
+        || err
+            .raw_response()
+            .map(|response| response.status().as_u16() == 429)
+            .unwrap_or(false)
+}
+
+/// Throttled requests and requests which never reached aws are worth retrying
+pub fn is_retryable<E>(err: &SdkError<E>) -> bool
+where
+    E: ProvideErrorMetadata,
+{
+    match err {
+        SdkError::DispatchFailure(failure) => failure.is_io() || failure.is_timeout(),
+        SdkError::TimeoutError(_) => true,
+        SdkError::ServiceError(_) | SdkError::ResponseError(_) => is_throttling(err),
+        _ => false,
+    }
+}
+
+/// Credentials a profile signing in with IAM Identity Center failed to
+/// resolve, for want of a current SSO token
+#[derive(Debug)]
+pub struct SsoSignIn(CredentialsError);
+
+impl fmt::Display for SsoSignIn {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for SsoSignIn {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// Whether `err` came of a connection failing rather than of what was
+/// said over one
+fn disconnected(err: &(dyn StdError + 'static)) -> bool {
+    iter::successors(Some(err), |&err| err.source()).any(|err| {
+        err.downcast_ref::<result::ConnectorError>()
+            .is_some_and(|err| err.is_io() || err.is_timeout())
+    })
+}
+
+/// Credentials of the shared config and credentials files. When the
+/// selected profile signs in with IAM Identity Center, its failures to
+/// resolve any are marked as wanting a new SSO token
+#[derive(Debug)]
+struct Profile {
+    files: ProfileFileCredentialsProvider,
+    sso: bool,
+}
+
+impl ProvideCredentials for Profile {
+    fn provide_credentials<'a>(&'a self) -> provider::future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        provider::future::ProvideCredentials::new(async move {
+            self.files
+                .provide_credentials()
+                .await
+                .map_err(|err| match err {
+                    CredentialsError::CredentialsNotLoaded(_)
+                    | CredentialsError::ProviderTimedOut(_) => err,
+                    err if !self.sso || disconnected(&err) => err,
+                    err => CredentialsError::provider_error(SsoSignIn(err)),
+                })
+        })
+    }
+}
+
+/// Whether the selected profile signs in with IAM Identity Center
+async fn sso_profile() -> bool {
+    let (fs, env) = (os_shim_internal::Fs::real(), os_shim_internal::Env::real());
+    aws_config::profile::load(&fs, &env, &EnvConfigFiles::default(), None)
+        .await
+        .ok()
+        .and_then(|profiles| {
+            profiles
+                .get_profile(profiles.selected_profile())
+                .map(|profile| {
+                    profile.get("sso_start_url").is_some() || profile.get("sso_session").is_some()
+                })
+        })
+        .unwrap_or(false)
+}
+
+/// The command signing in to IAM Identity Center again, when a request
+/// failed for want of a current SSO token
+fn sso_login(err: &(dyn StdError + 'static)) -> Option<String> {
+    iter::successors(Some(err), |&err| err.source()).find(|err| err.is::<SsoSignIn>())?;
+    Some(match env::var("AWS_PROFILE") {
+        Ok(profile) => format!("aws sso login --profile {}", profile),
+        Err(_) => "aws sso login".into(),
+    })
+}
+
+/// A failed request as it is reported: what aws said of a service error,
+/// otherwise the failure along with what caused it, and how to sign in
+/// again when that was an expired or missing SSO token
+pub fn describe<E>(err: &SdkError<E>) -> String
+where
+    E: ProvideErrorMetadata + StdError + 'static,
+{
+    match err {
+        SdkError::ServiceError(failure) => match (failure.err().code(), failure.err().message()) {
+            (Some(code), Some(message)) => format!("{}: {}", code, message),
+            _ => failure.err().to_string(),
+        },
+        err => {
+            let described = DisplayErrorContext(err).to_string();
+            match sso_login(err) {
+                Some(login) => format!("{}, sign in again with `{}`", described, login),
+                None => described,
+            }
+        }
+    }
+}
+
+/// A failed aws request, reported as `describe` has it
+#[derive(Debug)]
+pub struct Failure<E>(pub SdkError<E>);
+
+impl<E> fmt::Display for Failure<E>
+where
+    E: ProvideErrorMetadata + StdError + 'static,
+{
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        f.write_str(&describe(&self.0))
+    }
+}
+
+impl<E> StdError for Failure<E>
+where
+    E: ProvideErrorMetadata + StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// A service error as aws would answer it with `status`, for stand ins of
+/// aws to fail with
+pub fn service_error<E>(
+    err: E,
+    status: u16,
+) -> SdkError<E> {
+    let status = StatusCode::try_from(status).expect("invalid http status");
+    SdkError::service_error(err, HttpResponse::new(status, SdkBody::empty()))
+}
+
+/// Full jitter exponential backoff: a random delay up to a ceiling which
+/// doubles with each attempt
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempt))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF);
+    let millis = ceiling.as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+}
+
+/// Issues a request, retrying with backoff while it fails in a retryable way.
+/// Throttling holds back the other requests of the partition it is sent in
+/// as well. Each attempt is logged when verbose
+pub async fn retry<F, R, T, E>(mut request: F) -> Result<T, SdkError<E>>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T, SdkError<E>>>,
+    E: ProvideErrorMetadata + StdError + 'static,
+{
+    let max_retries = MAX_RETRIES.load(Ordering::SeqCst) as u32;
+    let operation = trace::operation::<E>();
+    let limiter = PARTITION
+        .try_with(|partition| partition.limiter.clone())
+        .ok();
+    let mut attempt = 0;
+    loop {
+        if let Some(limiter) = &limiter {
+            limiter.ready().await;
+        }
+        let started = Instant::now();
+        let result = request().await;
+        let elapsed = started.elapsed().as_millis();
+        match &result {
+            Err(err) if attempt < max_retries && is_retryable(err) => {
+                let delay = backoff(attempt);
+                if let Some(limiter) = limiter.as_ref().filter(|_| is_throttling(err)) {
+                    limiter.hold(delay);
+                }
+                log::debug!(
+                    "{} failed in {}ms: {}, retry {} of {} in {}ms",
+                    operation,
+                    elapsed,
+                    describe(err),
+                    attempt + 1,
+                    max_retries,
+                    delay.as_millis()
+                );
+                time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            Ok(_) => log::debug!("{} took {}ms", operation, elapsed),
+            Err(err) => match trace::failed_request_id(err) {
+                Some(id) => log::debug!(
+                    "{} failed in {}ms: {}, request id {}",
+                    operation,
+                    elapsed,
+                    describe(err),
+                    id
+                ),
+                None => log::debug!("{} failed in {}ms: {}", operation, elapsed, describe(err)),
+            },
+        }
+        return result;
+    }
+}
+
+/// The chain credentials are resolved by, going down the one the AWS cli
+/// does: env vars, the shared config and credentials files, a web identity
+/// token, then container and instance metadata, the metadata endpoints
+/// each given `timeout` to answer
+async fn credentials(
+    http_client: SharedHttpClient,
+    timeout: Duration,
+) -> CredentialsProviderChain {
+    // web identity tokens are exchanged for credentials with sts, which
+    // needs a region even where none is configured, as on CI runners
+    let set = REGION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let sts_region = RegionProviderChain::first_try(set)
+        .or_default_provider()
+        .or_else(Region::new("us-east-1"))
+        .region()
+        .await;
+    let conf = ProviderConfig::default()
+        .with_http_client(http_client)
+        .with_region(sts_region);
+    let metadata = imds::Client::builder()
+        .configure(&conf)
+        .connect_timeout(timeout)
+        .read_timeout(timeout)
+        .operation_timeout(timeout)
+        .build();
+    CredentialsProviderChain::first_try(
+        "Environment",
+        EnvironmentVariableCredentialsProvider::new(),
+    )
+    .or_else(
+        "Profile",
+        Profile {
+            files: ProfileFileCredentialsProvider::builder()
+                .configure(&conf)
+                .build(),
+            sso: sso_profile().await,
+        },
+    )
+    .or_else(
+        "WebIdentityToken",
+        WebIdentityTokenCredentialsProvider::builder()
+            .configure(&conf)
+            .build(),
+    )
+    .or_else(
+        "EcsContainer",
+        EcsCredentialsProvider::builder()
+            .configure(&conf)
+            .connect_timeout(timeout)
+            .read_timeout(timeout)
+            .build(),
+    )
+    .or_else(
+        "Ec2InstanceMetadata",
+        ImdsCredentialsProvider::builder()
+            .configure(&conf)
+            .imds_client(metadata)
+            .build(),
+    )
+}
+
+/// Settings shared by every client, loaded once. Credentials are resolved
+/// once and refreshed as they expire, which keeps them warm for a daemon's
+/// lifetime, and given as long as `set_credential_timeout` allows to
+/// resolve. Every client sends requests through the same http client, so
+/// connections to a host are pooled and set up once however many clients
+/// talk to it. Retries are left to `retry`
+async fn shared() -> &'static SdkConfig {
+    static SHARED: OnceCell<SdkConfig> = OnceCell::const_new();
+    SHARED
+        .get_or_init(|| async {
+            let timeout = Duration::from_millis(CREDENTIAL_TIMEOUT.load(Ordering::SeqCst));
+            let http_client = HttpClientBuilder::new()
+                .tls_provider(tls::Provider::Rustls(
+                    tls::rustls_provider::CryptoMode::AwsLc,
+                ))
+                .build_https();
+            aws_config::defaults(BehaviorVersion::latest())
+                .http_client(http_client.clone())
+                .credentials_provider(credentials(http_client, timeout).await)
+                .identity_cache(IdentityCache::lazy().load_timeout(timeout).build())
+                .retry_config(RetryConfig::disabled())
+                .load()
+                .await
+        })
+        .await
+}
+
+/// Defines a constructor for the client of each service, talking to the
+/// region clients talk to and tracing every request sent. A client is made
+/// once per region and handed out again after that, so bulk commands reuse
+/// warm connections rather than paying for a new client with every call
+macro_rules! clients {
+    ($($name:ident => $service:ident;)+) => {
+        $(
+            pub async fn $name() -> $service::Client {
+                static CLIENTS: Mutex<BTreeMap<String, $service::Client>> =
+                    Mutex::new(BTreeMap::new());
+                let (shared, region) = (shared().await, region().await);
+                CLIENTS
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .entry(region.to_string())
+                    .or_insert_with(|| {
+                        let config = $service::config::Builder::from(shared)
+                            .region(region)
+                            .interceptor(trace::Traced)
+                            .build();
+                        $service::Client::from_conf(config)
+                    })
+                    .clone()
+            }
+        )+
+    };
+}
+
+clients! {
+    lambda_client => aws_sdk_lambda;
+    tagging_client => aws_sdk_resourcegroupstagging;
+    ssm_client => aws_sdk_ssm;
+    ecs_client => aws_sdk_ecs;
+    secrets_client => aws_sdk_secretsmanager;
+    s3_client => aws_sdk_s3;
+    dynamodb_client => aws_sdk_dynamodb;
+    kms_client => aws_sdk_kms;
+    sts_client => aws_sdk_sts;
+}
+
+/// Collects every item of a paginated api, following continuation tokens
+/// until a page arrives without one
+pub async fn paginate<F, R, T>(mut fetch: F) -> Result<Vec<T>, Error>
+where
+    F: FnMut(Option<String>) -> R,
+    R: Future<Output = Result<(Vec<T>, Option<String>), Error>>,
+{
+    let mut items = Vec::new();
+    let mut token = None;
+    loop {
+        let (page, next) = fetch(token).await?;
+        items.extend(page);
+        match next.filter(|token| !token.is_empty()) {
+            Some(next) => token = Some(next),
+            None => return Ok(items),
+        }
+    }
+}
+
+/// Every function in the account and region
+pub async fn list_functions(lambda: LambdaClient) -> Result<Vec<FunctionConfiguration>, Error> {
+    paginate(|marker| {
+        let request = lambda.list_functions().set_marker(marker).max_items(50);
+        async move {
+            let page = retry(|| request.clone().send()).await?;
+            Ok((page.functions.unwrap_or_default(), page.next_marker))
+        }
+    })
+    .await
+}
+
+/// Names of the functions in the account and region, a page at a time as
+/// each arrives
+pub fn function_names(lambda: LambdaClient) -> impl Stream<Item = Result<Vec<String>, Error>> {
+    // None once the last page has been fetched, otherwise the marker to fetch
+    // the next page with
+    stream::try_unfold(Some(None), move |marker: Option<Option<String>>| {
+        let lambda = lambda.clone();
+        async move {
+            let marker = match marker {
+                Some(marker) => marker,
+                None => return Ok(None),
+            };
+            let request = lambda.list_functions().set_marker(marker).max_items(50);
+            let page = retry(|| request.clone().send()).await?;
+            let names = page
+                .functions
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|function| function.function_name)
+                .collect::<Vec<_>>();
+            let next = page.next_marker.filter(|marker| !marker.is_empty());
+            Ok(Some((names, next.map(Some))))
+        }
+    })
+}
+
+/// Every version of a function, including $LATEST
+pub async fn list_versions(
+    lambda: LambdaClient,
+    function: String,
+) -> Result<Vec<FunctionConfiguration>, Error> {
+    paginate(|marker| {
+        let request = lambda
+            .list_versions_by_function()
+            .function_name(function.clone())
+            .set_marker(marker)
+            .max_items(50);
+        async move {
+            let page = retry(|| request.clone().send()).await?;
+            Ok((page.versions.unwrap_or_default(), page.next_marker))
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_lambda::operation::get_function_configuration::GetFunctionConfigurationError;
+    use aws_smithy_types::error::ErrorMetadata;
+    use std::future;
+
+    #[test]
+    fn backoff_stays_under_ceiling() {
+        assert!(backoff(0) <= BASE_BACKOFF);
+        assert!(backoff(3) <= BASE_BACKOFF * 8);
+        assert!(backoff(40) <= MAX_BACKOFF);
+    }
+
+    fn failure(code: &str) -> SdkError<GetFunctionConfigurationError> {
+        service_error(
+            GetFunctionConfigurationError::generic(
+                ErrorMetadata::builder().code(code).message("nope").build(),
+            ),
+            400,
+        )
+    }
+
+    #[test]
+    fn is_retryable_detects_throttling() {
+        assert!(is_retryable(&failure("TooManyRequestsException")));
+        assert!(!is_retryable(&failure("ResourceNotFoundException")));
+        assert!(is_retryable(&service_error(
+            GetFunctionConfigurationError::generic(ErrorMetadata::builder().build()),
+            429
+        )));
+        assert!(!is_retryable::<GetFunctionConfigurationError>(
+            &SdkError::construction_failure("bad")
+        ));
+    }
+
+    #[test]
+    fn describe_reports_what_aws_said() {
+        assert_eq!(
+            describe(&failure("ResourceNotFoundException")),
+            "ResourceNotFoundException: nope"
+        )
+    }
+
+    #[test]
+    fn describe_says_how_to_sign_in_again() {
+        let signed_out = |err: CredentialsError| {
+            SdkError::<GetFunctionConfigurationError>::dispatch_failure(
+                result::ConnectorError::other(Box::new(err), None),
+            )
+        };
+        let expired = signed_out(CredentialsError::provider_error(SsoSignIn(
+            CredentialsError::provider_error("token expired"),
+        )));
+        assert!(describe(&expired).contains("sign in again with `aws sso login"));
+        let reworded = signed_out(CredentialsError::provider_error("SSO token expired"));
+        assert!(!describe(&reworded).contains("aws sso login"));
+        assert!(!describe(&failure("ExpiredTokenException")).contains("aws sso login"))
+    }
+
+    #[test]
+    fn parse_region_refuses_malformed_names() {
+        assert_eq!(
+            parse_region("us-gov-west-1")
+                .ok()
+                .map(|region| region.to_string()),
+            Some("us-gov-west-1".into())
+        );
+        for name in &["mars", "eu-west", "EU-WEST-1", "eu--1"] {
+            match parse_region(name) {
+                Err(Error::InvalidRegion(invalid)) => assert_eq!(&invalid, name),
+                other => panic!("expected an invalid region error, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn paginate_follows_tokens_until_exhausted() {
+        let pages = paginate(|token: Option<String>| {
+            future::ready(Ok(match token.as_deref() {
+                None => (vec![1, 2], Some("a".to_string())),
+                Some("a") => (vec![3], Some("b".to_string())),
+                _ => (vec![4], Some(String::new())),
+            }))
+        })
+        .await;
+        assert_eq!(pages.ok(), Some(vec![1, 2, 3, 4]))
+    }
+
+    #[tokio::test]
+    async fn limiter_holds_back_for_the_longest_hold() {
+        let limiter = Limiter::default();
+        limiter.hold(Duration::from_millis(50));
+        limiter.hold(Duration::from_millis(10));
+        let started = Instant::now();
+        limiter.ready().await;
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        let started = Instant::now();
+        limiter.ready().await;
+        assert!(started.elapsed() < Duration::from_millis(40))
+    }
+
+    #[tokio::test]
+    async fn in_partition_shares_a_limiter_per_account_and_region() {
+        let scoped = |account: &str, region: &str| {
+            in_partition(Some(account.into()), Some(region.into()), async {
+                PARTITION.with(|partition| partition.limiter.clone())
+            })
+        };
+        let limiter = scoped("123456789012", "eu-west-1").await;
+        assert!(Arc::ptr_eq(
+            &limiter,
+            &scoped("123456789012", "eu-west-1").await
+        ));
+        assert!(!Arc::ptr_eq(
+            &limiter,
+            &scoped("123456789012", "us-east-2").await
+        ));
+        assert!(!Arc::ptr_eq(
+            &limiter,
+            &scoped("210987654321", "eu-west-1").await
+        ));
+        let region = in_partition(None, Some("eu-west-1".into()), region()).await;
+        assert_eq!(region.as_ref(), "eu-west-1")
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{aws, error::Error, gc::Retention, lev_dir, source, Env};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Format of snapshot file names, which sort by the time they were taken
+const TIMESTAMP: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// Bucket snapshots are kept in when they are shared rather than local
+static BUCKET: Mutex<Option<String>> = Mutex::new(None);
+
+/// Keeps snapshots under `<function>/<timestamp>.json` keys of an s3 bucket
+/// rather than in `~/.lev/backups`
+pub fn set_bucket(bucket: Option<String>) {
+    *BUCKET
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = bucket
+}
+
+/// Whether changes go ahead even when the env they change can't be
+/// snapshotted
+static UNSNAPSHOTTED: AtomicBool = AtomicBool::new(false);
+
+/// Has changes go ahead even when the env they change can't be snapshotted,
+/// leaving nothing to roll back to, rather than being refused
+pub fn set_allow_unsnapshotted(allow: bool) {
+    UNSNAPSHOTTED.store(allow, Ordering::SeqCst)
+}
+
+/// Whether changes go ahead when the env they change can't be snapshotted
+pub fn allows_unsnapshotted() -> bool {
+    UNSNAPSHOTTED.load(Ordering::SeqCst)
+}
+
+fn bucket() -> Option<String> {
+    BUCKET
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+fn dir() -> PathBuf {
+    lev_dir().join("backups")
+}
+
+fn file_name(timestamp: DateTime<Utc>) -> String {
+    format!("{}.json", timestamp.format(TIMESTAMP))
+}
+
+/// Time a snapshot was taken, if the path names one
+fn taken(path: &Path) -> Option<DateTime<Utc>> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    NaiveDateTime::parse_from_str(stem, TIMESTAMP)
+        .ok()
+        .map(|naive| DateTime::from_utc(naive, Utc))
+}
+
+/// Writes a snapshot only its owner may read, as it holds secrets in the
+/// clear, in directories only its owner may list
+fn write_private(
+    path: &Path,
+    contents: &[u8],
+) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        // directories made before snapshots were kept private are made so
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)?;
+    Ok(())
+}
+
+/// Writes a function's env to `~/.lev/backups/<function>/<timestamp>.json`,
+/// or the bucket when there is one, ahead of changing it. Yields where the
+/// snapshot was written
+pub async fn snapshot(
+    function: &str,
+    env: &Env,
+) -> Result<String, Error> {
+    let name = format!("{}/{}", function, file_name(Utc::now()));
+    let sorted = env.iter().collect::<BTreeMap<_, _>>();
+    let contents = serde_json::to_vec_pretty(&sorted)?;
+    match bucket() {
+        None => {
+            let path = dir().join(name);
+            write_private(&path, &contents)?;
+            fs::set_permissions(dir(), fs::Permissions::from_mode(0o700))?;
+            Ok(path.display().to_string())
+        }
+        Some(bucket) => {
+            let destination = format!("s3://{}/{}", bucket, name);
+            source::write(&destination, contents).await?;
+            Ok(destination)
+        }
+    }
+}
+
+/// Snapshots in a local directory, in no particular order
+fn snapshots(dir: &Path) -> Result<Vec<(DateTime<Utc>, PathBuf)>, Error> {
+    Ok(fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| taken(&path).map(|timestamp| (timestamp, path)))
+        .collect())
+}
+
+/// A function's snapshots, newest first, along with where each is kept: a
+/// local path, or a key of the bucket
+async fn listing(function: &str) -> Result<Vec<(DateTime<Utc>, String)>, Error> {
+    let mut snapshots = match bucket() {
+        None => match snapshots(&dir().join(function)) {
+            Err(Error::Io(ref err)) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            other => other,
+        }?
+        .into_iter()
+        .map(|(timestamp, path)| (timestamp, path.display().to_string()))
+        .collect::<Vec<_>>(),
+        Some(bucket) => {
+            let s3 = aws::s3_client().await;
+            let prefix = format!("{}/", function);
+            aws::paginate(|continuation_token| {
+                let request = s3
+                    .list_objects_v2()
+                    .bucket(bucket.clone())
+                    .prefix(prefix.clone())
+                    .set_continuation_token(continuation_token);
+                async move {
+                    let page = aws::retry(|| request.clone().send()).await?;
+                    let snapshots = page
+                        .contents
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|object| object.key)
+                        .filter_map(|key| taken(Path::new(&key)).map(|timestamp| (timestamp, key)))
+                        .collect::<Vec<_>>();
+                    Ok((snapshots, page.next_continuation_token))
+                }
+            })
+            .await?
+        }
+    };
+    snapshots.sort_by_key(|(timestamp, _)| Reverse(*timestamp));
+    Ok(snapshots)
+}
+
+/// Times a function's snapshots were taken, newest first
+pub async fn list(function: &str) -> Result<Vec<DateTime<Utc>>, Error> {
+    Ok(listing(function)
+        .await?
+        .into_iter()
+        .map(|(timestamp, _)| timestamp)
+        .collect())
+}
+
+/// The env a snapshot listed by `listing` holds
+async fn read(location: String) -> Result<Env, Error> {
+    let source = match bucket() {
+        None => location,
+        Some(bucket) => format!("s3://{}/{}", bucket, location),
+    };
+    Ok(serde_json::from_slice(&source::read(&source).await?)?)
+}
+
+/// The newest snapshot, or the newest taken no later than `to`
+fn latest<L>(
+    snapshots: Vec<(DateTime<Utc>, L)>,
+    to: Option<DateTime<Utc>>,
+) -> Option<(DateTime<Utc>, L)> {
+    snapshots
+        .into_iter()
+        .filter(|(timestamp, _)| to.map(|to| *timestamp <= to).unwrap_or(true))
+        .max_by_key(|(timestamp, _)| *timestamp)
+}
+
+/// The env of a function's newest snapshot, or of the newest taken no later
+/// than `to`, along with the time it was taken
+pub async fn restore_point(
+    function: String,
+    to: Option<DateTime<Utc>>,
+) -> Result<(DateTime<Utc>, Env), Error> {
+    let (timestamp, location) =
+        latest(listing(&function).await?, to).ok_or(Error::NoSnapshot(function))?;
+    Ok((timestamp, read(location).await?))
+}
+
+/// Snapshots of a single function a retention policy doesn't keep
+fn expired(
+    mut snapshots: Vec<(DateTime<Utc>, PathBuf)>,
+    retention: &Retention,
+) -> Vec<PathBuf> {
+    snapshots.sort_by_key(|(timestamp, _)| Reverse(*timestamp));
+    snapshots
+        .into_iter()
+        .enumerate()
+        .filter(|(rank, (timestamp, _))| !retention.retains(*timestamp, *rank))
+        .map(|(_, (_, path))| path)
+        .collect()
+}
+
+/// Removes local snapshots a retention policy doesn't keep, yielding
+/// `(removed, total)` snapshot counts
+pub fn prune(
+    retention: &Retention,
+    dry_run: bool,
+) -> Result<(usize, usize), Error> {
+    let functions = match fs::read_dir(dir()) {
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok((0, 0)),
+        other => other?,
+    };
+    let (mut removed, mut total) = (0, 0);
+    for function in functions {
+        let function = function?.path();
+        if !function.is_dir() {
+            continue;
+        }
+        let snapshots = snapshots(&function)?;
+        total += snapshots.len();
+        for path in expired(snapshots, retention) {
+            removed += 1;
+            if !dry_run {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok((removed, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_private_keeps_snapshots_to_their_owner() {
+        let dir = std::env::temp_dir().join(format!("lev-test-backups-{}", std::process::id()));
+        let path = dir.join("foo").join(file_name(Utc::now()));
+        write_private(&path, b"{}").unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        fs::remove_dir_all(dir).unwrap()
+    }
+
+    #[test]
+    fn taken_parses_file_names() {
+        let timestamp = Utc::now();
+        assert_eq!(
+            taken(Path::new(&file_name(timestamp))).map(|t| t.timestamp_nanos() / 1000),
+            Some(timestamp.timestamp_nanos() / 1000)
+        );
+        assert_eq!(taken(Path::new("notes.txt")), None)
+    }
+
+    #[test]
+    fn latest_honors_cutoff() {
+        let now = Utc::now();
+        let snapshots = vec![
+            (now - chrono::Duration::hours(3), PathBuf::from("a")),
+            (now - chrono::Duration::hours(1), PathBuf::from("b")),
+            (now - chrono::Duration::hours(2), PathBuf::from("c")),
+        ];
+        assert_eq!(
+            latest(snapshots.clone(), None).map(|(_, path)| path),
+            Some(PathBuf::from("b"))
+        );
+        assert_eq!(
+            latest(snapshots.clone(), Some(now - chrono::Duration::minutes(90)))
+                .map(|(_, path)| path),
+            Some(PathBuf::from("c"))
+        );
+        assert_eq!(
+            latest(snapshots, Some(now - chrono::Duration::hours(4))),
+            None
+        )
+    }
+
+    #[test]
+    fn expired_keeps_newest() {
+        let snapshot = |age: i64, name: &str| {
+            (
+                Utc::now() - chrono::Duration::hours(age),
+                PathBuf::from(name),
+            )
+        };
+        assert_eq!(
+            expired(
+                vec![snapshot(3, "a"), snapshot(1, "b"), snapshot(2, "c")],
+                &Retention {
+                    keep: Some(2),
+                    ..Retention::default()
+                },
+            ),
+            vec![PathBuf::from("a")]
+        )
+    }
+}
@@ -0,0 +1,328 @@
+use crate::error::Error;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Name of the file lev reads settings from
+pub const FILE: &str = ".lev.toml";
+
+/// Stage function names are filled into the naming template for
+static STAGE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Has function names name the function of a stage, by way of the template
+/// of the `[naming]` table
+pub fn set_stage(stage: Option<String>) {
+    *STAGE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = stage
+}
+
+/// Stage `--stage` names, if any
+pub fn stage() -> Option<String> {
+    STAGE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Settings read from a `.lev.toml`
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Names standing in for functions, like `prod-api` for the arn of the
+    /// function it names
+    pub aliases: HashMap<String, String>,
+    pub defaults: DefaultsConfig,
+    pub gc: GcConfig,
+    /// Functions `--group` selects together, by the name of their group
+    pub groups: HashMap<String, Vec<String>>,
+    pub naming: NamingConfig,
+    pub notify: NotifyConfig,
+    pub output: OutputConfig,
+    pub redact: RedactConfig,
+    pub safety: SafetyConfig,
+}
+
+/// What commands fall back on when not told otherwise
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultsConfig {
+    /// Function commands which need one are run against without `-f`
+    pub function: Option<String>,
+    /// Region to talk to rather than that of the AWS config
+    pub region: Option<String>,
+    /// Profile of the AWS config to authenticate with, unless `AWS_PROFILE`
+    /// names another
+    pub profile: Option<String>,
+}
+
+/// How much of each kind of local state `gc` keeps, unless told otherwise
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GcConfig {
+    pub journal: RetentionConfig,
+    pub backups: RetentionConfig,
+}
+
+/// How much of a kind of local state `gc` keeps
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Age past which items are removed, like `90d`, `2w` or `12h`
+    pub max_age: Option<String>,
+    /// Newest items kept per function
+    pub keep: Option<usize>,
+}
+
+/// How the functions of each stage are named
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConfig {
+    /// Name of a function of a stage, like `myapp-{stage}-{name}`, which
+    /// `--stage` fills in
+    pub template: Option<String>,
+}
+
+impl NamingConfig {
+    /// The name of a function of a stage
+    fn name(
+        &self,
+        stage: &str,
+        name: &str,
+    ) -> Result<String, Error> {
+        match &self.template {
+            Some(template) if template.contains("{name}") => {
+                Ok(template.replace("{stage}", stage).replace("{name}", name))
+            }
+            Some(template) => Err(Error::Config(
+                FILE.into(),
+                format!("naming template {} has no {{name}} to fill in", template),
+            )),
+            None => Err(Error::NoNamingTemplate(stage.into())),
+        }
+    }
+
+    /// A glob matching the name of every function of a stage, when there is
+    /// a template to name them by
+    pub fn pattern(
+        &self,
+        stage: &str,
+    ) -> Option<String> {
+        self.template
+            .as_ref()
+            .filter(|template| template.contains("{name}"))
+            .map(|template| template.replace("{stage}", stage).replace("{name}", "*"))
+    }
+}
+
+/// Where `watch --notify` posts the changes it sees
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Url of an incoming webhook accepting `{"text": ...}` payloads, kept
+    /// here rather than on command lines as it is a credential of its own
+    pub webhook: Option<String>,
+}
+
+/// Settings of how envs are printed
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Format `get` prints in without `--output`
+    pub format: Option<String>,
+    /// Prints values as they are rather than masked, as lev once did
+    pub show_values: bool,
+}
+
+/// Settings guarding against unintended changes
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    /// Waits for lambda to finish applying every update, as `--wait` does
+    pub wait: bool,
+    /// Refuses every command which would change an env
+    pub read_only: bool,
+    /// Keys `unset` and `sync --prune` refuse to remove without `--force`
+    pub protected: Vec<String>,
+    /// Path or url of a policy file every change to an env must satisfy,
+    /// which is also what `validate` checks against
+    pub policy: Option<String>,
+}
+
+/// Settings of which values are never printed as they are
+#[derive(Deserialize, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Patterns of keys whose values are masked, like `.*TOKEN|.*SECRET`
+    pub keys: Vec<String>,
+}
+
+impl Config {
+    /// The function a name stands in for: that of an alias, that of the
+    /// stage given with `--stage`, or else the name itself. Arns name
+    /// functions of every stage as they are
+    pub fn function(
+        &self,
+        name: &str,
+    ) -> Result<String, Error> {
+        if let Some(function) = self.aliases.get(name) {
+            return Ok(function.clone());
+        }
+        match stage() {
+            Some(stage) if !name.contains(':') => self.naming.name(&stage, name),
+            _ => Ok(name.to_string()),
+        }
+    }
+
+    /// The functions of every group, with the names among them resolved
+    pub fn groups(&self) -> Result<HashMap<String, Vec<String>>, Error> {
+        self.groups
+            .iter()
+            .map(|(group, functions)| {
+                let functions = functions
+                    .iter()
+                    .map(|function| self.function(function))
+                    .collect::<Result<_, _>>()?;
+                Ok((group.clone(), functions))
+            })
+            .collect()
+    }
+
+    pub fn parse(
+        path: &Path,
+        contents: &str,
+    ) -> Result<Self, Error> {
+        toml::from_str(contents)
+            .map_err(|err| Error::Config(path.display().to_string(), err.message().into()))
+    }
+}
+
+/// Where settings are looked for, in order: the current directory, then
+/// `~/.config/lev`
+fn locations() -> Vec<PathBuf> {
+    let mut locations = vec![PathBuf::from(FILE)];
+    if let Some(home) = dirs::home_dir() {
+        locations.push(home.join(".config").join("lev").join(FILE));
+    }
+    locations
+}
+
+/// Reads the first `.lev.toml` found, or the defaults when there is none
+pub fn load() -> Result<Config, Error> {
+    for path in locations() {
+        match fs::read_to_string(&path) {
+            Err(ref err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+            Ok(contents) => return Config::parse(&path, &contents),
+        }
+    }
+    Ok(Config::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_defaults_missing_settings() {
+        assert_eq!(
+            Config::parse(Path::new(FILE), "").ok(),
+            Some(Config::default())
+        );
+        assert_eq!(
+            Config::parse(Path::new(FILE), "[output]\nshow_values = true\n")
+                .map(|config| config.output.show_values)
+                .ok(),
+            Some(true)
+        );
+        assert_eq!(
+            Config::parse(Path::new(FILE), "[redact]\nkeys = [\".*TOKEN\"]\n")
+                .map(|config| config.redact.keys)
+                .ok(),
+            Some(vec![".*TOKEN".to_string()])
+        )
+    }
+
+    #[test]
+    fn parse_reads_defaults_and_safety() {
+        let config = Config::parse(
+            Path::new(FILE),
+            "[defaults]\nfunction = \"orders-prod-handler\"\nregion = \"eu-west-1\"\n\
+             [output]\nformat = \"json\"\n\
+             [safety]\nread_only = true\nprotected = [\"DATABASE_URL\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.defaults.function.as_deref(),
+            Some("orders-prod-handler")
+        );
+        assert_eq!(config.defaults.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(config.defaults.profile, None);
+        assert_eq!(config.output.format.as_deref(), Some("json"));
+        assert!(config.safety.read_only);
+        assert_eq!(config.safety.protected, vec!["DATABASE_URL".to_string()]);
+        assert!(!config.safety.wait)
+    }
+
+    #[test]
+    fn function_and_groups_resolve_aliases() {
+        let config = Config::parse(
+            Path::new(FILE),
+            "[aliases]\nprod-api = \"arn:aws:lambda:us-east-1:123456789012:function:api-prod\"\n\
+             [groups]\nbackend = [\"prod-api\", \"worker\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.function("prod-api").ok(),
+            Some("arn:aws:lambda:us-east-1:123456789012:function:api-prod".into())
+        );
+        assert_eq!(config.function("api-dev").ok(), Some("api-dev".into()));
+        assert_eq!(
+            config
+                .groups()
+                .ok()
+                .and_then(|groups| groups.get("backend").cloned()),
+            Some(vec![
+                "arn:aws:lambda:us-east-1:123456789012:function:api-prod".to_string(),
+                "worker".to_string()
+            ])
+        )
+    }
+
+    #[test]
+    fn naming_fills_in_stage_and_name() {
+        let naming = NamingConfig {
+            template: Some("myapp-{stage}-{name}".into()),
+        };
+        assert_eq!(
+            naming.name("prod", "api").ok(),
+            Some("myapp-prod-api".into())
+        );
+        assert_eq!(naming.pattern("prod"), Some("myapp-prod-*".into()));
+        assert_eq!(NamingConfig::default().pattern("prod"), None);
+        match NamingConfig::default().name("prod", "api") {
+            Err(Error::NoNamingTemplate(stage)) => assert_eq!(stage, "prod"),
+            other => panic!("expected a missing template error, got {:?}", other),
+        }
+        let naming = NamingConfig {
+            template: Some("myapp-{stage}".into()),
+        };
+        match naming.name("prod", "api") {
+            Err(Error::Config(path, _)) => assert_eq!(path, FILE),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_unknown_settings() {
+        match Config::parse(Path::new(FILE), "[output]\nshow_value = true\n") {
+            Err(Error::Config(path, _)) => assert_eq!(path, FILE),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+}
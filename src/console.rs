@@ -0,0 +1,187 @@
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    sync::{Mutex, MutexGuard},
+};
+
+/// What commands printed while being captured
+#[derive(Debug, Default, PartialEq)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Set while capturing, in place of the process's stdout and stderr
+static CAPTURE: Mutex<Option<Captured>> = Mutex::new(None);
+
+/// Whether the daemon client whose command is being captured prints to a
+/// terminal
+static CLIENT_TERMINAL: Mutex<bool> = Mutex::new(false);
+
+/// Set when colors are turned off, with `--no-color` or `NO_COLOR`
+static NO_COLOR: Mutex<bool> = Mutex::new(false);
+
+/// Set when only errors are to be printed after changing envs
+static QUIET: Mutex<bool> = Mutex::new(false);
+
+/// Set when failures are reported as JSON objects rather than text
+static JSON_ERRORS: Mutex<bool> = Mutex::new(false);
+
+/// Colors changes are painted in
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Color {
+    Green,
+    Red,
+    Yellow,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        match self {
+            Color::Green => 32,
+            Color::Red => 31,
+            Color::Yellow => 33,
+        }
+    }
+}
+
+fn capturing() -> MutexGuard<'static, Option<Captured>> {
+    CAPTURE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn set_client_terminal(terminal: bool) {
+    *CLIENT_TERMINAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = terminal
+}
+
+/// Whether stdout ends up on a terminal, that of the daemon client when
+/// capturing
+pub fn terminal() -> bool {
+    if capturing().is_some() {
+        return *CLIENT_TERMINAL
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+    io::stdout().is_terminal()
+}
+
+/// Whether `NO_COLOR` asks for colors to be turned off, as it does whenever
+/// it is set to anything but an empty string
+pub fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR")
+        .map(|value| !value.is_empty())
+        .unwrap_or(false)
+}
+
+pub fn set_no_color(no_color: bool) {
+    *NO_COLOR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = no_color
+}
+
+pub fn set_quiet(quiet: bool) {
+    *QUIET
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = quiet
+}
+
+/// Whether commands which change envs print nothing but errors
+pub fn quiet() -> bool {
+    *QUIET
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn set_json_errors(json: bool) {
+    *JSON_ERRORS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = json
+}
+
+/// Whether failures are reported as JSON objects
+pub fn json_errors() -> bool {
+    *JSON_ERRORS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether output is colored: only on a terminal, unless turned off
+pub fn colored() -> bool {
+    !*NO_COLOR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        && terminal()
+}
+
+/// `text` in a color, when output is colored
+pub fn paint(
+    color: Color,
+    text: &str,
+) -> String {
+    if colored() {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Writes to stdout, or the capture if there is one
+pub fn out(args: fmt::Arguments) {
+    match capturing().as_mut() {
+        Some(captured) => captured.stdout.push_str(&args.to_string()),
+        None => print!("{}", args),
+    }
+}
+
+/// Writes to stderr, or the capture if there is one
+pub fn err(args: fmt::Arguments) {
+    match capturing().as_mut() {
+        Some(captured) => captured.stderr.push_str(&args.to_string()),
+        None => eprint!("{}", args),
+    }
+}
+
+/// Runs `f`, capturing everything printed through this module meanwhile
+pub fn capture<F, T>(f: F) -> (T, Captured)
+where
+    F: FnOnce() -> T,
+{
+    *capturing() = Some(Captured::default());
+    let result = f();
+    let captured = capturing().take().unwrap_or_default();
+    (result, captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_collects_output() {
+        let (result, captured) = capture(|| {
+            out(format_args!("a={}\n", 1));
+            err(format_args!("oops\n"));
+            42
+        });
+        assert_eq!(result, 42);
+        assert_eq!(
+            captured,
+            Captured {
+                stdout: "a=1\n".into(),
+                stderr: "oops\n".into(),
+            }
+        )
+    }
+
+    #[test]
+    fn paint_leaves_text_alone_off_terminals() {
+        let (painted, _) = capture(|| {
+            set_client_terminal(false);
+            paint(Color::Green, "+ A=1")
+        });
+        assert_eq!(painted, "+ A=1")
+    }
+}
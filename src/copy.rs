@@ -0,0 +1,145 @@
+use crate::{
+    aws::LambdaClient, changed, configuration, diff::Change, env, error::Error, keys, update, Env,
+};
+use futures::future;
+use glob::Pattern;
+
+/// Vars whose names match any of `keys`, or all vars when there are none,
+/// leaving out those matching any of `exclude`
+fn select(
+    env: Env,
+    keys: &[Pattern],
+    exclude: &[Pattern],
+) -> Vec<(String, String)> {
+    let mut selected = env
+        .into_iter()
+        .filter(|(k, _)| keys.is_empty() || keys.iter().any(|p| p.matches(k)))
+        .filter(|(k, _)| !exclude.iter().any(|p| p.matches(k)))
+        .collect::<Vec<_>>();
+    selected.sort();
+    selected
+}
+
+/// The vars of a function to copy onto others
+pub async fn vars(
+    lambda: LambdaClient,
+    function: String,
+    keys: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+) -> Result<Vec<(String, String)>, Error> {
+    let env = crate::get(lambda, function).await?;
+    Ok(select(env, &keys, &exclude))
+}
+
+/// The envs of `to` and `from` once `keys` have moved between them, or the
+/// keys `from` does not define
+fn split(
+    from: &Env,
+    to: &Env,
+    keys: &[String],
+) -> Result<(Env, Env), Vec<String>> {
+    let missing = keys
+        .iter()
+        .filter(|k| !from.contains_key(*k))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    let moved = to
+        .clone()
+        .into_iter()
+        .chain(keys.iter().map(|k| (k.clone(), from[k].clone())))
+        .collect();
+    let remaining = from
+        .iter()
+        .filter(|(k, _)| !keys.contains(k))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    Ok((moved, remaining))
+}
+
+/// Moves vars from one function to another, setting them on `to` before
+/// removing them from `from`. Should removing them fail, `to` is restored
+/// so the vars are never left defined twice. Protected vars only move when
+/// `force` is set. Yields the changes made to each
+pub async fn transfer(
+    lambda: LambdaClient,
+    from: String,
+    to: String,
+    keys: Vec<String>,
+    force: bool,
+) -> Result<Vec<(String, Vec<Change>)>, Error> {
+    let (source, target) = future::try_join(
+        configuration(lambda.clone(), from.clone()),
+        configuration(lambda.clone(), to.clone()),
+    )
+    .await?;
+    // names, ARNs and aliases of the same function all resolve to one ARN
+    if source.function_arn.is_some() && source.function_arn == target.function_arn {
+        return Err(Error::SameFunction(from));
+    }
+    let (source, target) = (env(source), env(target));
+    let (moved, remaining) = split(&source, &target, &keys)
+        .map_err(|missing| Error::MissingVars(from.clone(), missing.join(", ")))?;
+    // checked before `to` is changed, rather than undoing that when `from`
+    // can't be
+    keys::check_protected(&from, &source, &remaining, force)?;
+    let added = changed(
+        lambda.clone(),
+        to.clone(),
+        target.clone(),
+        moved.clone(),
+        false,
+    )
+    .await?;
+    match changed(lambda.clone(), from.clone(), source, remaining, force).await {
+        Ok(removed) => Ok(vec![(from, removed), (to, added)]),
+        Err(err) => {
+            if let Err(restore) = update(lambda, to.clone(), &moved, target, true).await {
+                errln!("failed to restore env of {}: {}", to, restore);
+            }
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Env {
+        ["DB_HOST", "DB_SECRET", "LOG_LEVEL"]
+            .iter()
+            .map(|k| (k.to_string(), "x".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn split_moves_keys() {
+        let to = Env::new();
+        let (moved, remaining) = split(&env(), &to, &["DB_HOST".into()]).unwrap();
+        assert_eq!(moved.keys().collect::<Vec<_>>(), vec!["DB_HOST"]);
+        assert!(!remaining.contains_key("DB_HOST"));
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(
+            split(&env(), &to, &["DB_HOST".into(), "NOPE".into()]),
+            Err(vec!["NOPE".to_string()])
+        )
+    }
+
+    #[test]
+    fn select_copies_everything_by_default() {
+        assert_eq!(select(env(), &[], &[]).len(), 3)
+    }
+
+    #[test]
+    fn select_applies_keys_and_exclusions() {
+        let keys = vec![Pattern::new("DB_*").unwrap()];
+        let exclude = vec![Pattern::new("*_SECRET").unwrap()];
+        assert_eq!(
+            select(env(), &keys, &exclude),
+            vec![("DB_HOST".to_string(), "x".to_string())]
+        )
+    }
+}
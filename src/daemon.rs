@@ -0,0 +1,241 @@
+use crate::{error::Error, lev_dir, whoami};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+/// A command line for the daemon to run on behalf of a client
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Request {
+    /// Arguments following the program name
+    pub args: Vec<String>,
+    /// Directory relative paths in the arguments are relative to
+    pub cwd: PathBuf,
+    /// Whether the client prints what the command does to a terminal
+    #[serde(default)]
+    pub terminal: bool,
+    /// Profile the client's `AWS_PROFILE` names
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Region the client's `AWS_REGION` names
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// What running a command line printed, and the status it exited with
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Response {
+    pub stdout: String,
+    pub stderr: String,
+    pub code: i32,
+}
+
+/// Socket the daemon listens on unless told otherwise
+pub fn socket() -> PathBuf {
+    lev_dir().join("daemon.sock")
+}
+
+fn write_line<T>(
+    mut stream: &UnixStream,
+    message: &T,
+) -> Result<(), Error>
+where
+    T: Serialize,
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+fn respond<F>(
+    stream: &UnixStream,
+    run: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(Request) -> Response,
+{
+    let mut line = String::new();
+    if BufReader::new(stream).read_line(&mut line)? == 0 {
+        // connections probing for a running daemon send nothing
+        return Ok(());
+    }
+    write_line(stream, &run(serde_json::from_str(&line)?))
+}
+
+/// Listens on `socket`, answering each client's request with what `run`
+/// yields for it. Requests are served one at a time, as commands share the
+/// capture of what they print
+pub fn serve<F>(
+    socket: &Path,
+    mut run: F,
+) -> Result<(), Error>
+where
+    F: FnMut(Request) -> Response,
+{
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(Error::DaemonRunning(socket.display().to_string()));
+        }
+        // left behind by a daemon which did not shut down cleanly
+        fs::remove_file(socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    for stream in listener.incoming() {
+        let served = stream
+            .map_err(Error::from)
+            .and_then(|stream| respond(&stream, &mut run));
+        if let Err(err) = served {
+            errln!("failed to serve client: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// A var of lev's own env, unless it is unset or empty
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Refuses a client using another profile than the daemon's, as the daemon's
+/// credentials are those of the profile it was started in. A client uses the
+/// profile its `AWS_PROFILE` names, else the one its .lev.toml does
+fn check_profile(
+    requested: Option<&str>,
+    configured: Option<&str>,
+    own: &str,
+) -> Result<(), Error> {
+    let profile = requested.or(configured).unwrap_or("default");
+    if profile != own {
+        return Err(Error::DaemonProfile(profile.into(), own.into()));
+    }
+    Ok(())
+}
+
+/// Refuses a client whose `AWS_REGION` differs from the daemon's, as the
+/// daemon's clients talk to the region it was started in
+fn check_region(
+    requested: Option<&str>,
+    own: Option<&str>,
+) -> Result<(), Error> {
+    if requested != own {
+        let unset = |region: Option<&str>| region.unwrap_or("unset").to_string();
+        return Err(Error::DaemonRegion(unset(requested), unset(own)));
+    }
+    Ok(())
+}
+
+/// Refuses a client using another profile or region than the ones this daemon
+/// resolves credentials and clients from
+pub fn serves_profile(
+    request: &Request,
+    configured: Option<&str>,
+) -> Result<(), Error> {
+    check_profile(request.profile.as_deref(), configured, &whoami::profile())?;
+    check_region(request.region.as_deref(), var("AWS_REGION").as_deref())
+}
+
+/// Has the daemon listening on `socket` run a command line
+pub fn forward(
+    socket: &Path,
+    args: Vec<String>,
+) -> Result<Response, Error> {
+    let stream = UnixStream::connect(socket)
+        .map_err(|_| Error::DaemonUnavailable(socket.display().to_string()))?;
+    let request = Request {
+        args,
+        cwd: env::current_dir()?,
+        terminal: io::stdout().is_terminal(),
+        profile: var("AWS_PROFILE"),
+        region: var("AWS_REGION"),
+    };
+    write_line(&stream, &request)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{process, thread, time::Duration};
+
+    #[test]
+    fn check_profile_refuses_other_profiles() {
+        assert!(check_profile(None, None, "default").is_ok());
+        assert!(check_profile(None, Some("prod"), "prod").is_ok());
+        assert!(check_profile(Some("prod"), Some("dev"), "prod").is_ok());
+        assert_eq!(
+            check_profile(None, Some("prod"), "default")
+                .unwrap_err()
+                .to_string(),
+            "the client uses profile prod, but the daemon reads credentials from default, \
+             start a daemon with AWS_PROFILE=prod or run without --use-daemon"
+        );
+        assert!(matches!(
+            check_profile(Some("dev"), Some("prod"), "prod"),
+            Err(Error::DaemonProfile(..))
+        ));
+        assert!(matches!(
+            check_profile(Some("dev"), None, "default"),
+            Err(Error::DaemonProfile(..))
+        ))
+    }
+
+    #[test]
+    fn check_region_refuses_other_regions() {
+        assert!(check_region(None, None).is_ok());
+        assert!(check_region(Some("eu-west-1"), Some("eu-west-1")).is_ok());
+        assert_eq!(
+            check_region(Some("eu-west-1"), None)
+                .unwrap_err()
+                .to_string(),
+            "the client's AWS_REGION is eu-west-1, but the daemon's is unset, \
+             start a daemon with the same AWS_REGION or run without --use-daemon"
+        );
+        assert!(matches!(
+            check_region(None, Some("us-east-1")),
+            Err(Error::DaemonRegion(..))
+        ))
+    }
+
+    #[test]
+    fn forward_round_trips_through_daemon() {
+        let socket = env::temp_dir().join(format!("lev-daemon-test-{}.sock", process::id()));
+        let listening = socket.clone();
+        thread::spawn(move || {
+            serve(&listening, |request| Response {
+                stdout: request.args.join(" "),
+                stderr: String::new(),
+                code: 3,
+            })
+        });
+        let mut response = forward(&socket, vec!["get".into(), "-f".into(), "foo".into()]);
+        for _ in 0..50 {
+            if response.is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+            response = forward(&socket, vec!["get".into(), "-f".into(), "foo".into()]);
+        }
+        assert_eq!(
+            response.ok(),
+            Some(Response {
+                stdout: "get -f foo".into(),
+                stderr: String::new(),
+                code: 3,
+            })
+        );
+        let _ = fs::remove_file(socket);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{console::Color, redact, Env};
+use std::fmt;
+
+/// A single key-level difference between two environments
+#[derive(Debug, PartialEq, Clone)]
+pub enum Change {
+    Added(String, String),
+    Removed(String, String),
+    Changed(String, String, String),
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added(k, _) | Change::Removed(k, _) | Change::Changed(k, _, _) => k,
+        }
+    }
+
+    /// Color the change is painted in: green for additions, red for removals
+    pub fn color(&self) -> Color {
+        match self {
+            Change::Added(..) => Color::Green,
+            Change::Removed(..) => Color::Red,
+            Change::Changed(..) => Color::Yellow,
+        }
+    }
+
+    /// The change without its values, safe to share outside of lev
+    pub fn summary(&self) -> String {
+        match self {
+            Change::Added(k, _) => format!("+ {}", k),
+            Change::Removed(k, _) => format!("- {}", k),
+            Change::Changed(k, _, _) => format!("~ {}", k),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match self {
+            Change::Added(k, v) => write!(f, "+ {}={}", k, redact::value(k, v)),
+            Change::Removed(k, v) => write!(f, "- {}={}", k, redact::value(k, v)),
+            Change::Changed(k, old, new) => write!(
+                f,
+                "~ {}={} -> {}",
+                k,
+                redact::value(k, old),
+                redact::value(k, new)
+            ),
+        }
+    }
+}
+
+/// Changes required to turn `before` into `after`, ordered by key
+pub fn diff(
+    before: &Env,
+    after: &Env,
+) -> Vec<Change> {
+    let mut changes = before
+        .iter()
+        .filter_map(|(k, old)| match after.get(k) {
+            None => Some(Change::Removed(k.clone(), old.clone())),
+            Some(new) if new != old => Some(Change::Changed(k.clone(), old.clone(), new.clone())),
+            _ => None,
+        })
+        .chain(
+            after
+                .iter()
+                .filter(|(k, _)| !before.contains_key(*k))
+                .map(|(k, v)| Change::Added(k.clone(), v.clone())),
+        )
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    changes
+}
+
+/// Counts of `(added, changed, removed)` vars across changes
+pub fn tally<'a, I>(changes: I) -> (usize, usize, usize)
+where
+    I: IntoIterator<Item = &'a Change>,
+{
+    changes.into_iter().fold(
+        (0, 0, 0),
+        |(added, changed, removed), change| match change {
+            Change::Added(..) => (added + 1, changed, removed),
+            Change::Changed(..) => (added, changed + 1, removed),
+            Change::Removed(..) => (added, changed, removed + 1),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn diff_reports_key_level_changes_in_order() {
+        let mut before = HashMap::new();
+        before.insert("A".to_string(), "1".to_string());
+        before.insert("B".to_string(), "2".to_string());
+        before.insert("C".to_string(), "3".to_string());
+        let mut after = HashMap::new();
+        after.insert("A".to_string(), "1".to_string());
+        after.insert("B".to_string(), "20".to_string());
+        after.insert("D".to_string(), "4".to_string());
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change::Changed("B".into(), "2".into(), "20".into()),
+                Change::Removed("C".into(), "3".into()),
+                Change::Added("D".into(), "4".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn tally_counts_by_kind() {
+        assert_eq!(
+            tally(&[
+                Change::Added("A".into(), "1".into()),
+                Change::Added("B".into(), "1".into()),
+                Change::Removed("C".into(), "1".into()),
+            ]),
+            (2, 0, 1)
+        )
+    }
+
+    #[test]
+    fn change_displays_with_markers() {
+        assert_eq!(Change::Added("A".into(), "1".into()).to_string(), "+ A=1");
+        assert_eq!(Change::Removed("A".into(), "1".into()).to_string(), "- A=1");
+        assert_eq!(
+            Change::Changed("A".into(), "1".into(), "2".into()).to_string(),
+            "~ A=1 -> 2"
+        );
+        assert_eq!(
+            Change::Changed("A".into(), "1".into(), "2".into()).summary(),
+            "~ A"
+        )
+    }
+}
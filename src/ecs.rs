@@ -0,0 +1,124 @@
+use crate::{
+    aws::{self, EcsClient},
+    error::Error,
+};
+use aws_sdk_ecs::types::ContainerDefinition;
+
+/// The `ssm://` or `secretsmanager://` reference an ecs secret's `valueFrom`
+/// translates to. Secrets Manager arns may name a JSON key of the secret
+/// after the secret's own arn, followed by a version stage and id lev has no
+/// way to refer to
+fn reference(value_from: &str) -> String {
+    let parts = value_from.split(':').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["arn", _, "secretsmanager", _, _, "secret", _, rest @ ..] => {
+            let secret = parts[..7].join(":");
+            match rest.first() {
+                Some(key) if !key.is_empty() => format!("secretsmanager://{}#{}", secret, key),
+                _ => format!("secretsmanager://{}", secret),
+            }
+        }
+        _ => format!("ssm://{}", value_from),
+    }
+}
+
+/// The container of a task definition to import from: the one named, or the
+/// only one there is
+fn container(
+    task_definition: &str,
+    mut containers: Vec<ContainerDefinition>,
+    name: Option<&str>,
+) -> Result<ContainerDefinition, Error> {
+    match name {
+        Some(name) => containers
+            .into_iter()
+            .find(|container| container.name.as_deref() == Some(name))
+            .ok_or_else(|| Error::NoSuchContainer(task_definition.into(), name.into())),
+        None if containers.len() == 1 => Ok(containers.remove(0)),
+        None => Err(Error::AmbiguousContainer(
+            task_definition.into(),
+            containers
+                .iter()
+                .filter_map(|container| container.name.clone())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+    }
+}
+
+/// Env vars of a container of an ecs task definition. Its secrets are
+/// yielded as `ssm://` and `secretsmanager://` references to where they are
+/// kept
+pub async fn task_vars(
+    ecs: EcsClient,
+    task_definition: String,
+    name: Option<String>,
+) -> Result<Vec<(String, String)>, Error> {
+    let request = ecs
+        .describe_task_definition()
+        .task_definition(task_definition.clone());
+    let described = aws::retry(|| request.clone().send()).await?;
+    let containers = described
+        .task_definition
+        .and_then(|definition| definition.container_definitions)
+        .unwrap_or_default();
+    let container = container(&task_definition, containers, name.as_deref())?;
+    let mut vars = container
+        .environment
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|pair| Some((pair.name?, pair.value.unwrap_or_default())))
+        .chain(
+            container
+                .secrets
+                .unwrap_or_default()
+                .into_iter()
+                .map(|secret| (secret.name, reference(&secret.value_from))),
+        )
+        .collect::<Vec<_>>();
+    vars.sort();
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_translates_value_from() {
+        assert_eq!(reference("/myapp/prod/db_url"), "ssm:///myapp/prod/db_url");
+        assert_eq!(
+            reference("arn:aws:ssm:us-east-1:123456789012:parameter/myapp/token"),
+            "ssm://arn:aws:ssm:us-east-1:123456789012:parameter/myapp/token"
+        );
+        assert_eq!(
+            reference("arn:aws:secretsmanager:us-east-1:123456789012:secret:prod-AbCdEf"),
+            "secretsmanager://arn:aws:secretsmanager:us-east-1:123456789012:secret:prod-AbCdEf"
+        );
+        assert_eq!(
+            reference(
+                "arn:aws:secretsmanager:us-east-1:123456789012:secret:prod-AbCdEf:DB_PASSWORD::"
+            ),
+            "secretsmanager://arn:aws:secretsmanager:us-east-1:123456789012:secret:prod-AbCdEf#DB_PASSWORD"
+        )
+    }
+
+    #[test]
+    fn container_requires_a_name_among_several() {
+        let named = |name: &str| ContainerDefinition::builder().name(name).build();
+        assert_eq!(
+            container("web:12", vec![named("app")], None)
+                .ok()
+                .and_then(|c| c.name),
+            Some("app".into())
+        );
+        assert!(container("web:12", vec![named("app"), named("proxy")], None).is_err());
+        assert_eq!(
+            container("web:12", vec![named("app"), named("proxy")], Some("proxy"))
+                .ok()
+                .and_then(|c| c.name),
+            Some("proxy".into())
+        );
+        assert!(container("web:12", vec![named("app")], Some("worker")).is_err())
+    }
+}
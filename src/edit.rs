@@ -0,0 +1,183 @@
+use crate::{error::Error, Env};
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    process::Command,
+};
+
+/// Whether a value needs quoting to read back as written
+pub fn needs_quotes(value: &str) -> bool {
+    value.trim() != value || value.starts_with('"') || value.contains(['\n', '\r'])
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A `KEY=value` dotenv line, double quoting values which would not
+/// otherwise read back as they are
+pub fn line(
+    key: &str,
+    value: &str,
+) -> String {
+    if needs_quotes(value) {
+        format!("{}={}\n", key, quote(value))
+    } else {
+        format!("{}={}\n", key, value)
+    }
+}
+
+/// An env as sorted dotenv lines
+pub fn dotenv(
+    function: &str,
+    env: &Env,
+) -> String {
+    let mut out = format!(
+        "# env of {}, vars removed here are removed from the function\n",
+        function
+    );
+    for (key, value) in env.iter().collect::<BTreeMap<_, _>>() {
+        out.push_str(&line(key, value));
+    }
+    out
+}
+
+/// The value of a double quoted dotenv value, without its quotes
+fn unquote(quoted: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = quoted.strip_prefix('"')?.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().trim().is_empty().then_some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Reads dotenv lines back into an env. Blank lines and `#` comments are
+/// skipped, and values may be double quoted
+pub fn parse(contents: &str) -> Result<Env, Error> {
+    let mut env = Env::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: &str| Error::InvalidDotenv(number + 1, reason.into());
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected KEY=value"))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(invalid("no name before `=`"));
+        }
+        let value = value.trim();
+        let value = if value.starts_with('"') {
+            unquote(value).ok_or_else(|| invalid("unterminated quoted value"))?
+        } else {
+            value.to_string()
+        };
+        env.insert(key.to_string(), value);
+    }
+    Ok(env)
+}
+
+/// The editor to open files in: `$VISUAL`, then `$EDITOR`, then vi
+fn editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".into())
+}
+
+/// Opens a file in the editor, which may carry arguments of its own, as in
+/// `code --wait`
+fn open(path: &Path) -> Result<(), Error> {
+    let editor = editor();
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(Error::EditorFailed(editor));
+    }
+    Ok(())
+}
+
+/// Has the editor edit an env as a dotenv file readable only by the current
+/// user, yielding the edited env. The file is kept when it fails to parse so
+/// edits are not lost
+pub fn edit(
+    function: &str,
+    env: &Env,
+) -> Result<Env, Error> {
+    let name = function.rsplit(':').next().unwrap_or(function);
+    let path = env::temp_dir().join(format!("lev-edit-{}-{}.env", name, std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?
+        .write_all(dotenv(function, env).as_bytes())?;
+    let edited = open(&path).and_then(|_| Ok(fs::read_to_string(&path)?));
+    match edited.and_then(|contents| parse(&contents)) {
+        Err(err @ Error::InvalidDotenv(..)) => {
+            errln!("kept your edits in {}", path.display());
+            Err(err)
+        }
+        other => {
+            let _ = fs::remove_file(&path);
+            other
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotenv_round_trips_through_parse() {
+        let mut env = Env::new();
+        env.insert("PLAIN".into(), "a=b c".into());
+        env.insert("LINES".into(), "one\ntwo".into());
+        env.insert("PADDED".into(), " x ".into());
+        env.insert("QUOTED".into(), "\"q\" \\ # not a comment".into());
+        env.insert("EMPTY".into(), "".into());
+        assert_eq!(parse(&dotenv("my-func", &env)).ok(), Some(env))
+    }
+
+    #[test]
+    fn parse_reports_line_numbers() {
+        match parse("# comment\n\nA=1\nnot a var\n") {
+            Err(Error::InvalidDotenv(line, _)) => assert_eq!(line, 4),
+            other => panic!("expected an invalid dotenv error, got {:?}", other),
+        }
+        assert!(parse("A=\"unterminated\n").is_err());
+        assert!(parse("=value\n").is_err())
+    }
+}
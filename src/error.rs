@@ -1,23 +1,783 @@
+// failure_derive expands to impls nested in anonymous consts
+#![allow(non_local_definitions)]
+
+use crate::{
+    aws::{self, SdkError},
+    trace,
+};
+use aws_credential_types::provider::error::CredentialsError;
+use aws_sdk_dynamodb::operation::{put_item::PutItemError, query::QueryError};
+use aws_sdk_ecs::operation::describe_task_definition::DescribeTaskDefinitionError;
+use aws_sdk_kms::operation::{decrypt::DecryptError, encrypt::EncryptError};
+use aws_sdk_lambda::operation::{
+    get_alias::GetAliasError, get_function::GetFunctionError,
+    get_function_configuration::GetFunctionConfigurationError, list_functions::ListFunctionsError,
+    list_versions_by_function::ListVersionsByFunctionError, publish_version::PublishVersionError,
+    update_alias::UpdateAliasError,
+    update_function_configuration::UpdateFunctionConfigurationError,
+};
+use aws_sdk_resourcegroupstagging::operation::get_resources::GetResourcesError;
+use aws_sdk_s3::operation::{
+    get_object::GetObjectError, list_objects_v2::ListObjectsV2Error, put_object::PutObjectError,
+};
+use aws_sdk_secretsmanager::operation::{
+    create_secret::CreateSecretError, delete_secret::DeleteSecretError,
+    get_secret_value::GetSecretValueError, put_secret_value::PutSecretValueError,
+};
+use aws_sdk_ssm::operation::{get_parameter::GetParameterError, put_parameter::PutParameterError};
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
 use failure::Fail;
-use rusoto_core::RusotoError;
-use rusoto_lambda::{GetFunctionConfigurationError, UpdateFunctionConfigurationError};
+use std::{error::Error as StdError, fmt, iter};
 
 #[derive(Debug, Fail)]
 pub enum Error {
     #[fail(display = "{}", _0)]
-    GetConfig(#[cause] RusotoError<GetFunctionConfigurationError>),
+    GetConfig(#[cause] aws::Failure<GetFunctionConfigurationError>),
+    #[fail(display = "{}", _0)]
+    GetFunction(#[cause] aws::Failure<GetFunctionError>),
+    #[fail(display = "{}", _0)]
+    UpdateConfig(#[cause] aws::Failure<UpdateFunctionConfigurationError>),
+    #[fail(display = "{}", _0)]
+    GetAlias(#[cause] aws::Failure<GetAliasError>),
+    #[fail(display = "{}", _0)]
+    PublishVersion(#[cause] aws::Failure<PublishVersionError>),
+    #[fail(display = "{}", _0)]
+    UpdateAlias(#[cause] aws::Failure<UpdateAliasError>),
+    #[fail(display = "{}", _0)]
+    ListFunctions(#[cause] aws::Failure<ListFunctionsError>),
+    #[fail(display = "{}", _0)]
+    ListVersions(#[cause] aws::Failure<ListVersionsByFunctionError>),
+    #[fail(display = "{}", _0)]
+    PutParameter(#[cause] aws::Failure<PutParameterError>),
+    #[fail(display = "{}", _0)]
+    GetParameter(#[cause] aws::Failure<GetParameterError>),
+    #[fail(display = "{}", _0)]
+    GetSecretValue(#[cause] aws::Failure<GetSecretValueError>),
+    #[fail(display = "{}", _0)]
+    PutSecretValue(#[cause] aws::Failure<PutSecretValueError>),
+    #[fail(display = "{}", _0)]
+    CreateSecret(#[cause] aws::Failure<CreateSecretError>),
+    #[fail(display = "{}", _0)]
+    DeleteSecret(#[cause] aws::Failure<DeleteSecretError>),
+    #[fail(display = "{}", _0)]
+    PutObject(#[cause] aws::Failure<PutObjectError>),
+    #[fail(display = "{}", _0)]
+    GetObject(#[cause] aws::Failure<GetObjectError>),
+    #[fail(display = "{}", _0)]
+    ListObjects(#[cause] aws::Failure<ListObjectsV2Error>),
+    #[fail(display = "{}", _0)]
+    PutItem(#[cause] aws::Failure<PutItemError>),
     #[fail(display = "{}", _0)]
-    UpdateConfig(#[cause] RusotoError<UpdateFunctionConfigurationError>),
+    Query(#[cause] aws::Failure<QueryError>),
+    #[fail(display = "{}", _0)]
+    GetCallerIdentity(#[cause] aws::Failure<GetCallerIdentityError>),
+    #[fail(display = "{}", _0)]
+    Encrypt(#[cause] aws::Failure<EncryptError>),
+    #[fail(display = "{}", _0)]
+    Decrypt(#[cause] aws::Failure<DecryptError>),
+    #[fail(display = "{} does not hold base64 encoded ciphertext", _0)]
+    InvalidCiphertext(String),
+    #[fail(display = "secret {} does not hold a JSON object", _0)]
+    InvalidSecret(String),
+    #[fail(display = "secret {} does not exist", _0)]
+    SecretNotFound(String),
+    #[fail(display = "{}", _0)]
+    DescribeTaskDefinition(#[cause] aws::Failure<DescribeTaskDefinitionError>),
+    #[fail(display = "task definition {} has no container named {}", _0, _1)]
+    NoSuchContainer(String, String),
+    #[fail(
+        display = "task definition {} has several containers, pick one of {} with --container",
+        _0, _1
+    )]
+    AmbiguousContainer(String, String),
+    #[fail(display = "invalid serverless config: {}", _0)]
+    ServerlessConfig(String),
+    #[fail(display = "serverless config declares no function {}", _0)]
+    NoServerlessFunction(String),
+    #[fail(display = "invalid template: {}", _0)]
+    InvalidTemplate(String),
+    #[fail(display = "template declares no resource {}", _0)]
+    NoSuchResource(String),
+    #[fail(
+        display = "the template does not name the function {} deploys as, pass --to",
+        _0
+    )]
+    UnnamedFunction(String),
+    #[fail(
+        display = "import {} can not be combined with -f, --tag, --group, --from-secret or \
+                   --from-url, name the function to import into with --to",
+        _0
+    )]
+    ImportConflict(String),
+    #[fail(display = "{}", _0)]
+    GetResources(#[cause] aws::Failure<GetResourcesError>),
+    #[fail(display = "no functions are tagged with {}", _0)]
+    NoTaggedFunctions(String),
+    #[fail(
+        display = "--stage {} needs a template in the [naming] table of .lev.toml",
+        _0
+    )]
+    NoNamingTemplate(String),
+    #[fail(display = "no group {} in .lev.toml", _0)]
+    NoSuchGroup(String),
+    #[fail(display = "group {} has no functions", _0)]
+    EmptyGroup(String),
+    #[fail(display = "no functions match {}", _0)]
+    NoMatchingFunctions(String),
+    #[fail(display = "{}", _0)]
+    Io(#[cause] std::io::Error),
+    #[fail(display = "invalid manifest: {}", _0)]
+    Manifest(#[cause] serde_yaml::Error),
+    #[fail(display = "{}", _0)]
+    Json(#[cause] serde_json::Error),
+    #[fail(display = "{}", _0)]
+    Http(#[cause] reqwest::Error),
+    #[fail(display = "{} does not name an object, expected s3://bucket/key", _0)]
+    InvalidLocation(String),
+    #[fail(display = "refusing to read {} over plain http, use https", _0)]
+    InsecureSource(String),
+    #[fail(display = "invalid deployment package: {}", _0)]
+    Package(#[cause] zip::result::ZipError),
+    #[fail(display = "failed to resolve {}: {}", _0, _1)]
+    Resolve(String, String),
+    #[fail(display = "update of {} failed: {}", _0, _1)]
+    UpdateFailed(String, String),
+    #[fail(display = "timed out waiting for the update of {} to complete", _0)]
+    UpdateTimeout(String),
+    #[fail(display = "failed to render {} output: {}", _0, _1)]
+    Render(String, String),
+    #[fail(display = "unknown output format {}, expected one of {}", _0, _1)]
+    UnknownOutput(String, String),
+    #[fail(display = "a daemon is already listening on {}", _0)]
+    DaemonRunning(String),
+    #[fail(
+        display = "no daemon is listening on {}, start one with `lev daemon`",
+        _0
+    )]
+    DaemonUnavailable(String),
+    #[fail(
+        display = "the client uses profile {}, but the daemon reads credentials from {}, \
+                   start a daemon with AWS_PROFILE={} or run without --use-daemon",
+        _0, _1, _0
+    )]
+    DaemonProfile(String, String),
+    #[fail(
+        display = "the client's AWS_REGION is {}, but the daemon's is {}, \
+                   start a daemon with the same AWS_REGION or run without --use-daemon",
+        _0, _1
+    )]
+    DaemonRegion(String, String),
+    #[fail(display = "{} does not define {}", _0, _1)]
+    MissingVars(String, String),
+    #[fail(display = "can not move vars from {} onto itself", _0)]
+    SameFunction(String),
+    #[fail(display = "no snapshot of {} to roll back to", _0)]
+    NoSnapshot(String),
+    #[fail(
+        display = "left {} as it is, as its env could not be snapshotted to roll back to: {}, \
+                   pass --allow-unsnapshotted to change it anyway",
+        _0, _1
+    )]
+    SnapshotFailed(String, Box<Error>),
+    #[fail(
+        display = "no audit table or log to read history from, pass one with --audit-table or --audit-log"
+    )]
+    AuditDisabled,
+    #[fail(display = "line {} of the edited env is invalid: {}", _0, _1)]
+    InvalidDotenv(usize, String),
+    #[fail(
+        display = "editor {} exited unsuccessfully, leaving the env as it was",
+        _0
+    )]
+    EditorFailed(String),
+    #[fail(display = "{} changed while it was being edited, edit it again", _0)]
+    EditConflict(String),
+    #[fail(display = "failed to read {}: {}", _0, _1)]
+    ValueFile(String, String),
+    #[fail(display = "invalid patch: {}", _0)]
+    InvalidPatch(String),
+    #[fail(display = "patch test of {} failed", _0)]
+    PatchTestFailed(String),
+    #[fail(display = "{} of {} holds no JSON to merge into", _1, _0)]
+    NotJson(String, String),
+    #[fail(display = "{} is not a valid env file: {}", _0, _1)]
+    InvalidEnvFile(String, String),
+    #[fail(
+        display = "${{{}}} is neither given with --var nor set in your env",
+        _0
+    )]
+    UndefinedTemplateVar(String),
+    #[fail(display = "unterminated ${{ in {}", _0)]
+    InvalidTemplateValue(String),
+    #[fail(
+        display = "{} would take up {} bytes of the env, more than lambda's limit of {}",
+        _0, _1, _2
+    )]
+    ValueTooLarge(String, usize, usize),
+    #[fail(display = "{} is not text, base64 encode it with --encode-base64", _0)]
+    BinaryValue(String),
+    #[fail(display = "{}'s {} does not hold base64 encoded text", _0, _1)]
+    InvalidBase64(String, String),
+    #[fail(display = "{} has no value and is not set in your env to inherit", _0)]
+    NotInherited(String),
+    #[fail(display = "{} and {} can not both be read from stdin", _0, _1)]
+    StdinTaken(String, String),
+    #[fail(
+        display = "{} is set to {}, which lev prints in place of redacted values, copy them with lev get --show-redacted",
+        _0, _1
+    )]
+    MaskedValue(String, &'static str),
+    #[fail(display = "invalid config {}: {}", _0, _1)]
+    Config(String, String),
+    #[fail(display = "invalid redaction pattern {}: {}", _0, _1)]
+    InvalidPattern(String, String),
+    #[fail(
+        display = "the env of {} would take up {} bytes, more than lambda's limit of {}: {}",
+        _0, _1, _2, _3
+    )]
+    EnvTooLarge(String, usize, usize, String),
+    #[fail(
+        display = "{} are not valid names, which start with a letter followed by letters, \
+                   digits or underscores, pass --sanitize to replace what lambda refuses",
+        _0
+    )]
+    InvalidKeys(String),
+    #[fail(
+        display = "{} of {} are protected, pass --force to remove them anyway",
+        _1, _0
+    )]
+    ProtectedKeys(String, String),
+    #[fail(display = "invalid policy {}: {}", _0, _1)]
+    InvalidPolicy(String, String),
+    #[fail(display = "the env of {} would violate policy: {}", _0, _1)]
+    PolicyViolation(String, String),
+    #[fail(
+        display = "no policy to validate against, pass --policy or set safety.policy in .lev.toml"
+    )]
+    NoPolicy,
+    #[fail(display = "no webhook to notify, set notify.webhook in .lev.toml")]
+    NoWebhook,
+    #[fail(display = "renaming vars of {} would overwrite {}", _0, _1)]
+    RenameCollision(String, String),
+    #[fail(display = "{} are reserved by lambda and can not be set", _0)]
+    ReservedKeys(String),
+    #[fail(
+        display = "{} change how lambda runs functions, pass --force to set them anyway",
+        _0
+    )]
+    RuntimeKeys(String),
+    #[fail(display = "{} is not a region lev knows", _0)]
+    InvalidRegion(String),
+    #[fail(display = ".lev.toml is read only, refusing changes to envs")]
+    ReadOnly,
+    #[fail(display = "function {} does not exist, did you mean {}?", _0, _1)]
+    NoSuchFunction(String, String),
+    #[fail(display = "exited with status {}", _0)]
+    Exit(i32),
+}
+
+/// Classes of failure scripts tell apart, to know whether to retry or to
+/// fix something first
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Kind {
+    NotFound,
+    AccessDenied,
+    Throttled,
+    Validation,
+    Network,
+    Other,
+}
+
+impl Kind {
+    /// Name the class of failure is reported by in machine readable errors
+    pub fn code(self) -> &'static str {
+        match self {
+            Kind::NotFound => "not_found",
+            Kind::AccessDenied => "access_denied",
+            Kind::Throttled => "throttled",
+            Kind::Validation => "validation",
+            Kind::Network => "network",
+            Kind::Other => "error",
+        }
+    }
+
+    /// Status lev exits with when failing this way, telling those to retry
+    /// from those to fix apart
+    pub fn status(self) -> i32 {
+        match self {
+            Kind::NotFound => 3,
+            Kind::AccessDenied => 4,
+            Kind::Throttled => 5,
+            Kind::Validation => 6,
+            Kind::Network => 7,
+            Kind::Other => 1,
+        }
+    }
+
+    /// Whether trying again later may succeed, without changing anything
+    pub fn retryable(self) -> bool {
+        matches!(self, Kind::Throttled | Kind::Network)
+    }
+}
+
+/// The class of an HTTP status aws or an https source answered with
+fn status_kind(status: u16) -> Option<Kind> {
+    match status {
+        401 | 403 => Some(Kind::AccessDenied),
+        404 => Some(Kind::NotFound),
+        429 => Some(Kind::Throttled),
+        _ => None,
+    }
+}
+
+/// The class of a service error by the code aws gives it
+fn code_kind(code: &str) -> Option<Kind> {
+    if code.contains("NotFound") || code.starts_with("NoSuch") {
+        Some(Kind::NotFound)
+    } else if code.contains("AccessDenied")
+        || code.contains("UnrecognizedClient")
+        || code.contains("ExpiredToken")
+    {
+        Some(Kind::AccessDenied)
+    } else if code.starts_with("Invalid") || code.contains("Validation") {
+        Some(Kind::Validation)
+    } else {
+        None
+    }
+}
+
+/// The class of a failed aws request, and the id aws knows it by when known.
+/// Service errors are told apart by the codes aws gives them, and failing
+/// that by their http status
+fn aws_kind<E>(failure: &aws::Failure<E>) -> (Kind, Option<&str>)
+where
+    E: ProvideErrorMetadata + fmt::Debug,
+{
+    let err = &failure.0;
+    let kind = match err {
+        SdkError::DispatchFailure(failure) if failure.is_io() || failure.is_timeout() => {
+            Kind::Network
+        }
+        SdkError::DispatchFailure(failure)
+            if failure
+                .as_connector_error()
+                .is_some_and(|err| credentials_failed(err)) =>
+        {
+            Kind::AccessDenied
+        }
+        SdkError::DispatchFailure(_) => Kind::Network,
+        SdkError::TimeoutError(_) => Kind::Network,
+        SdkError::ConstructionFailure(_) => Kind::Validation,
+        err if aws::is_throttling(err) => Kind::Throttled,
+        err => err
+            .code()
+            .and_then(code_kind)
+            .or_else(|| {
+                err.raw_response()
+                    .and_then(|response| status_kind(response.status().as_u16()))
+            })
+            .unwrap_or(Kind::Other),
+    };
+    (kind, trace::failed_request_id(err))
+}
+
+/// Whether credentials could not be resolved somewhere along the chain of
+/// errors leading to `err`
+fn credentials_failed(err: &(dyn StdError + 'static)) -> bool {
+    iter::successors(Some(err), |&err| err.source()).any(|err| err.is::<CredentialsError>())
+}
+
+/// Applies `f` to the aws failure a variant holds, if it holds one
+macro_rules! failure {
+    ($error:expr, $f:ident, [$($variant:ident),+]) => {
+        match $error {
+            $(Error::$variant(err) => Some($f(err)),)+
+            _ => None,
+        }
+    };
+}
+
+impl Error {
+    fn aws(&self) -> Option<(Kind, Option<&str>)> {
+        failure!(
+            self,
+            aws_kind,
+            [
+                GetConfig,
+                GetFunction,
+                UpdateConfig,
+                GetAlias,
+                PublishVersion,
+                UpdateAlias,
+                ListFunctions,
+                ListVersions,
+                PutParameter,
+                GetParameter,
+                GetSecretValue,
+                PutSecretValue,
+                CreateSecret,
+                DeleteSecret,
+                PutObject,
+                GetObject,
+                ListObjects,
+                PutItem,
+                Query,
+                GetCallerIdentity,
+                Encrypt,
+                Decrypt,
+                DescribeTaskDefinition,
+                GetResources
+            ]
+        )
+    }
+
+    /// The class of failure this is
+    pub fn kind(&self) -> Kind {
+        if let Some((kind, _)) = self.aws() {
+            return kind;
+        }
+        match self {
+            Error::SecretNotFound(_)
+            | Error::NoSuchContainer(..)
+            | Error::NoServerlessFunction(_)
+            | Error::NoSuchResource(_)
+            | Error::NoTaggedFunctions(_)
+            | Error::NoSuchGroup(_)
+            | Error::EmptyGroup(_)
+            | Error::NoMatchingFunctions(_)
+            | Error::NoSnapshot(_)
+            | Error::NoSuchFunction(..) => Kind::NotFound,
+            Error::AmbiguousContainer(..)
+            | Error::ServerlessConfig(_)
+            | Error::InvalidTemplate(_)
+            | Error::Manifest(_)
+            | Error::InvalidLocation(_)
+            | Error::InsecureSource(_)
+            | Error::UnknownOutput(..)
+            | Error::InvalidDotenv(..)
+            | Error::InvalidEnvFile(..)
+            | Error::NotJson(..)
+            | Error::RenameCollision(..)
+            | Error::ProtectedKeys(..)
+            | Error::SameFunction(_)
+            | Error::UnnamedFunction(_)
+            | Error::ImportConflict(_)
+            | Error::DaemonProfile(..)
+            | Error::DaemonRegion(..)
+            | Error::InvalidPolicy(..)
+            | Error::PolicyViolation(..)
+            | Error::NoPolicy
+            | Error::NoWebhook
+            | Error::InvalidPatch(_)
+            | Error::PatchTestFailed(_)
+            | Error::UndefinedTemplateVar(_)
+            | Error::InvalidTemplateValue(_)
+            | Error::ValueTooLarge(..)
+            | Error::BinaryValue(_)
+            | Error::NotInherited(_)
+            | Error::StdinTaken(..)
+            | Error::MaskedValue(..)
+            | Error::Config(..)
+            | Error::InvalidPattern(..)
+            | Error::EnvTooLarge(..)
+            | Error::InvalidKeys(_)
+            | Error::ReservedKeys(_)
+            | Error::RuntimeKeys(_)
+            | Error::InvalidRegion(_)
+            | Error::NoNamingTemplate(_)
+            | Error::ReadOnly => Kind::Validation,
+            Error::SnapshotFailed(_, err) => err.kind(),
+            Error::Http(err) => err
+                .status()
+                .and_then(|status| status_kind(status.as_u16()))
+                .unwrap_or(Kind::Network),
+            _ => Kind::Other,
+        }
+    }
+
+    /// The id aws knows the failed request by, when known
+    pub fn request_id(&self) -> Option<&str> {
+        self.aws().and_then(|(_, request_id)| request_id)
+    }
+}
+
+impl From<SdkError<GetFunctionConfigurationError>> for Error {
+    fn from(err: SdkError<GetFunctionConfigurationError>) -> Self {
+        Error::GetConfig(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<DescribeTaskDefinitionError>> for Error {
+    fn from(err: SdkError<DescribeTaskDefinitionError>) -> Self {
+        Error::DescribeTaskDefinition(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetFunctionError>> for Error {
+    fn from(err: SdkError<GetFunctionError>) -> Self {
+        Error::GetFunction(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<UpdateFunctionConfigurationError>> for Error {
+    fn from(err: SdkError<UpdateFunctionConfigurationError>) -> Self {
+        Error::UpdateConfig(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetAliasError>> for Error {
+    fn from(err: SdkError<GetAliasError>) -> Self {
+        Error::GetAlias(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<PublishVersionError>> for Error {
+    fn from(err: SdkError<PublishVersionError>) -> Self {
+        Error::PublishVersion(aws::Failure(err))
+    }
 }
 
-impl From<RusotoError<GetFunctionConfigurationError>> for Error {
-    fn from(err: RusotoError<GetFunctionConfigurationError>) -> Self {
-        Error::GetConfig(err)
+impl From<SdkError<UpdateAliasError>> for Error {
+    fn from(err: SdkError<UpdateAliasError>) -> Self {
+        Error::UpdateAlias(aws::Failure(err))
     }
 }
 
-impl From<RusotoError<UpdateFunctionConfigurationError>> for Error {
-    fn from(err: RusotoError<UpdateFunctionConfigurationError>) -> Self {
-        Error::UpdateConfig(err)
+impl From<SdkError<ListFunctionsError>> for Error {
+    fn from(err: SdkError<ListFunctionsError>) -> Self {
+        Error::ListFunctions(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<ListVersionsByFunctionError>> for Error {
+    fn from(err: SdkError<ListVersionsByFunctionError>) -> Self {
+        Error::ListVersions(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<PutParameterError>> for Error {
+    fn from(err: SdkError<PutParameterError>) -> Self {
+        Error::PutParameter(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetParameterError>> for Error {
+    fn from(err: SdkError<GetParameterError>) -> Self {
+        Error::GetParameter(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetSecretValueError>> for Error {
+    fn from(err: SdkError<GetSecretValueError>) -> Self {
+        Error::GetSecretValue(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<PutSecretValueError>> for Error {
+    fn from(err: SdkError<PutSecretValueError>) -> Self {
+        Error::PutSecretValue(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<CreateSecretError>> for Error {
+    fn from(err: SdkError<CreateSecretError>) -> Self {
+        Error::CreateSecret(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<DeleteSecretError>> for Error {
+    fn from(err: SdkError<DeleteSecretError>) -> Self {
+        Error::DeleteSecret(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<PutObjectError>> for Error {
+    fn from(err: SdkError<PutObjectError>) -> Self {
+        Error::PutObject(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetObjectError>> for Error {
+    fn from(err: SdkError<GetObjectError>) -> Self {
+        Error::GetObject(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<ListObjectsV2Error>> for Error {
+    fn from(err: SdkError<ListObjectsV2Error>) -> Self {
+        Error::ListObjects(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<PutItemError>> for Error {
+    fn from(err: SdkError<PutItemError>) -> Self {
+        Error::PutItem(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<QueryError>> for Error {
+    fn from(err: SdkError<QueryError>) -> Self {
+        Error::Query(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetCallerIdentityError>> for Error {
+    fn from(err: SdkError<GetCallerIdentityError>) -> Self {
+        Error::GetCallerIdentity(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<EncryptError>> for Error {
+    fn from(err: SdkError<EncryptError>) -> Self {
+        Error::Encrypt(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<DecryptError>> for Error {
+    fn from(err: SdkError<DecryptError>) -> Self {
+        Error::Decrypt(aws::Failure(err))
+    }
+}
+
+impl From<SdkError<GetResourcesError>> for Error {
+    fn from(err: SdkError<GetResourcesError>) -> Self {
+        Error::GetResources(aws::Failure(err))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::Manifest(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        Error::Package(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_lambda::types::error::{
+        InvalidParameterValueException, ResourceNotFoundException, TooManyRequestsException,
+    };
+    use aws_smithy_runtime_api::{
+        client::{orchestrator::HttpResponse, result::ConnectorError},
+        http::StatusCode,
+    };
+    use aws_smithy_types::{body::SdkBody, error::ErrorMetadata};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn kind_tells_aws_failures_apart() {
+        let not_found = Error::from(aws::service_error(
+            GetFunctionConfigurationError::ResourceNotFoundException(
+                ResourceNotFoundException::builder()
+                    .message("foo")
+                    .meta(
+                        ErrorMetadata::builder()
+                            .code("ResourceNotFoundException")
+                            .build(),
+                    )
+                    .build(),
+            ),
+            404,
+        ));
+        assert_eq!(not_found.kind(), Kind::NotFound);
+        assert_eq!(not_found.kind().status(), 3);
+        let throttled = Error::from(aws::service_error(
+            GetFunctionConfigurationError::TooManyRequestsException(
+                TooManyRequestsException::builder()
+                    .message("slow down")
+                    .meta(
+                        ErrorMetadata::builder()
+                            .code("TooManyRequestsException")
+                            .build(),
+                    )
+                    .build(),
+            ),
+            429,
+        ));
+        assert_eq!(throttled.kind(), Kind::Throttled);
+        assert!(throttled.kind().retryable());
+        let invalid = Error::from(aws::service_error(
+            UpdateFunctionConfigurationError::InvalidParameterValueException(
+                InvalidParameterValueException::builder()
+                    .message("bad")
+                    .meta(
+                        ErrorMetadata::builder()
+                            .code("InvalidParameterValueException")
+                            .build(),
+                    )
+                    .build(),
+            ),
+            400,
+        ));
+        assert_eq!(invalid.kind(), Kind::Validation);
+        let unreachable = Error::from(SdkError::<GetFunctionConfigurationError>::dispatch_failure(
+            ConnectorError::io("connection refused".into()),
+        ));
+        assert_eq!(unreachable.kind(), Kind::Network);
+        assert_eq!(unreachable.kind().status(), 7);
+        assert_eq!(unreachable.request_id(), None);
+        let unauthenticated = Error::from(
+            SdkError::<GetFunctionConfigurationError>::dispatch_failure(ConnectorError::other(
+                Box::new(CredentialsError::not_loaded("no profile named dev")),
+                None,
+            )),
+        );
+        assert_eq!(unauthenticated.kind(), Kind::AccessDenied);
+        let mentions_credentials =
+            Error::from(SdkError::<GetFunctionConfigurationError>::dispatch_failure(
+                ConnectorError::other("bad credentials header".into(), None),
+            ));
+        assert_eq!(mentions_credentials.kind(), Kind::Network)
+    }
+
+    #[test]
+    fn kind_reads_unknown_responses() {
+        let mut response = HttpResponse::new(
+            StatusCode::try_from(403).unwrap(),
+            SdkBody::from("{\"Type\":\"User\"}"),
+        );
+        response.headers_mut().insert("x-amzn-requestid", "abc-123");
+        let denied = Error::from(SdkError::service_error(
+            GetFunctionConfigurationError::generic(ErrorMetadata::builder().build()),
+            response,
+        ));
+        assert_eq!(denied.kind(), Kind::AccessDenied);
+        assert!(!denied.kind().retryable());
+        assert_eq!(denied.request_id(), Some("abc-123"))
+    }
+
+    #[test]
+    fn kind_classes_lev_failures() {
+        assert_eq!(
+            Error::ReservedKeys("AWS_REGION".into()).kind(),
+            Kind::Validation
+        );
+        assert_eq!(Error::NoSnapshot("foo".into()).kind(), Kind::NotFound);
+        assert_eq!(Error::AuditDisabled.kind().code(), "error")
     }
 }
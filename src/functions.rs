@@ -0,0 +1,148 @@
+use crate::{
+    aws::{self, LambdaClient},
+    error::Error,
+    lev_dir, whoami,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+/// Function names of an account and region, cached so completion and pickers
+/// needn't wait on lambda listing thousands of functions
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Cache {
+    pub refreshed: DateTime<Utc>,
+    pub names: Vec<String>,
+}
+
+/// Where the names of a region are cached, apart for each profile as
+/// profiles may well read credentials of other accounts
+fn path(region: &str) -> PathBuf {
+    lev_dir()
+        .join("functions")
+        .join(whoami::profile())
+        .join(format!("{}.json", region))
+}
+
+impl Cache {
+    /// Caches are refreshed in the background once they are this old
+    fn ttl() -> Duration {
+        Duration::minutes(5)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        Utc::now() - self.refreshed > Cache::ttl()
+    }
+
+    fn save(
+        &self,
+        region: &str,
+    ) -> Result<(), Error> {
+        let path = path(region);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+/// The cached function names of a region, in the account of the profile in
+/// effect, if there are any
+pub fn cached(region: &str) -> Option<Cache> {
+    fs::read(path(region))
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+}
+
+/// Names containing a filter, ignoring case. Every name contains an empty one
+pub fn matching<'a>(
+    names: &'a [String],
+    filter: &str,
+) -> impl Iterator<Item = &'a String> {
+    let filter = filter.to_lowercase();
+    names
+        .iter()
+        .filter(move |name| name.to_lowercase().contains(&filter))
+}
+
+/// Streams function names from lambda a page at a time, caching them once the
+/// last page has arrived
+pub fn refresh(
+    lambda: LambdaClient,
+    region: String,
+) -> impl Stream<Item = Result<Vec<String>, Error>> + Send {
+    let listed = Arc::new(Mutex::new(Vec::new()));
+    let cached = listed.clone();
+    aws::function_names(lambda)
+        .inspect_ok(move |page| {
+            if let Ok(mut listed) = listed.lock() {
+                listed.extend(page.iter().cloned());
+            }
+        })
+        .chain(
+            stream::once(future::lazy(move |_| {
+                let names = cached
+                    .lock()
+                    .map(|mut names| names.split_off(0))
+                    .unwrap_or_default();
+                let cache = Cache {
+                    refreshed: Utc::now(),
+                    names,
+                };
+                cache.save(&region).map(|_| None)
+            }))
+            .try_filter_map(future::ok),
+        )
+}
+
+/// Refreshes the cache in a detached `lev functions --refresh`, so stale names
+/// are answered right away and fresh ones are at hand next time
+pub fn refresh_in_background() {
+    let spawned = env::current_exe().and_then(|lev| {
+        Command::new(lev)
+            .args(["functions", "--refresh"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    });
+    if let Err(err) = spawned {
+        errln!("failed to refresh function names: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_ignores_case() {
+        let names = vec![
+            "Orders-API".to_string(),
+            "orders-worker".to_string(),
+            "payments".to_string(),
+        ];
+        assert_eq!(
+            matching(&names, "ORDERS").collect::<Vec<_>>(),
+            vec!["Orders-API", "orders-worker"]
+        );
+        assert_eq!(matching(&names, "").count(), 3)
+    }
+
+    #[test]
+    fn caches_go_stale() {
+        let cache = |age: i64| Cache {
+            refreshed: Utc::now() - Duration::minutes(age),
+            names: Vec::new(),
+        };
+        assert!(!cache(1).is_stale());
+        assert!(cache(10).is_stale())
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    backup,
+    config::{self, GcConfig},
+    error::Error,
+    journal,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{fmt, str::FromStr};
+
+/// Kinds of local state lev accrues over time
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Artifact {
+    Journal,
+    Backups,
+}
+
+impl Artifact {
+    pub fn all() -> Vec<Artifact> {
+        vec![Artifact::Journal, Artifact::Backups]
+    }
+
+    /// How much of this artifact to keep: `max_age` and `keep` when given,
+    /// or else what the `[gc]` table of a .lev.toml keeps of it, items
+    /// older than 90 days being removed when neither says
+    pub fn retention(
+        self,
+        config: &GcConfig,
+        max_age: Option<Duration>,
+        keep: Option<usize>,
+    ) -> Result<Retention, Error> {
+        let configured = match self {
+            Artifact::Journal => &config.journal,
+            Artifact::Backups => &config.backups,
+        };
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None => configured
+                .max_age
+                .as_deref()
+                .map(parse_age)
+                .transpose()
+                .map_err(|reason| {
+                    Error::Config(config::FILE.into(), format!("gc.{}: {}", self, reason))
+                })?
+                .unwrap_or_else(|| Duration::days(90)),
+        };
+        Ok(Retention {
+            max_age: Some(max_age),
+            keep: keep.or(configured.keep),
+        })
+    }
+
+    /// Removes whatever the policy doesn't retain, yielding `(removed, total)`
+    pub fn collect(
+        self,
+        retention: &Retention,
+        dry_run: bool,
+    ) -> Result<(usize, usize), Error> {
+        match self {
+            Artifact::Journal => journal::prune(retention, dry_run),
+            Artifact::Backups => backup::prune(retention, dry_run),
+        }
+    }
+}
+
+impl FromStr for Artifact {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "journal" => Ok(Artifact::Journal),
+            "backups" => Ok(Artifact::Backups),
+            other => Err(format!("unknown artifact type `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match self {
+            Artifact::Journal => write!(f, "journal"),
+            Artifact::Backups => write!(f, "backups"),
+        }
+    }
+}
+
+/// Parses ages like `90d`, `2w` or `12h`
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid age `{}`: expected a whole number followed by h, d or w",
+            s
+        )
+    };
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    // unsigned, as nothing is ever older than a negative age
+    let amount = s
+        .strip_suffix(unit)
+        .and_then(|amount| amount.parse::<u32>().ok())
+        .map(i64::from)
+        .ok_or_else(invalid)?;
+    match unit {
+        'h' => Ok(Duration::hours(amount)),
+        'd' => Ok(Duration::days(amount)),
+        'w' => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// How much of an artifact's history to keep
+#[derive(Debug, PartialEq, Default)]
+pub struct Retention {
+    /// Items older than this are removed
+    pub max_age: Option<Duration>,
+    /// At most this many of the newest items are kept per function
+    pub keep: Option<usize>,
+}
+
+impl Retention {
+    /// True if an item created at `timestamp`, which is the `rank`th newest
+    /// (starting at 0) among its function's items, should be kept
+    pub fn retains(
+        &self,
+        timestamp: DateTime<Utc>,
+        rank: usize,
+    ) -> bool {
+        let young = self
+            .max_age
+            .and_then(|age| Utc::now().checked_sub_signed(age))
+            .map(|cutoff| timestamp >= cutoff)
+            .unwrap_or(true);
+        let recent = self.keep.map(|keep| rank < keep).unwrap_or(true);
+        young && recent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_age_accepts_units() {
+        assert_eq!(parse_age("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_age("90d"), Ok(Duration::days(90)));
+        assert_eq!(parse_age("2w"), Ok(Duration::weeks(2)));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("-5d").is_err());
+        assert!(parse_age("5é").is_err());
+        assert!(parse_age("é").is_err());
+    }
+
+    #[test]
+    fn retention_prefers_flags_then_config() {
+        let config = GcConfig {
+            journal: config::RetentionConfig {
+                max_age: Some("2w".into()),
+                keep: Some(5),
+            },
+            ..GcConfig::default()
+        };
+        assert_eq!(
+            Artifact::Journal.retention(&config, None, None).ok(),
+            Some(Retention {
+                max_age: Some(Duration::weeks(2)),
+                keep: Some(5),
+            })
+        );
+        assert_eq!(
+            Artifact::Journal
+                .retention(&config, Some(Duration::days(1)), Some(1))
+                .ok(),
+            Some(Retention {
+                max_age: Some(Duration::days(1)),
+                keep: Some(1),
+            })
+        );
+        assert_eq!(
+            Artifact::Backups.retention(&config, None, None).ok(),
+            Some(Retention {
+                max_age: Some(Duration::days(90)),
+                keep: None,
+            })
+        );
+        let invalid = GcConfig {
+            backups: config::RetentionConfig {
+                max_age: Some("-1d".into()),
+                keep: None,
+            },
+            ..GcConfig::default()
+        };
+        assert!(Artifact::Backups.retention(&invalid, None, None).is_err())
+    }
+
+    #[test]
+    fn retention_applies_age_and_count() {
+        let retention = Retention {
+            max_age: Some(Duration::days(7)),
+            keep: Some(2),
+        };
+        assert!(retention.retains(Utc::now(), 0));
+        assert!(!retention.retains(Utc::now(), 2));
+        assert!(!retention.retains(Utc::now() - Duration::days(8), 0));
+        assert!(Retention::default().retains(Utc::now() - Duration::weeks(100), 100));
+    }
+}
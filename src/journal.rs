@@ -0,0 +1,149 @@
+use crate::{error::Error, gc::Retention, lev_dir};
+use aws_sdk_lambda::types::FunctionConfiguration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+/// A record of a function configuration revision produced by lev
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub function: String,
+    pub revision_id: String,
+}
+
+fn path() -> PathBuf {
+    lev_dir().join("journal.jsonl")
+}
+
+/// Appends the revision of an updated configuration to the journal
+pub fn record(conf: &FunctionConfiguration) -> Result<(), Error> {
+    let (function, revision_id) = match (&conf.function_name, &conf.revision_id) {
+        (Some(function), Some(revision_id)) => (function.clone(), revision_id.clone()),
+        _ => return Ok(()),
+    };
+    let entry = Entry {
+        timestamp: Utc::now(),
+        function,
+        revision_id,
+    };
+    let path = path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(())
+}
+
+fn entries(contents: &str) -> impl Iterator<Item = Entry> + '_ {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+}
+
+/// True when lev produced the given revision of a function's configuration
+pub fn contains(
+    function: &str,
+    revision_id: &str,
+) -> bool {
+    fs::read_to_string(path())
+        .map(|contents| {
+            entries(&contents).any(|e| e.function == function && e.revision_id == revision_id)
+        })
+        .unwrap_or_default()
+}
+
+/// Entries retained by a policy, newest first per function
+fn retained(
+    mut entries: Vec<Entry>,
+    retention: &Retention,
+) -> Vec<Entry> {
+    entries.sort_by_key(|entry| Reverse(entry.timestamp));
+    let mut ranks = HashMap::new();
+    let mut kept = entries
+        .into_iter()
+        .filter(|entry| {
+            let rank = ranks.entry(entry.function.clone()).or_insert(0);
+            *rank += 1;
+            retention.retains(entry.timestamp, *rank - 1)
+        })
+        .collect::<Vec<_>>();
+    kept.reverse();
+    kept
+}
+
+/// Drops journal entries a retention policy doesn't keep, yielding
+/// `(removed, total)` entry counts
+pub fn prune(
+    retention: &Retention,
+    dry_run: bool,
+) -> Result<(usize, usize), Error> {
+    let path = path();
+    let contents = match fs::read_to_string(&path) {
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok((0, 0)),
+        other => other?,
+    };
+    let total = contents.lines().count();
+    let kept = retained(entries(&contents).collect(), retention);
+    let removed = total - kept.len();
+    if dry_run || removed == 0 {
+        return Ok((removed, total));
+    }
+    let mut lines = String::new();
+    for entry in kept {
+        lines.push_str(&serde_json::to_string(&entry)?);
+        lines.push('\n');
+    }
+    let staged = path.with_extension("jsonl.tmp");
+    fs::write(&staged, lines)?;
+    fs::rename(staged, path)?;
+    Ok((removed, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retained_keeps_newest_per_function() {
+        let entry = |function: &str, revision_id: &str, age: i64| Entry {
+            timestamp: Utc::now() - chrono::Duration::hours(age),
+            function: function.into(),
+            revision_id: revision_id.into(),
+        };
+        let kept = retained(
+            vec![
+                entry("foo", "a", 3),
+                entry("foo", "b", 2),
+                entry("bar", "c", 1),
+            ],
+            &Retention {
+                keep: Some(1),
+                ..Retention::default()
+            },
+        );
+        assert_eq!(
+            kept.into_iter().map(|e| e.revision_id).collect::<Vec<_>>(),
+            vec!["b".to_string(), "c".to_string()]
+        )
+    }
+
+    #[test]
+    fn entries_skip_unparsable_lines() {
+        let contents = concat!(
+            r#"{"timestamp":"2019-07-01T00:00:00Z","function":"foo","revision_id":"abc"}"#,
+            "\nnot json\n"
+        );
+        assert_eq!(
+            entries(contents).map(|e| e.revision_id).collect::<Vec<_>>(),
+            vec!["abc".to_string()]
+        )
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{error::Error, source, Env};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// An operation of an RFC 6902 JSON patch
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "op", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// The reference tokens of a JSON pointer, unescaped
+fn tokens(pointer: &str) -> Result<Vec<String>, Error> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| Error::InvalidPatch(format!("{} is not a JSON pointer", pointer)))?;
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// The index of an array element a token names, `len` standing for the end
+/// when `-` names it
+fn index(
+    token: &str,
+    len: usize,
+    pointer: &str,
+) -> Result<usize, Error> {
+    if token == "-" {
+        return Ok(len);
+    }
+    token
+        .parse()
+        .ok()
+        .filter(|_| token == "0" || !token.starts_with('0'))
+        .ok_or_else(|| Error::InvalidPatch(format!("{} does not index an array", pointer)))
+}
+
+/// The value a JSON pointer names, along with the token naming it within its
+/// parent
+fn parent<'a>(
+    doc: &'a mut Value,
+    pointer: &str,
+) -> Result<(&'a mut Value, String), Error> {
+    let mut tokens = tokens(pointer)?;
+    let last = tokens
+        .pop()
+        .ok_or_else(|| Error::InvalidPatch("the whole env can not be patched".into()))?;
+    let missing = || Error::InvalidPatch(format!("{} does not exist", pointer));
+    let mut parent = doc;
+    for token in tokens {
+        parent = match parent {
+            Value::Object(object) => object.get_mut(&token).ok_or_else(missing)?,
+            Value::Array(array) => {
+                let index = index(&token, array.len(), pointer)?;
+                array.get_mut(index).ok_or_else(missing)?
+            }
+            _ => return Err(missing()),
+        };
+    }
+    Ok((parent, last))
+}
+
+fn get(
+    doc: &mut Value,
+    pointer: &str,
+) -> Result<Value, Error> {
+    if pointer.is_empty() {
+        return Ok(doc.clone());
+    }
+    let missing = || Error::InvalidPatch(format!("{} does not exist", pointer));
+    let (parent, token) = parent(doc, pointer)?;
+    match parent {
+        Value::Object(object) => object.get(&token).cloned().ok_or_else(missing),
+        Value::Array(array) => {
+            let index = index(&token, array.len(), pointer)?;
+            array.get(index).cloned().ok_or_else(missing)
+        }
+        _ => Err(missing()),
+    }
+}
+
+fn add(
+    doc: &mut Value,
+    pointer: &str,
+    value: Value,
+) -> Result<(), Error> {
+    let (parent, token) = parent(doc, pointer)?;
+    match parent {
+        Value::Object(object) => {
+            object.insert(token, value);
+        }
+        Value::Array(array) => {
+            let index = index(&token, array.len(), pointer)?;
+            if index > array.len() {
+                return Err(Error::InvalidPatch(format!("{} is past the end", pointer)));
+            }
+            array.insert(index, value);
+        }
+        _ => return Err(Error::InvalidPatch(format!("{} does not exist", pointer))),
+    }
+    Ok(())
+}
+
+fn remove(
+    doc: &mut Value,
+    pointer: &str,
+) -> Result<Value, Error> {
+    let missing = || Error::InvalidPatch(format!("{} does not exist", pointer));
+    let (parent, token) = parent(doc, pointer)?;
+    match parent {
+        Value::Object(object) => object.remove(&token).ok_or_else(missing),
+        Value::Array(array) => {
+            let index = index(&token, array.len(), pointer)?;
+            if index >= array.len() {
+                return Err(missing());
+            }
+            Ok(array.remove(index))
+        }
+        _ => Err(missing()),
+    }
+}
+
+/// Applies the operations of a JSON patch in order, failing as a whole when
+/// any one of them fails
+fn apply(
+    doc: &mut Value,
+    operations: &[Operation],
+) -> Result<(), Error> {
+    for operation in operations {
+        match operation.clone() {
+            Operation::Add { path, value } => add(doc, &path, value)?,
+            Operation::Remove { path } => {
+                remove(doc, &path)?;
+            }
+            Operation::Replace { path, value } => {
+                remove(doc, &path)?;
+                add(doc, &path, value)?;
+            }
+            Operation::Move { from, path } => {
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err(Error::InvalidPatch(format!(
+                        "{} can not be moved into itself",
+                        from
+                    )));
+                }
+                let value = remove(doc, &from)?;
+                add(doc, &path, value)?;
+            }
+            Operation::Copy { from, path } => {
+                let value = get(doc, &from)?;
+                add(doc, &path, value)?;
+            }
+            Operation::Test { path, value } => {
+                if get(doc, &path)? != value {
+                    return Err(Error::PatchTestFailed(path));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The operations of a JSON patch read from a local path, an
+/// s3://bucket/key or an https:// url
+pub async fn read(source: &str) -> Result<Vec<Operation>, Error> {
+    serde_json::from_slice(&source::read(source).await?)
+        .map_err(|err| Error::InvalidPatch(err.to_string()))
+}
+
+/// A function's env with a JSON patch applied to it, as though a JSON object
+/// of its vars. The patched env must still have strings for values
+pub fn patched(
+    env: &Env,
+    operations: &[Operation],
+) -> Result<Env, Error> {
+    let mut doc = Value::Object(
+        env.iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect::<Map<_, _>>(),
+    );
+    apply(&mut doc, operations)?;
+    match doc {
+        Value::Object(object) => object
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(value) => Ok((key, value)),
+                _ => Err(Error::InvalidPatch(format!("{} must be a string", key))),
+            })
+            .collect(),
+        _ => Err(Error::InvalidPatch("the env must stay an object".into())),
+    }
+}
+
+/// Merges `patch` into `target` as RFC 7386 has it: objects merge key by key
+/// all the way down, `null` removes a key, and anything else replaces what
+/// was there
+fn merge(
+    target: &mut Value,
+    patch: Value,
+) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => {
+            *target = patch;
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(&key);
+            } else {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// The value a JSON valued var of a function holds once `patch` is merged
+/// into its `current` value, or into nothing when it is unset
+pub fn merged(
+    function: &str,
+    key: &str,
+    current: Option<&String>,
+    patch: Value,
+) -> Result<String, Error> {
+    let mut value = match current {
+        Some(current) => serde_json::from_str(current)
+            .map_err(|_| Error::NotJson(function.into(), key.into()))?,
+        None => Value::Null,
+    };
+    merge(&mut value, patch);
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+    use serde_json::json;
+
+    fn operations(patch: Value) -> Vec<Operation> {
+        serde_json::from_value(patch).unwrap()
+    }
+
+    #[test]
+    fn patched_applies_every_operation() {
+        let patch = operations(json!([
+            {"op": "test", "path": "/LOG_LEVEL", "value": "info"},
+            {"op": "replace", "path": "/LOG_LEVEL", "value": "debug"},
+            {"op": "add", "path": "/FEATURE_X", "value": "on"},
+            {"op": "copy", "from": "/FEATURE_X", "path": "/FEATURE_Y"},
+            {"op": "move", "from": "/OLD_URL", "path": "/API~1URL"},
+            {"op": "remove", "path": "/LEGACY"}
+        ]));
+        assert_eq!(
+            patched(
+                &test_env(&[
+                    ("LOG_LEVEL", "info"),
+                    ("OLD_URL", "https://api.example.com"),
+                    ("LEGACY", "1")
+                ]),
+                &patch
+            )
+            .ok(),
+            Some(test_env(&[
+                ("LOG_LEVEL", "debug"),
+                ("FEATURE_X", "on"),
+                ("FEATURE_Y", "on"),
+                ("API/URL", "https://api.example.com")
+            ]))
+        )
+    }
+
+    #[test]
+    fn patched_refuses_failed_tests_and_non_strings() {
+        let current = test_env(&[("LOG_LEVEL", "info")]);
+        match patched(
+            &current,
+            &operations(json!([{"op": "test", "path": "/LOG_LEVEL", "value": "warn"}])),
+        ) {
+            Err(Error::PatchTestFailed(path)) => assert_eq!(path, "/LOG_LEVEL"),
+            other => panic!("expected a failed test, got {:?}", other),
+        }
+        match patched(
+            &current,
+            &operations(json!([{"op": "add", "path": "/RETRIES", "value": 3}])),
+        ) {
+            Err(Error::InvalidPatch(_)) => (),
+            other => panic!("expected an invalid patch, got {:?}", other),
+        }
+        match patched(
+            &current,
+            &operations(json!([{"op": "remove", "path": "/MISSING"}])),
+        ) {
+            Err(Error::InvalidPatch(_)) => (),
+            other => panic!("expected an invalid patch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merged_merges_objects_deeply() {
+        let current = json!({"feature": {"x": false, "y": true}, "limit": 10}).to_string();
+        assert_eq!(
+            merged(
+                "foo",
+                "CONFIG",
+                Some(&current),
+                json!({"feature": {"x": true}, "limit": null})
+            )
+            .ok()
+            .map(|value| serde_json::from_str::<Value>(&value).unwrap()),
+            Some(json!({"feature": {"x": true, "y": true}}))
+        );
+        assert_eq!(
+            merged("foo", "CONFIG", None, json!({"a": {"b": null, "c": 1}})).ok(),
+            Some(r#"{"a":{"c":1}}"#.into())
+        );
+        match merged("foo", "CONFIG", Some(&"plain".to_string()), json!({})) {
+            Err(Error::NotJson(function, key)) => {
+                assert_eq!((function, key), ("foo".into(), "CONFIG".into()))
+            }
+            other => panic!("expected a not json error, got {:?}", other),
+        }
+    }
+}
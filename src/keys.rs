@@ -0,0 +1,319 @@
+use crate::{error::Error, Env};
+use std::sync::Mutex;
+
+/// Keys lambda defines itself and refuses to let envs set
+const RESERVED: &[&str] = &[
+    "_HANDLER",
+    "_X_AMZN_TRACE_ID",
+    "AWS_ACCESS_KEY",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_DEFAULT_REGION",
+    "AWS_EXECUTION_ENV",
+    "AWS_LAMBDA_FUNCTION_MEMORY_SIZE",
+    "AWS_LAMBDA_FUNCTION_NAME",
+    "AWS_LAMBDA_FUNCTION_VERSION",
+    "AWS_LAMBDA_INITIALIZATION_TYPE",
+    "AWS_LAMBDA_LOG_GROUP_NAME",
+    "AWS_LAMBDA_LOG_STREAM_NAME",
+    "AWS_LAMBDA_RUNTIME_API",
+    "AWS_REGION",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "LAMBDA_RUNTIME_DIR",
+    "LAMBDA_TASK_ROOT",
+];
+
+/// Keys envs may set, but which change how the runtime starts or behaves
+const RUNTIME: &[&str] = &[
+    "AWS_LAMBDA_DOTNET_PREJIT",
+    "AWS_LAMBDA_EXEC_WRAPPER",
+    "AWS_XRAY_CONTEXT_MISSING",
+    "AWS_XRAY_DAEMON_ADDRESS",
+    "GEM_PATH",
+    "LANG",
+    "LD_LIBRARY_PATH",
+    "NODE_PATH",
+    "PATH",
+    "PYTHONPATH",
+    "TZ",
+];
+
+/// Whether lambda accepts a key: a letter followed by at least one letter,
+/// digit or underscore
+pub fn valid(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && key.len() > 1
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A key with the characters lambda refuses, like `-` or `.`, replaced by
+/// underscores. Keys which don't start with a letter are left for
+/// `check_names` to refuse
+pub fn sanitize(key: &str) -> String {
+    key.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Refuses keys lambda would, naming all of them at once
+pub fn check_names<'a, I>(keys: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut invalid = keys
+        .into_iter()
+        .filter(|key| !valid(key))
+        .map(|key| format!("`{}`", key))
+        .collect::<Vec<_>>();
+    invalid.sort_unstable();
+    invalid.dedup();
+    match invalid {
+        invalid if invalid.is_empty() => Ok(()),
+        invalid => Err(Error::InvalidKeys(invalid.join(", "))),
+    }
+}
+
+fn among<'a, I>(
+    keys: I,
+    names: &[&str],
+) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut found = keys
+        .into_iter()
+        .map(String::as_str)
+        .filter(|key| names.contains(key))
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    found.dedup();
+    found
+}
+
+/// Refuses keys lambda reserves for itself
+pub fn check_reserved<'a, I>(keys: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    match among(keys, RESERVED) {
+        reserved if reserved.is_empty() => Ok(()),
+        reserved => Err(Error::ReservedKeys(reserved.join(", "))),
+    }
+}
+
+/// Refuses keys which change how the runtime behaves, unless `force`d
+pub fn check_runtime<'a, I>(
+    keys: I,
+    force: bool,
+) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    match among(keys, RUNTIME) {
+        runtime if runtime.is_empty() || force => Ok(()),
+        runtime => Err(Error::RuntimeKeys(runtime.join(", "))),
+    }
+}
+
+/// Keys commands refuse to remove without `--force`
+static PROTECTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Has commands which remove vars refuse to remove these without `--force`
+pub fn set_protected(keys: Vec<String>) {
+    *PROTECTED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = keys
+}
+
+/// Refuses updates of a function's env which remove protected keys, unless
+/// `force`d
+pub fn check_protected(
+    function: &str,
+    current: &Env,
+    updated: &Env,
+    force: bool,
+) -> Result<(), Error> {
+    if force {
+        return Ok(());
+    }
+    let protected = PROTECTED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let removed = current
+        .keys()
+        .filter(|key| !updated.contains_key(*key))
+        .collect::<Vec<_>>();
+    match among(
+        removed,
+        &protected.iter().map(String::as_str).collect::<Vec<_>>(),
+    ) {
+        removed if removed.is_empty() => Ok(()),
+        removed => Err(Error::ProtectedKeys(function.into(), removed.join(", "))),
+    }
+}
+
+/// How an env falls short of the protected keys, every one of which it is
+/// expected to hold a value for
+pub fn unprotected(env: &Env) -> Vec<String> {
+    let protected = PROTECTED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    protected
+        .into_iter()
+        .filter_map(|key| match env.get(&key) {
+            None => Some(format!("missing protected {}", key)),
+            Some(value) if value.is_empty() => Some(format!("empty protected {}", key)),
+            Some(_) => None,
+        })
+        .collect()
+}
+
+/// A function's env with every var whose name starts with `old` renamed to
+/// start with `new` instead, refusing renames onto vars it already has
+pub fn rename_prefix(
+    function: &str,
+    env: &Env,
+    old: &str,
+    new: &str,
+) -> Result<Env, Error> {
+    let (renamed, kept): (Env, Env) = env
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .partition(|(key, _)| key.starts_with(old));
+    let renamed = renamed
+        .into_iter()
+        .map(|(key, value)| (format!("{}{}", new, &key[old.len()..]), value))
+        .collect::<Env>();
+    let mut taken = renamed
+        .keys()
+        .filter(|key| kept.contains_key(*key))
+        .map(|key| format!("`{}`", key))
+        .collect::<Vec<_>>();
+    if !taken.is_empty() {
+        taken.sort_unstable();
+        return Err(Error::RenameCollision(function.into(), taken.join(", ")));
+    }
+    Ok(kept.into_iter().chain(renamed).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    #[test]
+    fn check_protected_refuses_removals_unless_forced() {
+        set_protected(vec!["DATABASE_URL".into()]);
+        let current = test_env(&[("DATABASE_URL", "postgres://db"), ("DEBUG", "1")]);
+        assert!(
+            check_protected("foo", &current, &test_env(&[("DATABASE_URL", "x")]), false).is_ok()
+        );
+        match check_protected("foo", &current, &test_env(&[("DEBUG", "1")]), false) {
+            Err(Error::ProtectedKeys(function, keys)) => {
+                assert_eq!((function.as_str(), keys.as_str()), ("foo", "DATABASE_URL"))
+            }
+            other => panic!("expected a protected keys error, got {:?}", other),
+        }
+        assert!(check_protected("foo", &current, &Env::new(), true).is_ok());
+        assert!(unprotected(&current).is_empty());
+        assert_eq!(
+            unprotected(&test_env(&[("DATABASE_URL", "")])),
+            vec!["empty protected DATABASE_URL".to_string()]
+        );
+        assert_eq!(
+            unprotected(&Env::new()),
+            vec!["missing protected DATABASE_URL".to_string()]
+        )
+    }
+
+    #[test]
+    fn rename_prefix_renames_whole_namespaces() {
+        assert_eq!(
+            rename_prefix(
+                "foo",
+                &test_env(&[
+                    ("ACME_URL", "a"),
+                    ("ACME_TOKEN", "b"),
+                    ("LOG_LEVEL", "info")
+                ]),
+                "ACME_",
+                "ROADRUNNER_"
+            )
+            .ok(),
+            Some(test_env(&[
+                ("ROADRUNNER_URL", "a"),
+                ("ROADRUNNER_TOKEN", "b"),
+                ("LOG_LEVEL", "info")
+            ]))
+        );
+        match rename_prefix(
+            "foo",
+            &test_env(&[("OLD_URL", "a"), ("NEW_URL", "b")]),
+            "OLD_",
+            "NEW_",
+        ) {
+            Err(Error::RenameCollision(function, keys)) => {
+                assert_eq!((function.as_str(), keys.as_str()), ("foo", "`NEW_URL`"))
+            }
+            other => panic!("expected a rename collision, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn valid_follows_lambdas_rule() {
+        assert!(valid("LOG_LEVEL"));
+        assert!(valid("a1"));
+        assert!(!valid("A"));
+        assert!(!valid("1A"));
+        assert!(!valid("_A"));
+        assert!(!valid("LOG-LEVEL"));
+        assert!(!valid("LÖG"))
+    }
+
+    #[test]
+    fn sanitize_replaces_refused_characters() {
+        assert_eq!(sanitize("log-level"), "log_level");
+        assert_eq!(sanitize(" app.db url "), "app_db_url");
+        assert!(!valid(&sanitize("1A")))
+    }
+
+    #[test]
+    fn check_names_names_every_invalid_key() {
+        let keys = vec!["B-1".to_string(), "OK".into(), "A.B".into()];
+        match check_names(&keys) {
+            Err(Error::InvalidKeys(keys)) => assert_eq!(keys, "`A.B`, `B-1`"),
+            other => panic!("expected an invalid keys error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_reserved_names_every_reserved_key() {
+        let keys = vec![
+            "AWS_REGION".to_string(),
+            "LOG_LEVEL".into(),
+            "_HANDLER".into(),
+        ];
+        match check_reserved(&keys) {
+            Err(Error::ReservedKeys(keys)) => assert_eq!(keys, "AWS_REGION, _HANDLER"),
+            other => panic!("expected a reserved keys error, got {:?}", other),
+        }
+        assert!(check_reserved(&["LOG_LEVEL".to_string()]).is_ok())
+    }
+
+    #[test]
+    fn check_runtime_can_be_forced() {
+        let keys = vec!["TZ".to_string()];
+        assert!(check_runtime(&keys, false).is_err());
+        assert!(check_runtime(&keys, true).is_ok())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    aws::{self, KmsClient},
+    error::Error,
+    Env,
+};
+use aws_sdk_kms::primitives::Blob;
+use futures::future;
+
+/// Prefix marking values which hold base64 encoded KMS ciphertext
+pub const PREFIX: &str = "kms:";
+
+/// Encrypts a value with a KMS key, yielding its ciphertext base64 encoded
+/// behind the `kms:` prefix
+pub async fn encrypt(
+    kms: KmsClient,
+    key_id: String,
+    plaintext: String,
+) -> Result<String, Error> {
+    let request = kms
+        .encrypt()
+        .key_id(key_id)
+        .plaintext(Blob::new(plaintext.into_bytes()));
+    let encrypted = aws::retry(|| request.clone().send()).await?;
+    Ok(format!(
+        "{}{}",
+        PREFIX,
+        base64::encode(
+            &encrypted
+                .ciphertext_blob
+                .map(Blob::into_inner)
+                .unwrap_or_default()
+        )
+    ))
+}
+
+/// Decrypts the ciphertext of a `kms:` prefixed value
+async fn decrypt(
+    kms: KmsClient,
+    key: String,
+    ciphertext: &str,
+) -> Result<String, Error> {
+    let blob = base64::decode(ciphertext).map_err(|_| Error::InvalidCiphertext(key))?;
+    let request = kms.decrypt().ciphertext_blob(Blob::new(blob));
+    let decrypted = aws::retry(|| request.clone().send()).await?;
+    let plaintext = decrypted.plaintext.map(Blob::into_inner);
+    Ok(String::from_utf8_lossy(&plaintext.unwrap_or_default()).into_owned())
+}
+
+/// Decrypts every `kms:` prefixed value of a function's env. Values which
+/// fail to decrypt are left as they are, with a warning
+pub async fn decrypt_env(
+    kms: KmsClient,
+    function: String,
+    mut env: Env,
+) -> Result<Env, Error> {
+    let decrypted = future::join_all(env.iter().filter_map(|(key, value)| {
+        let ciphertext = value.strip_prefix(PREFIX)?;
+        let (kms, function) = (&kms, &function);
+        Some(async move {
+            match decrypt(kms.clone(), key.clone(), ciphertext).await {
+                Ok(plaintext) => Some((key.clone(), plaintext)),
+                Err(err) => {
+                    errln!("{}: failed to decrypt {}: {}", function, key, err);
+                    None
+                }
+            }
+        })
+    }))
+    .await;
+    env.extend(decrypted.into_iter().flatten().collect::<Vec<_>>());
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn decrypt_env_leaves_plain_values_alone() {
+        let mut env = Env::new();
+        env.insert("A".into(), "plain".into());
+        assert_eq!(
+            decrypt_env(aws::kms_client().await, "foo".into(), env.clone())
+                .await
+                .ok(),
+            Some(env)
+        )
+    }
+
+    #[tokio::test]
+    async fn decrypt_rejects_invalid_base64() {
+        match decrypt(aws::kms_client().await, "A".into(), "not base64!").await {
+            Err(Error::InvalidCiphertext(key)) => assert_eq!(key, "A"),
+            other => panic!(
+                "expected an invalid ciphertext error, got {:?}",
+                other.map(drop)
+            ),
+        }
+    }
+}
@@ -0,0 +1,786 @@
+//! Manages the env vars of AWS Lambda functions: reading them, setting and
+//! unsetting them, diffing them and syncing them with env files. The `lev`
+//! command line is a thin layer over what this crate exposes, which other
+//! tools may embed as well
+
+// aws errors carry the http responses they failed with, which makes ours large
+#![allow(clippy::result_large_err)]
+
+use aws::{LambdaClient, SdkError};
+use aws_sdk_lambda::{
+    operation::get_function_configuration::GetFunctionConfigurationError,
+    types::FunctionConfiguration,
+};
+use futures::{future, stream, StreamExt};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error as StdError,
+    future::Future,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Like `print!`, but captured while serving a daemon client
+#[macro_export]
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::console::out(format_args!($($arg)*))
+    };
+}
+
+/// Like `println!`, but captured while serving a daemon client
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::out!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::out!("{}\n", format_args!($($arg)*))
+    };
+}
+
+/// Like `eprintln!`, but captured while serving a daemon client
+#[macro_export]
+macro_rules! errln {
+    ($($arg:tt)*) => {
+        $crate::console::err(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+pub mod alias;
+pub mod audit;
+pub mod aws;
+pub mod backup;
+pub mod config;
+pub mod console;
+pub mod copy;
+pub mod daemon;
+pub mod diff;
+pub mod ecs;
+pub mod edit;
+pub mod error;
+pub mod functions;
+pub mod gc;
+pub mod journal;
+pub mod json;
+pub mod keys;
+pub mod kms;
+pub mod manifest;
+pub mod migrate;
+pub mod notify;
+pub mod plugin;
+pub mod policy;
+pub mod redact;
+pub mod render;
+pub mod resolve;
+pub mod sam;
+pub mod serverless;
+pub mod source;
+pub mod status;
+pub mod store;
+pub mod suggest;
+pub mod summary;
+pub mod sync;
+pub mod target;
+pub mod template;
+pub mod trace;
+pub mod tui;
+pub mod value;
+pub mod verify;
+pub mod versions;
+pub mod watch;
+pub mod whoami;
+
+use crate::{alias::Release, error::Error, store::LambdaEnvStore};
+
+pub fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn std::error::Error>>
+where
+    T: FromStr,
+    T::Err: StdError + 'static,
+    U: FromStr,
+    U::Err: StdError + 'static,
+{
+    let pos = s
+        .find('=')
+        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
+    Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
+}
+
+/// The vars of a function's env, by name
+pub type Env = HashMap<String, String>;
+
+/// Directory holding lev's local state
+pub fn lev_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(".lev")
+}
+
+pub fn env(conf: FunctionConfiguration) -> Env {
+    conf.environment
+        .map(|env| env.variables.unwrap_or_default())
+        .unwrap_or_default()
+}
+
+pub async fn configuration<S, F>(
+    lambda: S,
+    function: F,
+) -> Result<FunctionConfiguration, SdkError<GetFunctionConfigurationError>>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    let function = function.into();
+    aws::retry(|| lambda.get_configuration(function.clone())).await
+}
+
+pub async fn get<S, F>(
+    lambda: S,
+    function: F,
+) -> Result<Env, SdkError<GetFunctionConfigurationError>>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    configuration(lambda, function).await.map(env)
+}
+
+/// The env of a function, or of the version an alias or version `qualifier`
+/// names, with its kms: prefixed values decrypted unless `no_decrypt` is set
+pub async fn fetch(
+    lambda: LambdaClient,
+    function: String,
+    qualifier: Option<String>,
+    no_decrypt: bool,
+) -> Result<Env, Error> {
+    let (qualified, resolved) =
+        alias::qualified(lambda.clone(), function.clone(), qualifier).await?;
+    if let Some(r) = resolved {
+        errln!(
+            "{}: alias {} points at version {}",
+            function,
+            r.alias,
+            r.version
+        );
+    }
+    let env = get(lambda, qualified).await?;
+    if no_decrypt {
+        return Ok(env);
+    }
+    kms::decrypt_env(aws::kms_client().await, function, env).await
+}
+
+/// Replaces a function's env, snapshotting the `previous` one first.
+/// Updates removing protected vars are refused unless `force`d
+pub async fn update<S, F>(
+    lambda: S,
+    function: F,
+    previous: &Env,
+    updated: Env,
+    force: bool,
+) -> Result<Env, Error>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    update_with(lambda, function.into(), previous, updated, None, force).await
+}
+
+/// Like `update`, also setting the KMS key the env is encrypted with when
+/// one is given
+pub async fn update_with<S>(
+    lambda: S,
+    function: String,
+    previous: &Env,
+    updated: Env,
+    kms_key_arn: Option<String>,
+    force: bool,
+) -> Result<Env, Error>
+where
+    S: LambdaEnvStore,
+{
+    keys::check_protected(&function, previous, &updated, force)?;
+    keys::check_names(updated.keys())?;
+    keys::check_reserved(updated.keys())?;
+    policy::check(&function, &updated)?;
+    // lambda's own error for this doesn't say what's too large, or by how much
+    if let Some((size, offenders)) = summary::excess(previous, &updated) {
+        return Err(Error::EnvTooLarge(
+            function,
+            size,
+            summary::ENV_LIMIT,
+            offenders.join(", "),
+        ));
+    }
+    if let Err(err) = lambda.snapshot(function.clone(), previous.clone()).await {
+        if !backup::allows_unsnapshotted() {
+            return Err(Error::SnapshotFailed(function, Box::new(err)));
+        }
+        errln!("failed to snapshot env of {}: {}", function, err);
+    }
+    let conf = aws::retry(|| {
+        lambda.update_configuration(function.clone(), updated.clone(), kms_key_arn.clone())
+    })
+    .await?;
+    if let Err(err) = journal::record(&conf) {
+        errln!("failed to journal update: {}", err);
+    }
+    let revision_id = conf.revision_id.clone();
+    let after = env(conf);
+    if let Err(err) =
+        audit::record(function.clone(), revision_id, &diff::diff(previous, &after)).await
+    {
+        errln!("failed to audit update of {}: {}", function, err);
+    }
+    Ok(after)
+}
+
+/// Updates a function's env unless it already matches, sparing the function
+/// a new revision. Yields the changes made
+pub async fn changed<S>(
+    lambda: S,
+    function: String,
+    current: Env,
+    updated: Env,
+    force: bool,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+{
+    if current == updated {
+        return Ok(Vec::new());
+    }
+    let after = update(lambda, function, &current, updated, force).await?;
+    Ok(diff::diff(&current, &after))
+}
+
+pub async fn set_env<S, F>(
+    lambda: S,
+    function: F,
+    vars: Vec<(String, String)>,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    let function = function.into();
+    let current = get(lambda.clone(), function.clone()).await?;
+    let updated = current.clone().into_iter().chain(vars).collect();
+    changed(lambda, function, current, updated, false).await
+}
+
+/// Sets vars on a function: `vars` as they were given, `templates` with
+/// their `{NAME}` references to its current vars filled in before `registry`
+/// resolves them, and JSON valued vars with `json_merges` merged into them.
+/// With a KMS key to encrypt the env with, the function is updated even when
+/// its vars already match, so the key takes effect
+pub async fn assign<S>(
+    lambda: S,
+    function: String,
+    vars: Vec<(String, String)>,
+    templates: Vec<(String, String)>,
+    json_merges: Vec<(String, serde_json::Value)>,
+    kms_key_arn: Option<String>,
+    registry: &resolve::Registry,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+{
+    let current = get(lambda.clone(), function.clone()).await?;
+    let templates = templates
+        .into_iter()
+        .map(|(key, value)| {
+            let value = template::referencing(&value, &current);
+            (key, value)
+        })
+        .collect::<Vec<_>>();
+    let templates = registry.resolve(templates).await?;
+    let mut updated = current
+        .clone()
+        .into_iter()
+        .chain(vars)
+        .chain(templates)
+        .collect::<Env>();
+    for (key, patch) in json_merges {
+        let merged = json::merged(&function, &key, updated.get(&key), patch)?;
+        updated.insert(key, merged);
+    }
+    match kms_key_arn {
+        None => changed(lambda, function, current, updated, false).await,
+        Some(kms_key_arn) => {
+            let after = update_with(
+                lambda,
+                function,
+                &current,
+                updated,
+                Some(kms_key_arn),
+                false,
+            )
+            .await?;
+            Ok(diff::diff(&current, &after))
+        }
+    }
+}
+
+/// Unsets the vars `names` names, and those starting with any of `prefixes`,
+/// refusing to unset protected vars unless `force`d
+pub async fn unset_env<S, F>(
+    lambda: S,
+    function: F,
+    names: Vec<String>,
+    prefixes: Vec<String>,
+    force: bool,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    let function = function.into();
+    let current = get(lambda.clone(), function.clone()).await?;
+    let updated = current
+        .iter()
+        .filter(|(k, _)| !names.contains(k))
+        .filter(|(k, _)| !prefixes.iter().any(|prefix| k.starts_with(prefix.as_str())))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    changed(lambda, function, current, updated, force).await
+}
+
+/// Waits for an update to complete, if requested
+pub async fn settled<T>(
+    function: String,
+    changes: T,
+    wait: bool,
+) -> Result<T, Error> {
+    if wait {
+        status::wait(function).await?;
+    }
+    Ok(changes)
+}
+
+/// Follows an update with the requested release steps, reporting on them
+pub async fn released<T>(
+    lambda: LambdaClient,
+    function: String,
+    release: Release,
+    changes: T,
+) -> Result<T, Error> {
+    for note in alias::release(lambda, function.clone(), release).await? {
+        errln!("{}: {}", function, note);
+    }
+    Ok(changes)
+}
+
+/// Applies `f` to each function, running at most `parallel` at a time,
+/// pairing results with function names in their original order. Every
+/// function is run against, failing with the first failure once all are
+pub async fn each<F, R, T>(
+    functions: Vec<String>,
+    parallel: usize,
+    f: F,
+) -> Result<Vec<(String, T)>, Error>
+where
+    F: FnMut(String) -> R,
+    R: Future<Output = Result<T, Error>>,
+{
+    each_settled(functions, parallel, f)
+        .await
+        .into_iter()
+        .map(|(function, result)| result.map(|t| (function, t)))
+        .collect()
+}
+
+/// Like `each`, pairing every function with its own result rather than
+/// failing as a whole, so one failing function doesn't hide what became of
+/// the rest
+pub async fn each_settled<F, R, T>(
+    functions: Vec<String>,
+    parallel: usize,
+    f: F,
+) -> Vec<(String, Result<T, Error>)>
+where
+    F: FnMut(String) -> R,
+    R: Future<Output = Result<T, Error>>,
+{
+    // each account and region gets a pool of its own, with clients talking to
+    // its region and a limiter of its own, so one that is slow or throttled
+    // holds up only the functions which live in it
+    let mut pools = BTreeMap::<_, Vec<_>>::new();
+    for (i, function) in functions.into_iter().enumerate() {
+        pools
+            .entry(target::partition(&function))
+            .or_default()
+            .push((i, function));
+    }
+    let f = Mutex::new(f);
+    let f = &f;
+    let pooled = pools.into_iter().map(|((account, region), functions)| {
+        let pool = stream::iter(functions)
+            .map(move |(i, function)| {
+                let mut f = f.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let result = f(function.clone());
+                async move { (i, (function, result.await)) }
+            })
+            .buffer_unordered(parallel)
+            .collect::<Vec<_>>();
+        aws::in_partition(account, region, pool)
+    });
+    let mut results = future::join_all(pooled)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The env of a function
+pub async fn get_env<S, F>(
+    lambda: S,
+    function: F,
+) -> Result<Env, Error>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    Ok(get(lambda, function).await?)
+}
+
+/// The changes which would turn a function's env into `desired`, leaving
+/// the function as it is
+pub async fn diff_env<S, F>(
+    lambda: S,
+    function: F,
+    desired: Env,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+    F: Into<String>,
+{
+    let current = get_env(lambda, function).await?;
+    Ok(diff::diff(&current, &desired))
+}
+
+/// Syncs a function's env with `synced`, as `sync::layered` reads it from env
+/// files. Synced vars are merged over the current ones, or with `prune`
+/// replace them, refusing to remove protected vars unless `force`d. With
+/// `plan`, yields the changes syncing would make without making them
+pub async fn sync_env<S>(
+    lambda: S,
+    function: String,
+    synced: Env,
+    prune: bool,
+    force: bool,
+    plan: bool,
+) -> Result<Vec<diff::Change>, Error>
+where
+    S: LambdaEnvStore,
+{
+    let current = get_env(lambda.clone(), function.clone()).await?;
+    let synced = if prune {
+        synced
+    } else {
+        current.clone().into_iter().chain(synced).collect()
+    };
+    if plan {
+        keys::check_protected(&function, &current, &synced, force)?;
+        return Ok(diff::diff(&current, &synced));
+    }
+    changed(lambda, function, current, synced, force).await
+}
+
+/// An env holding these vars, for tests
+#[cfg(test)]
+pub(crate) fn test_env(vars: &[(&str, &str)]) -> Env {
+    vars.iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use aws_sdk_lambda::types::EnvironmentResponse;
+    use std::future;
+
+    #[test]
+    fn env_extracts_from_empty_config() {
+        assert_eq!(
+            env(FunctionConfiguration::builder().build()),
+            Default::default()
+        )
+    }
+
+    #[test]
+    fn env_extracts_from_nonempty_config() {
+        let mut vars = HashMap::new();
+        vars.insert("foo".to_string(), "bar".to_string());
+        assert_eq!(
+            env(FunctionConfiguration::builder()
+                .environment(
+                    EnvironmentResponse::builder()
+                        .set_variables(Some(vars.clone()))
+                        .build()
+                )
+                .build()),
+            vars
+        )
+    }
+
+    #[tokio::test]
+    async fn changed_skips_matching_envs() {
+        let mut vars = HashMap::new();
+        vars.insert("foo".to_string(), "bar".to_string());
+        let lambda = MemoryStore::default();
+        assert_eq!(
+            changed(lambda, "foo".into(), vars.clone(), vars, false)
+                .await
+                .ok(),
+            Some(Vec::new())
+        )
+    }
+
+    #[tokio::test]
+    async fn assign_fills_in_references_of_templates_only() {
+        let store = MemoryStore::default().with("lev-test-assign", test_env(&[("STAGE", "prod")]));
+        let changes = assign(
+            store.clone(),
+            "lev-test-assign".into(),
+            vec![("CERT".into(), "{STAGE}".into())],
+            vec![
+                ("URL".into(), "https://{STAGE}.example.com".into()),
+                ("LITERAL".into(), "{{STAGE}}".into()),
+            ],
+            Vec::new(),
+            None,
+            &resolve::Registry::default(),
+        )
+        .await;
+        assert!(changes.is_ok());
+        assert_eq!(
+            store.env("lev-test-assign"),
+            Some(test_env(&[
+                ("STAGE", "prod"),
+                ("CERT", "{STAGE}"),
+                ("URL", "https://prod.example.com"),
+                ("LITERAL", "{STAGE}")
+            ]))
+        )
+    }
+
+    #[tokio::test]
+    async fn update_refuses_changes_it_can_not_snapshot() {
+        let current = test_env(&[("LOG_LEVEL", "info")]);
+        let store = MemoryStore::default()
+            .with("lev-test-unsnapshotted", current.clone())
+            .failing_snapshots();
+        let updated = test_env(&[("LOG_LEVEL", "debug")]);
+        match update(
+            store.clone(),
+            "lev-test-unsnapshotted",
+            &current,
+            updated,
+            false,
+        )
+        .await
+        {
+            Err(Error::SnapshotFailed(function, _)) => {
+                assert_eq!(function, "lev-test-unsnapshotted")
+            }
+            other => panic!("expected a snapshot failed error, got {:?}", other),
+        }
+        assert_eq!(store.env("lev-test-unsnapshotted"), Some(current))
+    }
+
+    #[tokio::test]
+    async fn update_refuses_removing_protected_vars_unless_forced() {
+        keys::set_protected(vec!["DATABASE_URL".into()]);
+        let current = test_env(&[("DATABASE_URL", "postgres://db"), ("DEBUG", "1")]);
+        let store = MemoryStore::default().with("lev-test-protected", current.clone());
+        let updated = test_env(&[("DEBUG", "1")]);
+        match update(
+            store.clone(),
+            "lev-test-protected",
+            &current,
+            updated.clone(),
+            false,
+        )
+        .await
+        {
+            Err(Error::ProtectedKeys(function, keys)) => {
+                assert_eq!(
+                    (function.as_str(), keys.as_str()),
+                    ("lev-test-protected", "DATABASE_URL")
+                )
+            }
+            other => panic!("expected a protected keys error, got {:?}", other),
+        }
+        assert_eq!(store.env("lev-test-protected"), Some(current.clone()));
+        assert_eq!(
+            update(
+                store.clone(),
+                "lev-test-protected",
+                &current,
+                updated.clone(),
+                true
+            )
+            .await
+            .ok(),
+            Some(updated.clone())
+        );
+        assert_eq!(store.env("lev-test-protected"), Some(updated))
+    }
+
+    #[tokio::test]
+    async fn set_env_and_unset_env_update_the_store() {
+        let store = MemoryStore::default().with("lev-test-set", test_env(&[("LOG_LEVEL", "info")]));
+        assert_eq!(
+            set_env(
+                store.clone(),
+                "lev-test-set",
+                vec![("LOG_LEVEL".into(), "debug".into())]
+            )
+            .await
+            .ok(),
+            Some(vec![diff::Change::Changed(
+                "LOG_LEVEL".into(),
+                "info".into(),
+                "debug".into()
+            )])
+        );
+        assert_eq!(
+            unset_env(
+                store.clone(),
+                "lev-test-set",
+                vec!["LOG_LEVEL".into()],
+                Vec::new(),
+                false
+            )
+            .await
+            .ok(),
+            Some(vec![diff::Change::Removed(
+                "LOG_LEVEL".into(),
+                "debug".into()
+            )])
+        );
+        assert_eq!(store.env("lev-test-set"), Some(Env::new()));
+        match get_env(store, "lev-test-missing").await {
+            Err(Error::GetConfig(_)) => (),
+            other => panic!("expected a missing function, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_env_merges_unless_pruning() {
+        let store = MemoryStore::default().with(
+            "lev-test-sync",
+            test_env(&[("LOG_LEVEL", "info"), ("REGION", "us-east-1")]),
+        );
+        let synced = || test_env(&[("LOG_LEVEL", "warn")]);
+        assert_eq!(
+            sync_env(
+                store.clone(),
+                "lev-test-sync".into(),
+                synced(),
+                true,
+                false,
+                true
+            )
+            .await
+            .ok()
+            .map(|changes| changes.len()),
+            Some(2)
+        );
+        assert_eq!(store.env("lev-test-sync").map(|env| env.len()), Some(2));
+        sync_env(
+            store.clone(),
+            "lev-test-sync".into(),
+            synced(),
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            store.env("lev-test-sync"),
+            Some(test_env(&[("LOG_LEVEL", "warn"), ("REGION", "us-east-1")]))
+        )
+    }
+
+    #[tokio::test]
+    async fn each_preserves_order_across_pools() {
+        let functions = vec![
+            "arn:aws:lambda:eu-west-1:123456789012:function:a".to_string(),
+            "b".to_string(),
+            "arn:aws:lambda:us-east-1:123456789012:function:c".to_string(),
+            "d".to_string(),
+        ];
+        let results = each(functions.clone(), 1, |function| {
+            future::ready(Ok::<_, Error>(function.len()))
+        })
+        .await
+        .ok()
+        .unwrap_or_default();
+        assert_eq!(
+            results.into_iter().map(|(f, _)| f).collect::<Vec<_>>(),
+            functions
+        )
+    }
+
+    #[tokio::test]
+    async fn each_runs_functions_in_the_region_they_live_in() {
+        let functions = vec![
+            "arn:aws:lambda:eu-west-1:123456789012:function:a".to_string(),
+            "us-east-2:b".to_string(),
+            "arn:aws:lambda:eu-west-1:210987654321:function:c".to_string(),
+        ];
+        let results = each(functions, 2, |_| async {
+            Ok::<_, Error>(aws::region().await.to_string())
+        })
+        .await
+        .ok()
+        .unwrap_or_default();
+        assert_eq!(
+            results
+                .into_iter()
+                .map(|(_, region)| region)
+                .collect::<Vec<_>>(),
+            vec!["eu-west-1", "us-east-2", "eu-west-1"]
+        )
+    }
+
+    #[tokio::test]
+    async fn each_settled_carries_on_past_failures() {
+        let store = MemoryStore::default()
+            .with("lev-test-a", test_env(&[("LOG_LEVEL", "info")]))
+            .with("lev-test-c", test_env(&[("LOG_LEVEL", "info")]));
+        let functions = ["lev-test-a", "lev-test-b", "lev-test-c"]
+            .iter()
+            .map(|function| function.to_string())
+            .collect();
+        let results = each_settled(functions, 1, |function| {
+            set_env(
+                store.clone(),
+                function,
+                vec![("LOG_LEVEL".into(), "debug".into())],
+            )
+        })
+        .await;
+        assert_eq!(
+            results
+                .iter()
+                .map(|(function, result)| (function.as_str(), result.is_ok()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("lev-test-a", true),
+                ("lev-test-b", false),
+                ("lev-test-c", true)
+            ]
+        );
+        assert_eq!(
+            store.env("lev-test-c"),
+            Some(test_env(&[("LOG_LEVEL", "debug")]))
+        )
+    }
+}
@@ -1,223 +1,2649 @@
+// aws errors carry the http responses they failed with, which makes ours large
+#![allow(clippy::result_large_err)]
+
+use chrono::{DateTime, Utc};
 use failure::Fail;
-use futures::Future;
-use rusoto_core::{credential::ChainProvider, request::HttpClient, RusotoError};
-use rusoto_lambda::{
-    Environment, FunctionConfiguration, GetFunctionConfigurationError,
-    GetFunctionConfigurationRequest, Lambda, LambdaClient, UpdateFunctionConfigurationRequest,
-};
+use futures::{future, StreamExt, TryStreamExt};
+use glob::Pattern;
+use regex::Regex;
 use std::{
-    collections::HashMap, error::Error as StdError, process::exit, str::FromStr, time::Duration,
+    future::Future,
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::Arc,
+    time::Duration,
 };
 use structopt::StructOpt;
 use tokio::runtime::Runtime;
 
-// Ours
-mod error;
-use crate::error::Error;
+use lev::{
+    alias::Release,
+    assign, audit,
+    aws::{self, LambdaClient},
+    backup, changed,
+    config::{self, Config},
+    console, copy, daemon, diff, each, each_settled, ecs, edit, errln,
+    error::Error,
+    fetch, functions, gc, get, get_env, json, keys, kms,
+    manifest::{self, Manifest},
+    migrate,
+    notify::Notifier,
+    out, outln, parse_key_val, policy, redact, released,
+    render::{Output, Renderer, Renderers},
+    resolve::Registry,
+    sam, serverless, set_env, settled, source, status, suggest, summary, sync, sync_env,
+    target::{self, parse_parallelism, Target},
+    trace, tui, unset_env, value, verify, versions, watch, whoami, Env,
+};
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| format!("invalid time `{}`: {}", s, err))
+}
+
+/// Asks for the value of each key on the terminal, without echoing what is typed
+fn prompt_secrets(keys: &[String]) -> Result<Vec<(String, String)>, Error> {
+    keys.iter()
+        .map(|key| {
+            Ok((
+                key.clone(),
+                rpassword::prompt_password(format!("{}: ", key))?,
+            ))
+        })
+        .collect()
+}
+
+/// Vars with a prefix prepended to their names, when there is one
+fn prefixed<V>(
+    prefix: &Option<String>,
+    vars: Vec<(String, V)>,
+) -> Vec<(String, V)> {
+    match prefix {
+        None => vars,
+        Some(prefix) => vars
+            .into_iter()
+            .map(|(key, value)| (format!("{}{}", prefix, key), value))
+            .collect(),
+    }
+}
+
+/// The vars of an env whose names start with any of `prefixes`, when there
+/// are any, and match `matching`, when it is given
+fn filtered(
+    env: Env,
+    prefixes: &[String],
+    matching: &Option<Regex>,
+) -> Env {
+    env.into_iter()
+        .filter(|(key, _)| {
+            prefixes.is_empty()
+                || prefixes
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix.as_str()))
+        })
+        .filter(|(key, _)| {
+            matching
+                .as_ref()
+                .map(|matching| matching.is_match(key))
+                .unwrap_or(true)
+        })
+        .collect()
+}
 
-fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<std::error::Error>>
-where
-    T: FromStr,
-    T::Err: StdError + 'static,
-    U: FromStr,
-    U::Err: StdError + 'static,
-{
-    let pos = s
-        .find('=')
-        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{}`", s))?;
-    Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
+/// An env as `get` prints it. Values only need hiding from those looking over
+/// your shoulder, so every one is masked at a terminal unless values are
+/// shown, while pipes and files still have redacted keys masked
+fn printed(
+    env: Env,
+    show_values: bool,
+    show_redacted: bool,
+    terminal: bool,
+) -> Env {
+    match (show_redacted, show_values || !terminal) {
+        (true, _) => env,
+        (false, false) => redact::mask(env),
+        (false, true) => redact::redact(env),
+    }
+}
+
+/// Vars with the characters lambda refuses replaced in their names, noting
+/// each renamed
+fn sanitized<V>(vars: Vec<(String, V)>) -> Vec<(String, V)> {
+    vars.into_iter()
+        .map(|(key, value)| {
+            let sanitized = keys::sanitize(&key);
+            if sanitized != key && !console::quiet() {
+                errln!("renamed {} to {}", key, sanitized);
+            }
+            (sanitized, value)
+        })
+        .collect()
+}
+
+/// Parses `KEY=value`, or a bare `KEY` whose value is left to be looked up
+fn parse_assignment(s: &str) -> (String, Option<String>) {
+    match s.split_once('=') {
+        Some((key, value)) => (key.into(), Some(value.into())),
+        None => (s.into(), None),
+    }
 }
 
 #[derive(StructOpt, PartialEq, Debug)]
 #[structopt(name = "lev", about = "AWS lambda env manager")]
+struct Lev {
+    /// Maximum number of retries of throttled or undelivered AWS requests
+    #[structopt(long = "max-retries", default_value = "3")]
+    max_retries: usize,
+    /// Seconds to give credentials to resolve, instance and container
+    /// metadata being slow to answer on some EC2 hosts and CI runners, and
+    /// credential processes waiting on MFA
+    #[structopt(long = "credential-timeout", default_value = "10")]
+    credential_timeout: u64,
+    /// Has a running `lev daemon` run the command
+    #[structopt(long = "use-daemon")]
+    use_daemon: bool,
+    /// Socket the daemon listens on, defaults to ~/.lev/daemon.sock
+    #[structopt(long = "daemon-socket", parse(from_os_str))]
+    daemon_socket: Option<PathBuf>,
+    /// S3 bucket to keep env snapshots in, sharing them with everyone using
+    /// it, rather than ~/.lev/backups
+    #[structopt(long = "backup-bucket")]
+    backup_bucket: Option<String>,
+    /// Changes envs even when the previous ones can't be snapshotted, which
+    /// leaves nothing to roll back to
+    #[structopt(long = "allow-unsnapshotted")]
+    allow_unsnapshotted: bool,
+    /// DynamoDB table to record every change in, keyed by `function` and
+    /// `timestamp` strings
+    #[structopt(long = "audit-table")]
+    audit_table: Option<String>,
+    /// Bearer token sent when reading manifests and env files from https:// urls
+    #[structopt(long = "source-token")]
+    source_token: Option<String>,
+    /// File to append a JSON line to for every change, a local alternative to
+    /// an audit table
+    #[structopt(long = "audit-log", parse(from_os_str))]
+    audit_log: Option<PathBuf>,
+    /// Prints changes without coloring them, as setting NO_COLOR does
+    #[structopt(long = "no-color")]
+    no_color: bool,
+    /// Prints nothing but errors after changing envs
+    #[structopt(long = "quiet", short = "q")]
+    quiet: bool,
+    /// Logs each AWS call, how long it took and its retries to stderr, and
+    /// with -vv each HTTP request along with its AWS request id
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: u8,
+    /// Reports failures on stderr as text, or as JSON objects of their
+    /// code, message, request_id and whether they are retryable
+    #[structopt(
+        long = "errors",
+        default_value = "text",
+        raw(possible_values = r#"&["text", "json"]"#)
+    )]
+    errors: String,
+    /// Stage to run against, naming functions by the template of the
+    /// `[naming]` table of a .lev.toml, like `myapp-{stage}-{name}`
+    #[structopt(long = "stage")]
+    stage: Option<String>,
+    #[structopt(subcommand)]
+    command: Options,
+}
+
+#[derive(StructOpt, PartialEq, Debug)]
+enum Backups {
+    #[structopt(name = "list", about = "Lists when a function's snapshots were taken")]
+    List {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+    },
+    #[structopt(name = "restore", about = "Restores a function's env from a snapshot")]
+    Restore {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+        /// Restores the newest snapshot taken no later than this RFC 3339 time
+        /// rather than the newest one
+        #[structopt(long = "to", parse(try_from_str = "parse_time"))]
+        to: Option<DateTime<Utc>>,
+        /// Restores a snapshot without the vars the .lev.toml protects
+        #[structopt(long = "force")]
+        force: bool,
+    },
+}
+
+#[derive(StructOpt, PartialEq, Debug)]
+enum ImportSource {
+    #[structopt(
+        name = "ecs",
+        about = "Imports the container env and secrets of an ECS task definition"
+    )]
+    Ecs {
+        /// Family:revision or arn of the task definition
+        #[structopt(long = "task-def")]
+        task_definition: String,
+        /// Container of the task definition to import from, needed when it
+        /// has several
+        #[structopt(long = "container")]
+        container: Option<String>,
+        /// Function to import into
+        #[structopt(long = "to")]
+        to: String,
+        /// Imports secretsmanager:// and ssm:// references rather than values
+        #[structopt(long = "as-references")]
+        references: bool,
+    },
+    #[structopt(
+        name = "serverless",
+        about = "Imports the provider and function environment of a serverless.yml"
+    )]
+    Serverless {
+        /// Path or url of the serverless.yml
+        #[structopt(long = "config", default_value = "serverless.yml")]
+        file: String,
+        /// Function of the serverless.yml to import the environment of
+        #[structopt(long = "function")]
+        function: String,
+        /// Stage to resolve variables for, defaults to the global --stage, or
+        /// else dev
+        #[structopt(long = "stage")]
+        stage: Option<String>,
+        /// Function to import into, defaults to the name serverless deploys
+        /// the function as
+        #[structopt(long = "to")]
+        to: Option<String>,
+        /// Imports ssm:// references rather than values
+        #[structopt(long = "as-references")]
+        references: bool,
+    },
+    #[structopt(
+        name = "sam",
+        about = "Imports the environment of a SAM or CloudFormation function resource"
+    )]
+    Sam {
+        /// Path or url of the template
+        template: String,
+        /// Logical id of the template's function resource
+        #[structopt(long = "logical-id")]
+        logical_id: String,
+        /// Value of a template parameter Ref and Fn::Sub resolve, may be
+        /// repeated
+        #[structopt(
+            long = "parameter",
+            number_of_values = 1,
+            parse(try_from_str = "parse_key_val")
+        )]
+        parameters: Vec<(String, String)>,
+        /// Function to import into, defaults to the FunctionName the
+        /// template gives the resource
+        #[structopt(long = "to")]
+        to: Option<String>,
+        /// Imports secretsmanager:// and ssm:// references rather than values
+        #[structopt(long = "as-references")]
+        references: bool,
+    },
+}
+
+impl ImportSource {
+    /// Name of the subcommand, for errors
+    fn name(&self) -> &'static str {
+        match self {
+            ImportSource::Ecs { .. } => "ecs",
+            ImportSource::Serverless { .. } => "serverless",
+            ImportSource::Sam { .. } => "sam",
+        }
+    }
+
+    /// The function named to import into, if one was
+    fn to(&self) -> Option<&str> {
+        match self {
+            ImportSource::Ecs { to, .. } => Some(to),
+            ImportSource::Serverless { to, .. } | ImportSource::Sam { to, .. } => to.as_deref(),
+        }
+    }
+
+    fn to_mut(&mut self) -> Option<&mut String> {
+        match self {
+            ImportSource::Ecs { to, .. } => Some(to),
+            ImportSource::Serverless { to, .. } | ImportSource::Sam { to, .. } => to.as_mut(),
+        }
+    }
+}
+
+#[derive(StructOpt, PartialEq, Debug)]
 enum Options {
     #[structopt(name = "get", about = "Gets a function's current env")]
     Get {
+        #[structopt(flatten)]
+        target: Target,
+        /// Alias or version to read, resolving aliases to their version
+        #[structopt(long = "qualifier")]
+        qualifier: Option<String>,
+        #[structopt(flatten)]
+        output: Output,
+        /// Prints kms: prefixed values as stored rather than decrypting them
+        #[structopt(long = "no-decrypt")]
+        no_decrypt: bool,
+        /// Writes the envs to a local path or an s3://bucket/key object rather
+        /// than stdout
+        #[structopt(long = "export-to")]
+        export_to: Option<String>,
+        /// Prints this var's base64 encoded value decoded
+        #[structopt(long = "decode-base64", number_of_values = 1)]
+        decode_base64: Vec<String>,
+        /// Prints values as they are rather than masked on a terminal, still
+        /// masking those of redacted keys
+        #[structopt(long = "show-values")]
+        show_values: bool,
+        /// Prints every value as it is, including those of the keys of the
+        /// `[redact]` table of a .lev.toml
+        #[structopt(long = "show-redacted")]
+        show_redacted: bool,
+        /// Prints only vars whose names start with this prefix, may be
+        /// repeated
+        #[structopt(long = "prefix", number_of_values = 1)]
+        prefixes: Vec<String>,
+        /// Prints only vars whose names match this regular expression
+        #[structopt(long = "match")]
+        matching: Option<String>,
+    },
+    #[structopt(name = "exec", about = "Runs a command with a function's env vars")]
+    Exec {
         #[structopt(short = "f", long = "function")]
         function: String,
+        /// Alias or version whose env to run with
+        #[structopt(long = "qualifier")]
+        qualifier: Option<String>,
+        /// Passes kms: prefixed values on as stored rather than decrypting them
+        #[structopt(long = "no-decrypt")]
+        no_decrypt: bool,
+        /// Command to run, and its arguments, after `--`
+        #[structopt(name = "command", raw(required = "true", last = "true"))]
+        command: Vec<String>,
+    },
+    #[structopt(
+        name = "edit",
+        about = "Edits a function's env in $EDITOR, applying the changes on save"
+    )]
+    Edit {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+        /// Removes the vars the .lev.toml protects when deleted while editing
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "tui",
+        about = "Browses functions and edits their envs in the terminal"
+    )]
+    Tui {
+        /// Lists functions afresh rather than from the local cache
+        #[structopt(long = "refresh")]
+        refresh: bool,
+        /// Removes the vars the .lev.toml protects when deleted while editing
+        #[structopt(long = "force")]
+        force: bool,
     },
     #[structopt(name = "set", about = "Sets a function's env var")]
     Set {
+        #[structopt(flatten)]
+        target: Target,
+        #[structopt(flatten)]
+        release: Release,
+        /// Waits for lambda to finish applying the update before exiting
+        #[structopt(long = "wait")]
+        wait: bool,
+        /// KMS key to encrypt the env with, or an empty string for lambda's
+        /// default key
+        #[structopt(long = "kms-key-arn")]
+        kms_key_arn: Option<String>,
+        /// Prompts for this var's value without echoing it, keeping it out of
+        /// shell history and the process list
+        #[structopt(long = "secret", number_of_values = 1)]
+        secrets: Vec<String>,
+        /// Reads vars to set from stdin, as a JSON object or dotenv lines. Vars
+        /// given as arguments take precedence
+        #[structopt(long = "stdin")]
+        stdin: bool,
+        /// Path, s3:// or https:// url of an env file of vars to set, as
+        /// dotenv lines or a JSON object, may be repeated. Later files, stdin
+        /// and vars given as arguments take precedence
+        #[structopt(long = "file", number_of_values = 1)]
+        files: Vec<String>,
+        /// Fills in `${name}` in the values of env files, may be repeated.
+        /// Names not given are filled in from your own env
+        #[structopt(
+            long = "var",
+            number_of_values = 1,
+            parse(try_from_str = "parse_key_val")
+        )]
+        template_vars: Vec<(String, String)>,
+        /// Prepends a prefix to the name of every var set, like MYAPP_
+        #[structopt(long = "prefix")]
+        prefix: Option<String>,
+        /// Base64 encodes this var's value, as read, before setting it
+        #[structopt(long = "encode-base64", number_of_values = 1)]
+        encode_base64: Vec<String>,
+        /// Deep merges a JSON object into the JSON a var holds, as in
+        /// CONFIG='{"feature":{"x":true}}', rather than replacing it. A null
+        /// removes a key, may be repeated
+        #[structopt(
+            long = "json-merge",
+            number_of_values = 1,
+            parse(try_from_str = "parse_key_val")
+        )]
+        json_merges: Vec<(String, serde_json::Value)>,
+        /// Sets vars which change how lambda runs the function, like PATH or TZ
+        #[structopt(long = "force")]
+        force: bool,
+        /// Replaces characters lambda refuses in names, like `-`, with `_`
+        #[structopt(long = "sanitize")]
+        sanitize: bool,
+        /// Vars to set. A value of `@path` is read from the file at path, and
+        /// one of `-` from stdin. A name without a value takes the value of
+        /// your own env var of that name. `{NAME}` in a value given as it is
+        /// stands for the function's current value of NAME
+        #[structopt(name = "name=value", parse(from_str = "parse_assignment"))]
+        vars: Vec<(String, Option<String>)>,
+    },
+    #[structopt(name = "unset", about = "Unsets a function's env var")]
+    Unset {
+        #[structopt(flatten)]
+        target: Target,
+        #[structopt(flatten)]
+        release: Release,
+        /// Waits for lambda to finish applying the update before exiting
+        #[structopt(long = "wait")]
+        wait: bool,
+        /// Unsets every var whose name starts with this prefix, may be
+        /// repeated
+        #[structopt(long = "prefix", number_of_values = 1)]
+        prefixes: Vec<String>,
+        /// Unsets vars the .lev.toml protects along with the rest
+        #[structopt(long = "force")]
+        force: bool,
+        #[structopt(name = "names")]
+        names: Vec<String>,
+    },
+    #[structopt(name = "move", about = "Moves env vars from one function to another")]
+    Move {
+        /// Function to move env vars from
+        #[structopt(long = "from")]
+        from: String,
+        /// Function to move env vars to
+        #[structopt(long = "to")]
+        to: String,
+        #[structopt(name = "keys", raw(required = "true"))]
+        keys: Vec<String>,
+        /// Moves vars the .lev.toml protects along with the rest
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "rollback",
+        about = "Restores a function's env from a snapshot lev took before changing it"
+    )]
+    Rollback {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+        /// Restores the newest snapshot taken no later than this RFC 3339 time
+        /// rather than the newest one
+        #[structopt(long = "to", parse(try_from_str = "parse_time"))]
+        to: Option<DateTime<Utc>>,
+        /// Restores a snapshot without the vars the .lev.toml protects
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "history",
+        about = "Lists the changes to a function's env recorded in the audit table"
+    )]
+    History {
         #[structopt(short = "f", long = "function")]
         function: String,
-        #[structopt(name = "name=value", parse(try_from_str = "parse_key_val"))]
+        /// Lists only this many of the most recent changes
+        #[structopt(long = "limit")]
+        limit: Option<usize>,
+        /// Lists how the env changed between published versions rather than
+        /// reading the audit table or log
+        #[structopt(long = "from-versions")]
+        from_versions: bool,
+    },
+    #[structopt(name = "backups", about = "Lists or restores env snapshots")]
+    Backups {
+        #[structopt(subcommand)]
+        command: Backups,
+    },
+    #[structopt(
+        name = "encrypt",
+        about = "Encrypts a value with a KMS key, printing it in a form get decrypts"
+    )]
+    Encrypt {
+        /// Id, arn or alias of the KMS key to encrypt with
+        #[structopt(long = "kms-key")]
+        kms_key: String,
+        plaintext: String,
+    },
+    #[structopt(
+        name = "functions",
+        about = "Lists function names, answering from a local cache when it has them"
+    )]
+    Functions {
+        /// Lists only names containing this, ignoring case
+        filter: Option<String>,
+        /// Refreshes the cache from lambda, listing names as each page arrives
+        #[structopt(long = "refresh")]
+        refresh: bool,
+    },
+    #[structopt(name = "copy", about = "Copies env vars from one function to others")]
+    Copy {
+        /// Function to copy env vars from
+        #[structopt(long = "from")]
+        from: String,
+        #[structopt(flatten)]
+        target: Target,
+        /// Glob patterns selecting env var names to copy, defaults to all
+        #[structopt(long = "keys")]
+        keys: Vec<Pattern>,
+        /// Glob patterns selecting env var names not to copy
+        #[structopt(long = "exclude")]
+        exclude: Vec<Pattern>,
+    },
+    #[structopt(
+        name = "sync",
+        about = "Sets the vars of layered env files on a function"
+    )]
+    Sync {
+        #[structopt(flatten)]
+        target: Target,
+        /// Path, s3:// or https:// url of an env file of dotenv lines or a
+        /// JSON object, may be repeated, later files overriding earlier ones
+        #[structopt(long = "file", raw(required = "true"), number_of_values = 1)]
+        files: Vec<String>,
+        /// Fills in `${name}` in the values of env files, may be repeated.
+        /// Names not given are filled in from your own env
+        #[structopt(
+            long = "var",
+            number_of_values = 1,
+            parse(try_from_str = "parse_key_val")
+        )]
         vars: Vec<(String, String)>,
+        /// Removes the vars none of the env files define, making the env
+        /// exactly what they hold
+        #[structopt(long = "prune")]
+        prune: bool,
+        /// Removes protected vars along with the rest when pruning
+        #[structopt(long = "force", requires = "prune")]
+        force: bool,
+        /// Prints the pending changes without applying them
+        #[structopt(long = "plan")]
+        plan: bool,
+        /// Waits for lambda to finish applying updates before exiting
+        #[structopt(long = "wait", conflicts_with = "plan")]
+        wait: bool,
     },
-    #[structopt(name = "unset", about = "Unsets a function's env var")]
-    Unset {
+    #[structopt(
+        name = "rename-prefix",
+        about = "Renames every env var sharing a prefix in a single update"
+    )]
+    RenamePrefix {
+        #[structopt(flatten)]
+        target: Target,
+        /// Prefix the vars to rename start with
+        #[structopt(name = "old")]
+        old: String,
+        /// Prefix to rename them to start with
+        #[structopt(name = "new")]
+        new: String,
+        /// Prints the pending changes without applying them
+        #[structopt(long = "plan")]
+        plan: bool,
+        /// Waits for lambda to finish applying updates before exiting
+        #[structopt(long = "wait", conflicts_with = "plan")]
+        wait: bool,
+        /// Renames vars the .lev.toml protects along with the rest
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "patch",
+        about = "Applies an RFC 6902 JSON patch to a function's env"
+    )]
+    Patch {
+        #[structopt(flatten)]
+        target: Target,
+        /// Path, s3:// or https:// url of a JSON patch of the env as a JSON
+        /// object of its vars, like `[{"op": "remove", "path": "/DEBUG"}]`
+        #[structopt(long = "rfc6902")]
+        patch: String,
+        /// Prints the pending changes without applying them
+        #[structopt(long = "plan")]
+        plan: bool,
+        /// Waits for lambda to finish applying updates before exiting
+        #[structopt(long = "wait", conflicts_with = "plan")]
+        wait: bool,
+        /// Removes and moves vars the .lev.toml protects along with the rest
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "apply",
+        about = "Reconciles functions with the envs declared in a manifest"
+    )]
+    Apply {
+        /// Path, s3:// or https:// url of a yaml manifest of functions and
+        /// their environments
+        #[structopt(name = "manifest")]
+        manifest: String,
+        /// Maximum number of functions to operate on concurrently
+        #[structopt(
+            long = "parallel",
+            default_value = "4",
+            parse(try_from_str = "parse_parallelism")
+        )]
+        parallel: usize,
+        /// Prints the pending changes without applying them
+        #[structopt(long = "plan")]
+        plan: bool,
+        /// Waits for lambda to finish applying updates before exiting
+        #[structopt(long = "wait", conflicts_with = "plan")]
+        wait: bool,
+        /// Removes the vars the .lev.toml protects when the manifest leaves
+        /// them out
+        #[structopt(long = "force")]
+        force: bool,
+    },
+    #[structopt(
+        name = "plan",
+        about = "Previews the changes applying a manifest would make"
+    )]
+    Plan {
+        /// Path, s3:// or https:// url of a yaml manifest of functions and
+        /// their environments
+        #[structopt(name = "manifest")]
+        manifest: String,
+        /// Maximum number of functions to operate on concurrently
+        #[structopt(
+            long = "parallel",
+            default_value = "4",
+            parse(try_from_str = "parse_parallelism")
+        )]
+        parallel: usize,
+        /// Exit code used when changes are pending, 0 to always succeed
+        #[structopt(long = "exit-code", default_value = "2")]
+        exit_code: i32,
+    },
+    #[structopt(
+        name = "diff",
+        about = "Shows where functions have drifted from a manifest"
+    )]
+    Diff {
+        /// Path, s3:// or https:// url of a yaml manifest of functions and
+        /// their environments
+        #[structopt(name = "manifest")]
+        manifest: String,
+        /// Maximum number of functions to operate on concurrently
+        #[structopt(
+            long = "parallel",
+            default_value = "4",
+            parse(try_from_str = "parse_parallelism")
+        )]
+        parallel: usize,
+        /// Exit code used when differences exist, 0 to always succeed
+        #[structopt(long = "exit-code", default_value = "2")]
+        exit_code: i32,
+    },
+    #[structopt(name = "versions", about = "Lists a function's published versions")]
+    Versions {
         #[structopt(short = "f", long = "function")]
         function: String,
-        #[structopt(name = "names")]
-        names: Vec<String>,
+        /// Summarizes the env changes each version introduced
+        #[structopt(long = "with-env-diff")]
+        with_env_diff: bool,
+    },
+    #[structopt(
+        name = "verify",
+        about = "Verifies functions define the env vars their handlers require"
+    )]
+    Verify {
+        #[structopt(flatten)]
+        target: Target,
+        /// Path, s3:// or https:// url of a lev.manifest.json to verify
+        /// against, rather than the one in each function's deployment package
+        #[structopt(long = "manifest")]
+        manifest: Option<String>,
+        /// Exit code used when required vars are missing, 0 to always succeed
+        #[structopt(long = "exit-code", default_value = "2")]
+        exit_code: i32,
+    },
+    #[structopt(
+        name = "validate",
+        about = "Audits functions' envs against an org policy of required, forbidden and \
+                 constrained vars"
+    )]
+    Validate {
+        #[structopt(flatten)]
+        target: Target,
+        /// Path, s3://bucket/key or https:// url of the yaml policy to check
+        /// against, rather than the one .lev.toml names
+        #[structopt(long = "policy")]
+        policy: Option<String>,
+        /// Exit code used when any env violates the policy, 0 to always
+        /// succeed
+        #[structopt(long = "exit-code", default_value = "2")]
+        exit_code: i32,
+    },
+    #[structopt(
+        name = "size",
+        about = "Reports how much of lambda's 4KB env limit functions use"
+    )]
+    Size {
+        #[structopt(flatten)]
+        target: Target,
+    },
+    #[structopt(
+        name = "summary",
+        about = "Summarizes the health of every function of a stage"
+    )]
+    Summary {
+        /// Stage whose functions to summarize: those the `[naming]` template
+        /// names for it, or without one those carrying a matching `stage` tag
+        #[structopt(long = "stage")]
+        stage: String,
+        /// Path or url of a yaml manifest declaring the stage's environments,
+        /// to report drift from
+        #[structopt(long = "manifest")]
+        manifest: Option<String>,
+        /// Maximum number of functions to operate on concurrently
+        #[structopt(
+            long = "parallel",
+            default_value = "4",
+            parse(try_from_str = "parse_parallelism")
+        )]
+        parallel: usize,
+    },
+    #[structopt(name = "gc", about = "Removes stale local lev state")]
+    Gc {
+        /// Removes items older than this age, e.g. 90d, 2w or 12h, rather
+        /// than what the `[gc]` table of a .lev.toml keeps, or else 90d
+        #[structopt(long = "max-age", parse(try_from_str = "gc::parse_age"))]
+        max_age: Option<chrono::Duration>,
+        /// Keeps at most this many of the newest items per function, rather
+        /// than what the `[gc]` table of a .lev.toml keeps
+        #[structopt(long = "keep")]
+        keep: Option<usize>,
+        /// Restricts collection to an artifact type, may be repeated
+        #[structopt(long = "type", number_of_values = 1)]
+        types: Vec<gc::Artifact>,
+        /// Reports what would be removed without removing it
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
     },
+    #[structopt(name = "watch", about = "Watches functions for env changes")]
+    Watch {
+        #[structopt(flatten)]
+        target: Target,
+        /// Seconds between polls of each function
+        #[structopt(long = "interval", default_value = "30")]
+        interval: u64,
+        /// Notifies the webhook of the `[notify]` table of a .lev.toml of env
+        /// changes not made through lev
+        #[structopt(long = "notify")]
+        notify: bool,
+    },
+    #[structopt(
+        name = "import",
+        about = "Imports env vars into a function from an external source",
+        raw(setting = "structopt::clap::AppSettings::SubcommandsNegateReqs")
+    )]
+    Import {
+        #[structopt(flatten)]
+        target: Target,
+        /// Name of a secret holding a JSON object of env vars
+        #[structopt(long = "from-secret", raw(required_unless = r#""url""#))]
+        secret: Option<String>,
+        /// Path, s3:// or https:// url of a JSON object of env vars
+        #[structopt(long = "from-url", name = "url", conflicts_with = "secret")]
+        url: Option<String>,
+        /// Imports secretsmanager:// and ssm:// references rather than values
+        #[structopt(long = "as-references", conflicts_with = "url")]
+        references: bool,
+        #[structopt(subcommand)]
+        source: Option<ImportSource>,
+    },
+    #[structopt(
+        name = "migrate-to-ssm",
+        about = "Moves a function's env vars into SSM Parameter Store"
+    )]
+    MigrateToSsm {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+        /// Parameter Store path to nest parameters under
+        #[structopt(long = "prefix")]
+        prefix: String,
+        /// Glob patterns selecting env var names to migrate
+        #[structopt(long = "keys", required = true)]
+        keys: Vec<Pattern>,
+    },
+    #[structopt(
+        name = "migrate-to-secrets",
+        about = "Moves a function's env vars into a Secrets Manager secret"
+    )]
+    MigrateToSecrets {
+        #[structopt(short = "f", long = "function")]
+        function: String,
+        /// Name of the secret to consolidate values into
+        #[structopt(long = "secret", number_of_values = 1)]
+        secret: String,
+        /// Glob patterns selecting env var names to migrate
+        #[structopt(long = "keys", required = true)]
+        keys: Vec<Pattern>,
+        /// Prints the migration without applying it
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Inlines values referencing the secret back into the env
+        #[structopt(long = "rollback")]
+        rollback: bool,
+    },
+    #[structopt(
+        name = "whoami",
+        about = "Prints the account and identity lev makes requests as, and its region and profile"
+    )]
+    Whoami,
+    #[structopt(
+        name = "daemon",
+        about = "Serves commands of --use-daemon clients, keeping credentials warm"
+    )]
+    Daemon,
 }
 
-type Env = HashMap<String, String>;
+impl Options {
+    /// The function a command is run against, when it names one
+    fn function(&self) -> Option<&str> {
+        match self {
+            Options::Import {
+                source: Some(source),
+                ..
+            } => source.to(),
+            Options::Get { target, .. }
+            | Options::Set { target, .. }
+            | Options::Unset { target, .. }
+            | Options::Verify { target, .. }
+            | Options::Validate { target, .. }
+            | Options::Size { target }
+            | Options::Watch { target, .. }
+            | Options::Import { target, .. }
+            | Options::Sync { target, .. }
+            | Options::Patch { target, .. }
+            | Options::RenamePrefix { target, .. } => target.function.as_deref(),
+            Options::Exec { function, .. }
+            | Options::Edit { function, .. }
+            | Options::Rollback { function, .. }
+            | Options::History { function, .. }
+            | Options::Versions { function, .. }
+            | Options::MigrateToSsm { function, .. }
+            | Options::MigrateToSecrets { function, .. }
+            | Options::Backups {
+                command: Backups::List { function } | Backups::Restore { function, .. },
+            } => Some(function),
+            Options::Move { from, .. } | Options::Copy { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+
+    /// Every function a command names, to be resolved from aliases
+    fn functions_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Options::Import {
+                source: Some(source),
+                ..
+            } => source.to_mut().into_iter().collect(),
+            Options::Get { target, .. }
+            | Options::Set { target, .. }
+            | Options::Unset { target, .. }
+            | Options::Verify { target, .. }
+            | Options::Validate { target, .. }
+            | Options::Size { target }
+            | Options::Watch { target, .. }
+            | Options::Import { target, .. }
+            | Options::Sync { target, .. }
+            | Options::Patch { target, .. }
+            | Options::RenamePrefix { target, .. } => target.function.iter_mut().collect(),
+            Options::Exec { function, .. }
+            | Options::Edit { function, .. }
+            | Options::Rollback { function, .. }
+            | Options::History { function, .. }
+            | Options::Versions { function, .. }
+            | Options::MigrateToSsm { function, .. }
+            | Options::MigrateToSecrets { function, .. }
+            | Options::Backups {
+                command: Backups::List { function } | Backups::Restore { function, .. },
+            } => vec![function],
+            Options::Move { from, to, .. } => vec![from, to],
+            Options::Copy { from, target, .. } => std::iter::once(from)
+                .chain(target.function.iter_mut())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
 
-fn env(conf: FunctionConfiguration) -> Env {
-    conf.environment
-        .map(|env| env.variables.unwrap_or_default())
-        .unwrap_or_default()
+    /// Whether a command changes the envs of functions, which a read only
+    /// .lev.toml refuses
+    fn changes_envs(&self) -> bool {
+        match self {
+            Options::Set { .. }
+            | Options::Unset { .. }
+            | Options::Move { .. }
+            | Options::Rollback { .. }
+            | Options::Backups {
+                command: Backups::Restore { .. },
+            }
+            | Options::Copy { .. }
+            | Options::Import { .. }
+            | Options::Edit { .. }
+            | Options::Tui { .. }
+            | Options::MigrateToSsm { .. } => true,
+            Options::Apply { plan, .. }
+            | Options::Sync { plan, .. }
+            | Options::Patch { plan, .. }
+            | Options::RenamePrefix { plan, .. } => !plan,
+            Options::MigrateToSecrets { dry_run, .. } => !dry_run,
+            _ => false,
+        }
+    }
 }
 
-fn get<F>(
-    lambda: LambdaClient,
-    function: F,
-) -> impl Future<Item = Env, Error = RusotoError<GetFunctionConfigurationError>> + Send
-where
-    F: Into<String>,
-{
-    lambda
-        .get_function_configuration(GetFunctionConfigurationRequest {
-            function_name: function.into(),
-            ..GetFunctionConfigurationRequest::default()
-        })
-        .map(env)
+/// A command line with `-f <function>` added ahead of any `--`, for commands
+/// left without a function to run against
+fn with_function(
+    args: Vec<String>,
+    function: &str,
+) -> Vec<String> {
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    let mut args = args;
+    args.splice(end..end, vec!["-f".to_string(), function.to_string()]);
+    args
 }
 
-fn set<F>(
-    lambda: LambdaClient,
-    function: F,
-    vars: Vec<(String, String)>,
-) -> impl Future<Item = Env, Error = Error> + Send
-where
-    F: Into<String>,
-{
-    let function = function.into();
-    get(lambda.clone(), function.clone())
-        .map_err(Error::from)
-        .and_then(move |current| {
-            let updated = current.into_iter().chain(vars).collect();
-            lambda
-                .update_function_configuration(UpdateFunctionConfigurationRequest {
-                    function_name: function,
-                    environment: Some(Environment {
-                        variables: Some(updated),
-                    }),
-                    ..UpdateFunctionConfigurationRequest::default()
-                })
-                .map(env)
-                .map_err(Error::from)
-        })
+/// Parses a command line, running commands which name no function against
+/// the default function of a .lev.toml
+fn parse(args: Vec<String>) -> Result<Lev, structopt::clap::Error> {
+    match Lev::from_iter_safe(&args) {
+        Err(err)
+            if err.kind == structopt::clap::ErrorKind::MissingRequiredArgument
+                && err.message.contains("--function") =>
+        {
+            match config::load()
+                .ok()
+                .and_then(|config| config.defaults.function)
+            {
+                Some(function) => Lev::from_iter_safe(with_function(args, &function)),
+                None => Err(err),
+            }
+        }
+        parsed => parsed,
+    }
+}
+
+/// Runs a command with `env` added to lev's own environment, exiting with
+/// the command's status when it fails
+fn exec(
+    command: &[String],
+    env: Env,
+) -> Result<(), Error> {
+    let (program, args) = match command.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let status = Command::new(program).args(args).envs(env).status()?;
+    exit_if(!status.success(), status.code().unwrap_or(1))
+}
+
+/// Restores a function's env from its newest snapshot, or the newest taken no
+/// later than `to`
+async fn rollback(
+    function: String,
+    to: Option<DateTime<Utc>>,
+    force: bool,
+) -> Result<(), Error> {
+    let (taken, snapshot) = backup::restore_point(function.clone(), to).await?;
+    errln!(
+        "{}: restoring snapshot taken at {}",
+        function,
+        taken.to_rfc3339()
+    );
+    let lambda = aws::lambda_client().await;
+    let current = get(lambda.clone(), function.clone()).await?;
+    let changes = changed(lambda, function.clone(), current, snapshot, force).await?;
+    render_changes(vec![(function, changes)], false);
+    Ok(())
+}
+
+/// The function an import subcommand imports into, and the vars it imports.
+/// Sources may hold references, which are resolved unless asked to be
+/// imported as they are
+async fn imported(
+    source: ImportSource,
+    references: bool,
+) -> Result<(String, Vec<(String, String)>), Error> {
+    let resolve = |vars: Vec<(String, String)>, as_references: bool| async move {
+        if references || as_references {
+            return Ok(vars);
+        }
+        Registry::new().await.resolve(vars).await
+    };
+    match source {
+        ImportSource::Ecs {
+            task_definition,
+            container,
+            to,
+            references,
+        } => {
+            let vars = ecs::task_vars(aws::ecs_client().await, task_definition, container).await?;
+            Ok((to, resolve(vars, references).await?))
+        }
+        ImportSource::Serverless {
+            file,
+            function,
+            stage,
+            to,
+            references,
+        } => {
+            let stage = stage.or_else(config::stage).unwrap_or_else(|| "dev".into());
+            let (deployed, vars) = serverless::config_vars(file, stage, function).await?;
+            Ok((to.unwrap_or(deployed), resolve(vars, references).await?))
+        }
+        ImportSource::Sam {
+            template,
+            logical_id,
+            parameters,
+            to,
+            references,
+        } => {
+            let (named, vars) =
+                sam::template_vars(template, logical_id.clone(), parameters).await?;
+            let to = to.or(named).ok_or(Error::UnnamedFunction(logical_id))?;
+            Ok((to, resolve(vars, references).await?))
+        }
+    }
+}
+
+/// The names of the functions a target selects
+async fn selected(target: Target) -> Result<Vec<String>, Error> {
+    target::functions(
+        aws::lambda_client().await,
+        aws::tagging_client().await,
+        target,
+    )
+    .await
 }
 
-fn unset<F>(
+fn render_all(
+    renderer: &dyn Renderer,
+    results: Vec<(String, Env)>,
+) -> Result<(), Error> {
+    out!("{}", renderer.render(&results)?);
+    for warning in renderer.warnings(&results) {
+        errln!("warning: {}", warning);
+    }
+    Ok(())
+}
+
+/// Renders planned or applied changes, returning true if there were any.
+/// Applied changes go unrendered when quiet
+fn render_changes(
+    results: Vec<(String, Vec<diff::Change>)>,
+    planned: bool,
+) -> bool {
+    let (added, changed, removed) = diff::tally(results.iter().flat_map(|(_, changes)| changes));
+    if !planned && console::quiet() {
+        return added + changed + removed > 0;
+    }
+    for (function, changes) in &results {
+        if changes.is_empty() {
+            outln!("  {}: no changes", function);
+            continue;
+        }
+        outln!("~ {}", function);
+        for change in changes {
+            outln!(
+                "    {}",
+                console::paint(change.color(), &change.to_string())
+            )
+        }
+    }
+    outln!();
+    outln!(
+        "{}: {} to add, {} to change, {} to remove.",
+        if planned { "Plan" } else { "Applied" },
+        added,
+        changed,
+        removed
+    );
+    added + changed + removed > 0
+}
+
+/// Renders the changes made to every function which succeeded, then reports
+/// each failure along with the function it befell, failing once all are
+/// reported. A lone function's failure is left to be reported as it is
+fn render_settled(
+    results: Vec<(String, Result<Vec<diff::Change>, Error>)>,
+    planned: bool,
+) -> Result<(), Error> {
+    let total = results.len();
+    let (succeeded, failed): (Vec<_>, Vec<_>) =
+        results.into_iter().partition(|(_, result)| result.is_ok());
+    let mut failed = failed
+        .into_iter()
+        .filter_map(|(function, result)| result.err().map(|err| (function, err)))
+        .collect::<Vec<_>>();
+    if total == 1 && failed.len() == 1 {
+        return Err(failed.remove(0).1);
+    }
+    let succeeded = succeeded
+        .into_iter()
+        .filter_map(|(function, result)| result.ok().map(|changes| (function, changes)))
+        .collect::<Vec<_>>();
+    if failed.is_empty() || !succeeded.is_empty() {
+        render_changes(succeeded, planned);
+    }
+    let statuses = failed
+        .iter()
+        .map(|(function, err)| report(Some(function.as_str()), err))
+        .collect::<Vec<_>>();
+    match statuses.first() {
+        Some(status) => {
+            if !console::json_errors() {
+                errln!("{} of {} functions failed", failed.len(), total);
+            }
+            Err(Error::Exit(*status))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Renders only the functions which differ, returning true if any did
+fn render_diff(results: Vec<(String, Vec<diff::Change>)>) -> bool {
+    let mut drifted = false;
+    for (function, changes) in results.into_iter().filter(|(_, c)| !c.is_empty()) {
+        drifted = true;
+        outln!("{}", function);
+        for change in changes {
+            outln!("  {}", console::paint(change.color(), &change.to_string()))
+        }
+    }
+    drifted
+}
+
+/// Prints how each function measures up to its handler's expectations,
+/// returning true if any lacks required vars
+fn render_verdicts(results: Vec<(String, verify::Verdict)>) -> bool {
+    let (mut satisfied, mut missing, mut undeclared) = (0, 0, 0);
+    for (function, verdict) in &results {
+        let marker = match verdict {
+            verify::Verdict::Satisfied => {
+                satisfied += 1;
+                " "
+            }
+            verify::Verdict::Missing(_) => {
+                missing += 1;
+                "!"
+            }
+            verify::Verdict::Undeclared => {
+                undeclared += 1;
+                " "
+            }
+        };
+        outln!("{} {}: {}", marker, function, verdict);
+    }
+    outln!();
+    outln!(
+        "Verified: {} ok, {} missing required vars, {} undeclared.",
+        satisfied,
+        missing,
+        undeclared
+    );
+    missing > 0
+}
+
+fn render_violations(results: Vec<(String, Vec<String>)>) -> bool {
+    let mut violating = 0;
+    for (function, violations) in &results {
+        if violations.is_empty() {
+            outln!("  {}: ok", function);
+        } else {
+            violating += 1;
+            outln!("! {}: {}", function, violations.join(", "));
+        }
+    }
+    outln!();
+    outln!(
+        "Validated: {} ok, {} violating policy.",
+        results.len() - violating,
+        violating
+    );
+    violating > 0
+}
+
+fn render_summary(healths: &[(String, summary::Health)]) {
+    for row in summary::table(healths) {
+        outln!("{}", row);
+    }
+    outln!();
+    outln!(
+        "Summarized: {} functions, {} needing attention.",
+        healths.len(),
+        healths
+            .iter()
+            .filter(|(_, health)| health.attention())
+            .count()
+    );
+}
+
+/// Prints each function's share of the env limit, and each of its keys'
+fn render_sizes(results: &[(String, Env)]) {
+    for (i, (function, env)) in results.iter().enumerate() {
+        if i > 0 {
+            outln!();
+        }
+        let size = summary::size(env);
+        let remaining = if size > summary::ENV_LIMIT {
+            format!("{} over", size - summary::ENV_LIMIT)
+        } else {
+            format!("{} left", summary::ENV_LIMIT - size)
+        };
+        outln!(
+            "{}: {} of {} bytes, {}",
+            function,
+            size,
+            summary::ENV_LIMIT,
+            remaining
+        );
+        for row in summary::breakdown(env) {
+            outln!("  {}", row);
+        }
+    }
+}
+
+/// Prints versions newest first, along with how each changed the env of the
+/// version published before it
+fn render_timeline(
+    versions: Vec<versions::Version>,
+    limit: Option<usize>,
+) {
+    let chain = versions::chain(&versions);
+    let limit = limit.unwrap_or(chain.len());
+    for (version, changes) in chain.into_iter().rev().take(limit) {
+        let summary = if changes.is_empty() {
+            "no env changes".to_string()
+        } else {
+            changes
+                .iter()
+                .map(diff::Change::summary)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        outln!(
+            "{} version {} {}",
+            version.last_modified,
+            version.version,
+            summary
+        );
+    }
+}
+
+fn render_versions(
+    versions: Vec<versions::Version>,
+    with_env_diff: bool,
+) {
+    for (version, changes) in versions::chain(&versions) {
+        if !with_env_diff {
+            outln!("{}\t{}", version.version, version.last_modified);
+            continue;
+        }
+        let summary = changes
+            .iter()
+            .map(diff::Change::summary)
+            .collect::<Vec<_>>()
+            .join(", ");
+        outln!(
+            "{}\t{}\t{}",
+            version.version,
+            version.last_modified,
+            summary
+        );
+    }
+}
+
+fn render_identity(identity: whoami::Identity) {
+    outln!("Account  {}", identity.account);
+    outln!("Arn      {}", identity.arn);
+    outln!("Region   {}", identity.region);
+    outln!("Profile  {}", identity.profile);
+}
+
+/// Exits with `code` when there are differences to report
+fn exit_if(
+    differs: bool,
+    code: i32,
+) -> Result<(), Error> {
+    if differs && code != 0 {
+        return Err(Error::Exit(code));
+    }
+    Ok(())
+}
+
+fn render_migration(
+    function: &str,
+    migrated: &[migrate::Migrated],
+    checklist: Vec<String>,
+    dry_run: bool,
+) {
+    if migrated.is_empty() {
+        outln!("No env vars of {} matched the provided keys", function);
+        return;
+    }
+    if dry_run {
+        outln!("Dry run, no changes were made. Would migrate");
+    }
+    for m in migrated {
+        outln!("{}={}", m.key, m.reference)
+    }
+    outln!();
+    outln!("Code-change checklist:");
+    for item in checklist {
+        outln!("  [ ] {}", item)
+    }
+}
+
+fn render_restored(
+    function: &str,
+    restored: &[String],
+    dry_run: bool,
+) {
+    if restored.is_empty() {
+        outln!("No env vars of {} reference the secret", function);
+        return;
+    }
+    if dry_run {
+        outln!("Dry run, no changes were made. Would restore");
+    }
+    for name in restored {
+        outln!("{}", name)
+    }
+}
+
+/// Defers starting the runtime's thread pool until a command first needs it.
+/// Local commands never do
+#[derive(Default)]
+struct LazyRuntime(Option<Runtime>);
+
+impl LazyRuntime {
+    fn block_on<F>(
+        &mut self,
+        future: F,
+    ) -> F::Output
+    where
+        F: Future,
+    {
+        self.0
+            .get_or_insert_with(|| Runtime::new().expect("failed to initialize runtime"))
+            .block_on(future)
+    }
+
+    /// Runs a future in the background, its output unwanted
+    fn spawn<F>(
+        &mut self,
+        future: F,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.0
+            .get_or_insert_with(|| Runtime::new().expect("failed to initialize runtime"))
+            .spawn(future);
+    }
+}
+
+/// A command's failure, suggesting functions in place of one lambda could
+/// not find
+fn suggested(
+    err: Error,
+    rt: &mut LazyRuntime,
+) -> Result<(), Error> {
+    match suggest::missing(&err) {
+        Some(function) => {
+            rt.block_on(async { suggest::suggest(aws::lambda_client().await, function, err).await })
+        }
+        None => Err(err),
+    }
+}
+
+/// Reports a command's failure, yielding the status to exit with
+fn status(result: Result<(), Error>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(Error::Exit(code)) => code,
+        Err(err) => report(None, &err),
+    }
+}
+
+/// Reports a failure, of `function` when it befell one of many, yielding
+/// the status it calls for
+fn report(
+    function: Option<&str>,
+    err: &Error,
+) -> i32 {
+    let kind = err.kind();
+    let mut messages = Vec::<String>::new();
+    for fail in <dyn Fail>::iter_chain(err) {
+        let message = fail.to_string();
+        if messages.last() != Some(&message) {
+            messages.push(message);
+        }
+    }
+    if console::json_errors() {
+        let mut reported = serde_json::json!({
+            "code": kind.code(),
+            "message": messages.join(": "),
+            "request_id": err.request_id(),
+            "retryable": kind.retryable(),
+        });
+        if let Some(function) = function {
+            reported["function"] = function.into();
+        }
+        errln!("{}", reported);
+    } else if let Some(function) = function {
+        errln!("{}: {}", function, messages.join(": "));
+    } else {
+        for message in messages {
+            errln!("{}", message);
+        }
+    }
+    kind.status()
+}
+
+/// Functions' envs as the tui browses and edits them
+struct Remote<'a> {
     lambda: LambdaClient,
-    function: F,
-    names: Vec<String>,
-) -> impl Future<Item = Env, Error = Error> + Send
-where
-    F: Into<String>,
-{
-    let function = function.into();
-    get(lambda.clone(), function.clone())
-        .map_err(Error::from)
-        .and_then(move |current| {
-            let updated = current
-                .into_iter()
-                .filter(|(k, _)| !names.contains(k))
-                .collect();
-            lambda
-                .update_function_configuration(UpdateFunctionConfigurationRequest {
-                    function_name: function,
-                    environment: Some(Environment {
-                        variables: Some(updated),
-                    }),
-                    ..UpdateFunctionConfigurationRequest::default()
-                })
-                .map(env)
-                .map_err(Error::from)
-        })
+    rt: &'a mut LazyRuntime,
+    /// Whether protected vars may be removed
+    force: bool,
 }
 
-fn render(env: Env) {
-    for (k, v) in env {
-        println!("{}={}", k, v)
+impl tui::Envs for Remote<'_> {
+    fn load(
+        &mut self,
+        function: &str,
+    ) -> Result<Env, Error> {
+        self.rt
+            .block_on(get_env(self.lambda.clone(), function.to_string()))
+    }
+
+    fn apply(
+        &mut self,
+        function: &str,
+        current: Env,
+        updated: Env,
+    ) -> Result<Vec<diff::Change>, Error> {
+        self.rt.block_on(changed(
+            self.lambda.clone(),
+            function.to_string(),
+            current,
+            updated,
+            self.force,
+        ))
     }
 }
 
-fn credentials() -> ChainProvider {
-    let mut chain = ChainProvider::new();
-    chain.set_timeout(Duration::from_millis(200));
-    chain
+/// Applies the settings of the `.lev.toml` in effect
+fn configure() -> Result<Config, Error> {
+    let config = config::load()?;
+    redact::set_keys(&config.redact.keys)?;
+    target::set_groups(config.groups()?);
+    keys::set_protected(config.safety.protected.clone());
+    aws::set_region(
+        config
+            .defaults
+            .region
+            .as_deref()
+            .map(aws::parse_region)
+            .transpose()?,
+    );
+    Ok(config)
 }
 
-fn lambda_client() -> LambdaClient {
-    LambdaClient::new_with(
-        HttpClient::new().expect("failed to create request dispatcher"),
-        credentials(),
-        Default::default(),
-    )
+/// Runs a daemon client's command line, capturing what it prints
+fn handle(
+    request: daemon::Request,
+    rt: &mut LazyRuntime,
+    socket: &Path,
+) -> daemon::Response {
+    let (code, captured) = console::capture(|| {
+        // the client's .lev.toml is the one to read, including for defaults
+        if let Err(err) = std::env::set_current_dir(&request.cwd) {
+            return status(Err(err.into()));
+        }
+        let args = std::iter::once("lev".to_string()).chain(request.args.iter().cloned());
+        let lev = match parse(args.collect()) {
+            Ok(lev) => lev,
+            Err(err) if err.use_stderr() => {
+                errln!("{}", err.message);
+                return 1;
+            }
+            Err(err) => {
+                outln!("{}", err.message);
+                return 0;
+            }
+        };
+        console::set_json_errors(lev.errors == "json");
+        let interactive = match &lev.command {
+            Options::Daemon
+            | Options::Watch { .. }
+            | Options::Exec { .. }
+            | Options::Edit { .. }
+            | Options::Tui { .. } => true,
+            Options::Set {
+                secrets,
+                stdin,
+                vars,
+                ..
+            } => !secrets.is_empty() || *stdin || value::reads_stdin(vars),
+            _ => false,
+        };
+        if interactive {
+            errln!("long running and interactive commands can not be run by the daemon");
+            return 1;
+        }
+        if let Options::Set { vars, .. } = &lev.command {
+            if value::inherits(vars) {
+                errln!("the daemon has its own env, pass values rather than names to inherit");
+                return 1;
+            }
+        }
+        console::set_client_terminal(request.terminal);
+        config::set_stage(lev.stage);
+        let configured = configure().and_then(|config| {
+            daemon::serves_profile(&request, config.defaults.profile.as_deref())
+        });
+        if let Err(err) = configured {
+            return status(Err(err));
+        }
+        aws::set_max_retries(lev.max_retries);
+        backup::set_bucket(lev.backup_bucket);
+        backup::set_allow_unsnapshotted(lev.allow_unsnapshotted);
+        audit::set_table(lev.audit_table);
+        audit::set_log(lev.audit_log);
+        source::set_token(lev.source_token);
+        console::set_no_color(lev.no_color);
+        console::set_quiet(lev.quiet);
+        trace::set_verbosity(lev.verbose);
+        status(run(lev.command, rt, socket).or_else(|err| suggested(err, rt)))
+    });
+    daemon::Response {
+        stdout: captured.stdout,
+        stderr: captured.stderr,
+        code,
+    }
 }
 
 fn main() {
-    let mut rt = Runtime::new().expect("failed to initialize runtime");
-    let result = match Options::from_args() {
-        Options::Get { function } => rt.block_on(
-            get(lambda_client(), function)
-                .map_err(Error::from)
-                .map(render),
-        ),
-        Options::Set { function, vars } => rt.block_on(
-            set(lambda_client(), function, vars)
-                .map_err(Error::from)
-                .map(render),
-        ),
-        Options::Unset { function, names } => rt.block_on(
-            unset(lambda_client(), function, names)
-                .map_err(Error::from)
-                .map(render),
-        ),
-    };
-    if let Err(err) = result {
-        for cause in Fail::iter_causes(&err) {
-            eprintln!("{}", cause);
+    let lev = parse(std::env::args().collect()).unwrap_or_else(|err| err.exit());
+    console::set_json_errors(lev.errors == "json");
+    let socket = lev.daemon_socket.clone().unwrap_or_else(daemon::socket);
+    let no_color = lev.no_color || console::no_color_env();
+    if lev.use_daemon {
+        let mut args = std::env::args()
+            .skip(1)
+            .filter(|arg| arg != "--use-daemon")
+            .collect::<Vec<_>>();
+        // the daemon has its own env, so hand NO_COLOR on as a flag
+        if no_color && !lev.no_color {
+            args.insert(0, "--no-color".into());
+        }
+        match daemon::forward(&socket, args) {
+            Ok(response) => {
+                print!("{}", response.stdout);
+                eprint!("{}", response.stderr);
+                exit(response.code)
+            }
+            Err(err) => exit(status(Err(err))),
+        }
+    }
+    aws::set_max_retries(lev.max_retries);
+    aws::set_credential_timeout(Duration::from_secs(lev.credential_timeout));
+    backup::set_bucket(lev.backup_bucket);
+    backup::set_allow_unsnapshotted(lev.allow_unsnapshotted);
+    audit::set_table(lev.audit_table);
+    audit::set_log(lev.audit_log);
+    source::set_token(lev.source_token);
+    console::set_no_color(no_color);
+    console::set_quiet(lev.quiet);
+    trace::set_verbosity(lev.verbose);
+    config::set_stage(lev.stage);
+    let config = configure().unwrap_or_else(|err| exit(status(Err(err))));
+    // credentials are only read once a client is made, so this is early
+    // enough for the profile to be the one they are read from
+    if let Some(profile) = config.defaults.profile {
+        if std::env::var_os("AWS_PROFILE").is_none() {
+            std::env::set_var("AWS_PROFILE", profile);
         }
-        exit(1)
+    }
+    let mut rt = LazyRuntime::default();
+    exit(status(
+        run(lev.command, &mut rt, &socket).or_else(|err| suggested(err, &mut rt)),
+    ))
+}
+
+fn run(
+    mut command: Options,
+    rt: &mut LazyRuntime,
+    socket: &Path,
+) -> Result<(), Error> {
+    let config = config::load()?;
+    for function in command.functions_mut() {
+        *function = config.function(function)?;
+    }
+    if config.safety.read_only && command.changes_envs() {
+        return Err(Error::ReadOnly);
+    }
+    // fetched up front, so an unreadable policy refuses changes rather
+    // than letting them through unchecked
+    policy::set(match &config.safety.policy {
+        Some(source) if command.changes_envs() => {
+            Some(Arc::new(rt.block_on(policy::Policy::fetch(source))?))
+        }
+        _ => None,
+    });
+    // lambda only answers for functions in its own region, so an arn from
+    // elsewhere has every client talk to the region it names
+    if let Some(region) = command.function().and_then(target::region) {
+        aws::set_region(Some(aws::parse_region(&region)?));
+    }
+    match command {
+        Options::Get {
+            target,
+            qualifier,
+            output,
+            no_decrypt,
+            export_to,
+            decode_base64,
+            show_values,
+            show_redacted,
+            prefixes,
+            matching,
+        } => {
+            let parallel = target.parallel;
+            let matching = matching
+                .map(|pattern| {
+                    Regex::new(&pattern)
+                        .map_err(|err| Error::InvalidPattern(pattern, err.to_string()))
+                })
+                .transpose()?;
+            let output = Output {
+                format: output.format.or(config.output.format),
+                ..output
+            };
+            let show_values = show_values || config.output.show_values;
+            let terminal = export_to.is_none() && console::terminal();
+            Renderers::new(&output).find(&output).and_then(|renderer| {
+                let (decode_base64, prefixes, matching) = (&decode_base64, &prefixes, &matching);
+                let results = rt.block_on(async {
+                    let functions = selected(target).await?;
+                    each(functions, parallel, |function| {
+                        let qualifier = qualifier.clone();
+                        async move {
+                            let lambda = aws::lambda_client().await;
+                            let env =
+                                fetch(lambda, function.clone(), qualifier, no_decrypt).await?;
+                            let env = value::decode(&function, env, decode_base64)?;
+                            let env = filtered(env, prefixes, matching);
+                            Ok(printed(env, show_values, show_redacted, terminal))
+                        }
+                    })
+                    .await
+                })?;
+                match export_to {
+                    None => render_all(&*renderer, results),
+                    Some(destination) => {
+                        let rendered = renderer.render(&results)?;
+                        rt.block_on(source::write(&destination, rendered.into_bytes()))?;
+                        errln!("exported envs to {}", destination);
+                        Ok(())
+                    }
+                }
+            })
+        }
+        Options::Exec {
+            function,
+            qualifier,
+            no_decrypt,
+            command,
+        } => rt
+            .block_on(async {
+                fetch(aws::lambda_client().await, function, qualifier, no_decrypt).await
+            })
+            .and_then(|env| exec(&command, env)),
+        Options::Edit { function, force } => {
+            let lambda = rt.block_on(aws::lambda_client());
+            let current = rt.block_on(get(lambda.clone(), function.clone()))?;
+            let edited = edit::edit(&function, &current)?;
+            if edited == current {
+                if !console::quiet() {
+                    outln!("  {}: no changes", function);
+                }
+                return Ok(());
+            }
+            // changes made elsewhere while editing would be silently undone
+            let changes = rt.block_on(async {
+                if get(lambda.clone(), function.clone()).await? != current {
+                    return Err(Error::EditConflict(function.clone()));
+                }
+                changed(lambda, function.clone(), current, edited, force).await
+            })?;
+            render_changes(vec![(function, changes)], false);
+            Ok(())
+        }
+        Options::Tui { refresh, force } => {
+            let region = rt.block_on(aws::region()).to_string();
+            let lambda = rt.block_on(aws::lambda_client());
+            let (names, pages) = match functions::cached(&region) {
+                Some(cache) if !refresh => {
+                    if cache.is_stale() {
+                        functions::refresh_in_background();
+                    }
+                    (cache.names, None)
+                }
+                // the browser opens right away, with names added as lambda
+                // lists them
+                _ => {
+                    let (sender, pages) = std::sync::mpsc::channel();
+                    let listed = functions::refresh(lambda.clone(), region).for_each(move |page| {
+                        let _ = sender.send(page);
+                        future::ready(())
+                    });
+                    rt.spawn(listed);
+                    (Vec::new(), Some(pages))
+                }
+            };
+            tui::run(names, pages, &mut Remote { lambda, rt, force })
+        }
+        Options::Set {
+            target,
+            release,
+            wait,
+            kms_key_arn,
+            secrets,
+            stdin,
+            files,
+            template_vars,
+            prefix,
+            encode_base64,
+            json_merges,
+            force,
+            sanitize,
+            vars,
+        } => {
+            let parallel = target.parallel;
+            let wait = wait || config.safety.wait;
+            let filed = if files.is_empty() {
+                Vec::new()
+            } else {
+                let mut filed = rt
+                    .block_on(sync::layered(files, template_vars.into_iter().collect()))?
+                    .into_iter()
+                    .map(|(key, value)| {
+                        value::encode(&key, value.into_bytes(), &encode_base64)
+                            .map(|value| (key, value))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                filed.sort();
+                filed
+            };
+            // only values given as they are have their references filled in,
+            // not what files, stdin or the references they hold resolve to
+            let (templates, vars): (Vec<_>, Vec<_>) = vars
+                .into_iter()
+                .partition(|(_, value)| value::is_template(value));
+            let templates = value::read_all(templates, std::io::empty(), &encode_base64)?;
+            let vars = if stdin {
+                if let Some((key, _)) = vars.iter().find(|(_, value)| value::is_stdin(value)) {
+                    return Err(Error::StdinTaken("--stdin".into(), key.clone()));
+                }
+                value::read_env(std::io::stdin(), &encode_base64)?
+                    .into_iter()
+                    .chain(value::read_all(vars, std::io::empty(), &encode_base64)?)
+                    .collect()
+            } else {
+                value::read_all(vars, std::io::stdin(), &encode_base64)?
+            };
+            let vars = filed.into_iter().chain(vars).collect();
+            let secrets = prompt_secrets(&secrets)?
+                .into_iter()
+                .map(|(key, secret)| {
+                    value::encode(&key, secret.into_bytes(), &encode_base64)
+                        .map(|secret| (key, secret))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let (vars, templates, secrets, json_merges) = (
+                prefixed(&prefix, vars),
+                prefixed(&prefix, templates),
+                prefixed(&prefix, secrets),
+                prefixed(&prefix, json_merges),
+            );
+            let (vars, templates, secrets, json_merges) = if sanitize {
+                (
+                    sanitized(vars),
+                    sanitized(templates),
+                    sanitized(secrets),
+                    sanitized(json_merges),
+                )
+            } else {
+                (vars, templates, secrets, json_merges)
+            };
+            let keys = vars
+                .iter()
+                .chain(&templates)
+                .chain(&secrets)
+                .map(|(key, _)| key)
+                .chain(json_merges.iter().map(|(key, _)| key));
+            keys::check_names(keys.clone())?;
+            keys::check_reserved(keys.clone())?;
+            keys::check_runtime(keys, force)?;
+            rt.block_on(async {
+                let registry = Registry::new().await;
+                let (functions, vars) =
+                    future::try_join(selected(target), registry.resolve(vars)).await?;
+                // secrets are set as they are typed, never resolved
+                let vars = vars.into_iter().chain(secrets).collect::<Vec<_>>();
+                let (vars, templates, json_merges) = (&vars, &templates, &json_merges);
+                let (release, kms_key_arn, registry) = (&release, &kms_key_arn, &registry);
+                let results = each_settled(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    let changes = assign(
+                        lambda.clone(),
+                        function.clone(),
+                        vars.clone(),
+                        templates.clone(),
+                        json_merges.clone(),
+                        kms_key_arn.clone(),
+                        registry,
+                    )
+                    .await?;
+                    let changes = settled(function.clone(), changes, wait).await?;
+                    released(lambda, function, release.clone(), changes).await
+                })
+                .await;
+                render_settled(results, false)
+            })
+        }
+        Options::Unset {
+            target,
+            release,
+            wait,
+            prefixes,
+            force,
+            names,
+        } => {
+            let parallel = target.parallel;
+            let wait = wait || config.safety.wait;
+            rt.block_on(async {
+                let functions = selected(target).await?;
+                let (names, prefixes, release) = (&names, &prefixes, &release);
+                let results = each_settled(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    let changes = unset_env(
+                        lambda.clone(),
+                        function.clone(),
+                        names.clone(),
+                        prefixes.clone(),
+                        force,
+                    )
+                    .await?;
+                    let changes = settled(function.clone(), changes, wait).await?;
+                    released(lambda, function, release.clone(), changes).await
+                })
+                .await;
+                render_settled(results, false)
+            })
+        }
+        Options::Apply {
+            manifest,
+            parallel,
+            plan,
+            wait,
+            force,
+        } => {
+            let wait = wait || config.safety.wait;
+            rt.block_on(async {
+                let manifest = Manifest::fetch(&manifest).await?;
+                let results = manifest::run(manifest, parallel, !plan, force).await?;
+                let updated = results
+                    .iter()
+                    .filter(|(_, changes)| wait && !changes.is_empty())
+                    .map(|(function, _)| function.clone())
+                    .collect();
+                each(updated, parallel, status::wait).await?;
+                render_changes(results, plan);
+                Ok(())
+            })
+        }
+        Options::Plan {
+            manifest,
+            parallel,
+            exit_code,
+        } => rt
+            .block_on(async {
+                let manifest = Manifest::fetch(&manifest).await?;
+                manifest::run(manifest, parallel, false, false).await
+            })
+            .and_then(|results| exit_if(render_changes(results, true), exit_code)),
+        Options::Diff {
+            manifest,
+            parallel,
+            exit_code,
+        } => rt
+            .block_on(async {
+                let manifest = Manifest::fetch(&manifest).await?;
+                manifest::run(manifest, parallel, false, false).await
+            })
+            .and_then(|results| exit_if(render_diff(results), exit_code)),
+        Options::Verify {
+            target,
+            manifest,
+            exit_code,
+        } => {
+            let parallel = target.parallel;
+            rt.block_on(async {
+                let expectations = async {
+                    match manifest {
+                        Some(manifest) => verify::Expectations::fetch(&manifest)
+                            .await
+                            .map(|e| Some(Arc::new(e))),
+                        None => Ok(None),
+                    }
+                };
+                let (functions, expectations) =
+                    future::try_join(selected(target), expectations).await?;
+                each(functions, parallel, |function| {
+                    let expectations = expectations.clone();
+                    async move {
+                        verify::verify(aws::lambda_client().await, function, expectations).await
+                    }
+                })
+                .await
+            })
+            .and_then(|results| exit_if(render_verdicts(results), exit_code))
+        }
+        Options::Validate {
+            target,
+            policy,
+            exit_code,
+        } => {
+            let parallel = target.parallel;
+            let source = policy.or(config.safety.policy).ok_or(Error::NoPolicy)?;
+            rt.block_on(async {
+                let (functions, policy) =
+                    future::try_join(selected(target), policy::Policy::fetch(&source)).await?;
+                let policy = &policy;
+                each(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    Ok(policy.violations(&get(lambda, function).await?))
+                })
+                .await
+            })
+            .and_then(|results| exit_if(render_violations(results), exit_code))
+        }
+        Options::Size { target } => {
+            let parallel = target.parallel;
+            rt.block_on(async {
+                let functions = selected(target).await?;
+                each(functions, parallel, |function| async move {
+                    get_env(aws::lambda_client().await, function).await
+                })
+                .await
+            })
+            .map(|results| render_sizes(&results))
+        }
+        Options::Summary {
+            stage,
+            manifest,
+            parallel,
+        } => {
+            let target = match config.naming.pattern(&stage) {
+                Some(pattern) => Target {
+                    function: Some(pattern),
+                    parallel,
+                    ..Target::default()
+                },
+                None => Target {
+                    tags: vec![("stage".into(), stage)],
+                    parallel,
+                    ..Target::default()
+                },
+            };
+            rt.block_on(async {
+                let declared = async {
+                    match manifest {
+                        Some(manifest) => Manifest::fetch(&manifest).await.map(Some),
+                        None => Ok(None),
+                    }
+                };
+                let policy = async {
+                    match &config.safety.policy {
+                        Some(source) => policy::Policy::fetch(source).await.map(Some),
+                        None => Ok(None),
+                    }
+                };
+                let (functions, mut declared, policy) =
+                    future::try_join3(selected(target), declared, policy).await?;
+                let policy = policy.as_ref();
+                each(functions, parallel, |function| {
+                    let environment = declared.as_mut().map(|manifest| {
+                        manifest
+                            .functions
+                            .remove(&function)
+                            .map(|declared| declared.environment)
+                    });
+                    async move {
+                        let lambda = aws::lambda_client().await;
+                        summary::health(lambda, function, environment.flatten(), policy).await
+                    }
+                })
+                .await
+            })
+            .map(|healths| render_summary(&healths))
+        }
+        Options::Gc {
+            max_age,
+            keep,
+            mut types,
+            dry_run,
+        } => {
+            if types.is_empty() {
+                types = gc::Artifact::all();
+            }
+            types.into_iter().try_for_each(|artifact| {
+                let retention = artifact.retention(&config.gc, max_age, keep)?;
+                let (removed, total) = artifact.collect(&retention, dry_run)?;
+                outln!(
+                    "{}: {} {} of {} items",
+                    artifact,
+                    if dry_run { "would remove" } else { "removed" },
+                    removed,
+                    total
+                );
+                Ok(())
+            })
+        }
+        Options::Versions {
+            function,
+            with_env_diff,
+        } => rt
+            .block_on(async { versions::versions(aws::lambda_client().await, function).await })
+            .map(|versions| render_versions(versions, with_env_diff)),
+        Options::Watch {
+            target,
+            interval,
+            notify,
+        } => rt.block_on(async {
+            let notifier = if notify {
+                let webhook = config.notify.webhook.clone().ok_or(Error::NoWebhook)?;
+                Some(Notifier::new(webhook))
+            } else {
+                None
+            };
+            let functions = selected(target).await?;
+            watch::watch(
+                aws::lambda_client().await,
+                functions,
+                Duration::from_secs(interval),
+                notifier,
+            )
+            .for_each(|line| {
+                outln!("{}", line);
+                future::ready(())
+            })
+            .await;
+            Ok(())
+        }),
+        Options::History {
+            function,
+            limit,
+            from_versions: true,
+        } => rt
+            .block_on(async { versions::versions(aws::lambda_client().await, function).await })
+            .map(|versions| render_timeline(versions, limit)),
+        Options::History {
+            function, limit, ..
+        } => rt.block_on(audit::history(function, limit)).map(|entries| {
+            for entry in entries {
+                outln!("{}", entry);
+            }
+        }),
+        Options::Functions { filter, refresh } => {
+            let region = rt.block_on(aws::region()).to_string();
+            let filter = filter.unwrap_or_default();
+            match functions::cached(&region) {
+                Some(cache) if !refresh => {
+                    for name in functions::matching(&cache.names, &filter) {
+                        outln!("{}", name);
+                    }
+                    if cache.is_stale() {
+                        functions::refresh_in_background();
+                    }
+                    Ok(())
+                }
+                _ => rt.block_on(async {
+                    functions::refresh(aws::lambda_client().await, region)
+                        .try_for_each(|page| {
+                            for name in functions::matching(&page, &filter) {
+                                outln!("{}", name);
+                            }
+                            future::ok(())
+                        })
+                        .await
+                }),
+            }
+        }
+        Options::Encrypt { kms_key, plaintext } => rt
+            .block_on(async { kms::encrypt(aws::kms_client().await, kms_key, plaintext).await })
+            .map(|ciphertext| outln!("{}", ciphertext)),
+        Options::Move {
+            from,
+            to,
+            keys,
+            force,
+        } => rt
+            .block_on(async {
+                copy::transfer(aws::lambda_client().await, from, to, keys, force).await
+            })
+            .map(|results| {
+                render_changes(results, false);
+            }),
+        Options::Rollback {
+            function,
+            to,
+            force,
+        }
+        | Options::Backups {
+            command:
+                Backups::Restore {
+                    function,
+                    to,
+                    force,
+                },
+        } => rt.block_on(rollback(function, to, force)),
+        Options::Backups {
+            command: Backups::List { function },
+        } => rt.block_on(backup::list(&function)).map(|snapshots| {
+            for taken in snapshots {
+                outln!("{}", taken.to_rfc3339());
+            }
+        }),
+        Options::Copy {
+            from,
+            target,
+            keys,
+            exclude,
+        } => {
+            let parallel = target.parallel;
+            rt.block_on(async {
+                let (functions, vars) = future::try_join(selected(target), async {
+                    copy::vars(aws::lambda_client().await, from, keys, exclude).await
+                })
+                .await?;
+                let results = each_settled(functions, parallel, |function| {
+                    let vars = vars.clone();
+                    async move { set_env(aws::lambda_client().await, function, vars).await }
+                })
+                .await;
+                render_settled(results, false)
+            })
+        }
+        Options::Import {
+            target,
+            secret,
+            url,
+            references,
+            source,
+        } => {
+            let parallel = target.parallel;
+            rt.block_on(async {
+                let (functions, vars) = match source {
+                    Some(source) => {
+                        if target.function.is_some()
+                            || !target.tags.is_empty()
+                            || target.group.is_some()
+                            || secret.is_some()
+                            || url.is_some()
+                        {
+                            return Err(Error::ImportConflict(source.name().into()));
+                        }
+                        let (function, vars) = imported(source, references).await?;
+                        (vec![function], vars)
+                    }
+                    None => {
+                        let vars = async {
+                            match secret {
+                                Some(secret) => {
+                                    migrate::secret_vars(
+                                        aws::secrets_client().await,
+                                        secret,
+                                        references,
+                                    )
+                                    .await
+                                }
+                                None => migrate::source_vars(url.unwrap_or_default()).await,
+                            }
+                        };
+                        future::try_join(selected(target), vars).await?
+                    }
+                };
+                let results = each_settled(functions, parallel, |function| {
+                    let vars = vars.clone();
+                    async move { set_env(aws::lambda_client().await, function, vars).await }
+                })
+                .await;
+                render_settled(results, false)
+            })
+        }
+        Options::Sync {
+            target,
+            files,
+            vars,
+            prune,
+            force,
+            plan,
+            wait,
+        } => {
+            let parallel = target.parallel;
+            let wait = wait || config.safety.wait;
+            rt.block_on(async {
+                let (functions, synced) = future::try_join(
+                    selected(target),
+                    sync::layered(files, vars.into_iter().collect()),
+                )
+                .await?;
+                let synced = &synced;
+                let results = each_settled(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    let changes =
+                        sync_env(lambda, function.clone(), synced.clone(), prune, force, plan)
+                            .await?;
+                    settled(function, changes, wait && !plan).await
+                })
+                .await;
+                render_settled(results, plan)
+            })
+        }
+        Options::RenamePrefix {
+            target,
+            old,
+            new,
+            plan,
+            wait,
+            force,
+        } => {
+            let parallel = target.parallel;
+            let wait = wait || config.safety.wait;
+            rt.block_on(async {
+                let functions = selected(target).await?;
+                let (old, new) = (&old, &new);
+                let results = each_settled(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    let current = get(lambda.clone(), function.clone()).await?;
+                    let renamed = keys::rename_prefix(&function, &current, old, new)?;
+                    if plan {
+                        keys::check_protected(&function, &current, &renamed, force)?;
+                        return Ok(diff::diff(&current, &renamed));
+                    }
+                    let changes =
+                        changed(lambda, function.clone(), current, renamed, force).await?;
+                    settled(function, changes, wait).await
+                })
+                .await;
+                render_settled(results, plan)
+            })
+        }
+        Options::Patch {
+            target,
+            patch,
+            plan,
+            wait,
+            force,
+        } => {
+            let parallel = target.parallel;
+            let wait = wait || config.safety.wait;
+            rt.block_on(async {
+                let (functions, operations) =
+                    future::try_join(selected(target), json::read(&patch)).await?;
+                let operations = &operations;
+                let results = each_settled(functions, parallel, |function| async move {
+                    let lambda = aws::lambda_client().await;
+                    let current = get(lambda.clone(), function.clone()).await?;
+                    let patched = json::patched(&current, operations)?;
+                    if plan {
+                        keys::check_protected(&function, &current, &patched, force)?;
+                        return Ok(diff::diff(&current, &patched));
+                    }
+                    let changes =
+                        changed(lambda, function.clone(), current, patched, force).await?;
+                    settled(function, changes, wait).await
+                })
+                .await;
+                render_settled(results, plan)
+            })
+        }
+        Options::MigrateToSsm {
+            function,
+            prefix,
+            keys,
+        } => rt
+            .block_on(async {
+                migrate::to_ssm(
+                    aws::lambda_client().await,
+                    aws::ssm_client().await,
+                    function.clone(),
+                    prefix,
+                    keys,
+                )
+                .await
+            })
+            .map(|(arn, migrated)| {
+                let checklist = migrate::ssm_checklist(&arn, &migrated);
+                render_migration(&function, &migrated, checklist, false)
+            }),
+        Options::MigrateToSecrets {
+            function,
+            secret,
+            keys,
+            dry_run,
+            rollback,
+        } => {
+            if rollback {
+                rt.block_on(async {
+                    migrate::from_secrets(
+                        aws::lambda_client().await,
+                        aws::secrets_client().await,
+                        function.clone(),
+                        secret,
+                        keys,
+                        dry_run,
+                    )
+                    .await
+                })
+                .map(|restored| render_restored(&function, &restored, dry_run))
+            } else {
+                rt.block_on(async {
+                    migrate::to_secrets(
+                        aws::lambda_client().await,
+                        aws::secrets_client().await,
+                        function.clone(),
+                        secret.clone(),
+                        keys,
+                        dry_run,
+                    )
+                    .await
+                })
+                .map(|(arn, migrated)| {
+                    let checklist = migrate::secrets_checklist(&arn, &secret, &migrated);
+                    render_migration(&function, &migrated, checklist, dry_run)
+                })
+            }
+        }
+        Options::Whoami => rt
+            .block_on(async { whoami::whoami(aws::sts_client().await).await })
+            .map(render_identity),
+        Options::Daemon => daemon::serve(socket, |request| handle(request, rt, socket)),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{env, Options};
-    use rusoto_lambda::{EnvironmentResponse, FunctionConfiguration};
-    use std::collections::HashMap;
+    use super::{
+        console, diff, exec, filtered, gc, printed, redact, render_changes, render_settled,
+        with_function, Backups, Env, Error, ImportSource, Lev, Options, Output, Release, Target,
+    };
+    use glob::Pattern;
+    use regex::Regex;
 
     use structopt::StructOpt;
 
     #[test]
-    fn env_extracts_from_empty_config() {
+    fn render_changes_keeps_quiet_about_applied_changes() {
+        let results = || {
+            vec![(
+                "foo".to_string(),
+                vec![diff::Change::Added("A".into(), "1".into())],
+            )]
+        };
+        let (rendered, captured) = console::capture(|| {
+            console::set_quiet(true);
+            let rendered = (
+                render_changes(results(), false),
+                render_changes(results(), true),
+            );
+            console::set_quiet(false);
+            rendered
+        });
+        assert_eq!(rendered, (true, true));
+        assert!(captured.stdout.starts_with("~ foo\n"));
+        assert!(captured.stdout.contains("Plan: 1 to add"));
+        assert!(!captured.stdout.contains("Applied"))
+    }
+
+    #[test]
+    fn render_settled_reports_successes_and_failures_together() {
+        let (result, captured) = console::capture(|| {
+            render_settled(
+                vec![
+                    (
+                        "foo".to_string(),
+                        Ok(vec![diff::Change::Added("A".into(), "1".into())]),
+                    ),
+                    ("bar".to_string(), Err(Error::NoSuchGroup("x".into()))),
+                ],
+                true,
+            )
+        });
+        match result {
+            Err(Error::Exit(status)) => assert_eq!(status, 3),
+            other => panic!("expected an exit, got {:?}", other),
+        }
+        assert!(captured.stdout.starts_with("~ foo\n"));
+        assert!(captured.stderr.starts_with("bar: "));
+        assert!(captured.stderr.contains("1 of 2 functions failed"))
+    }
+
+    #[test]
+    fn lev_options() {
         assert_eq!(
-            env(FunctionConfiguration {
-                ..FunctionConfiguration::default()
-            }),
-            Default::default()
+            Lev {
+                max_retries: 5,
+                credential_timeout: 30,
+                use_daemon: true,
+                daemon_socket: Some("/tmp/lev.sock".into()),
+                backup_bucket: Some("envs".into()),
+                allow_unsnapshotted: true,
+                audit_table: Some("audit".into()),
+                source_token: None,
+                audit_log: Some("audit.jsonl".into()),
+                no_color: true,
+                quiet: true,
+                verbose: 2,
+                errors: "json".into(),
+                stage: Some("prod".into()),
+                command: Options::Unset {
+                    target: Target {
+                        function: Some("foo".into()),
+                        ..Target::default()
+                    },
+                    release: Release::default(),
+                    wait: false,
+                    prefixes: Vec::new(),
+                    force: false,
+                    names: vec!["bar".into()],
+                },
+            },
+            Lev::from_iter(&[
+                "lev",
+                "--max-retries",
+                "5",
+                "--credential-timeout",
+                "30",
+                "--use-daemon",
+                "--daemon-socket",
+                "/tmp/lev.sock",
+                "--backup-bucket",
+                "envs",
+                "--allow-unsnapshotted",
+                "--audit-table",
+                "audit",
+                "--audit-log",
+                "audit.jsonl",
+                "--no-color",
+                "-q",
+                "-vv",
+                "--errors",
+                "json",
+                "--stage",
+                "prod",
+                "unset",
+                "-f",
+                "foo",
+                "bar"
+            ])
         )
     }
 
     #[test]
-    fn env_extracts_from_nonempty_config() {
-        let mut vars = HashMap::new();
-        vars.insert("foo".to_string(), "bar".to_string());
+    fn filtered_keeps_selected_vars() {
+        let env = ["DB_URL", "DB_USER", "CACHE_URL", "LOG_LEVEL"]
+            .iter()
+            .map(|key| (key.to_string(), "x".to_string()))
+            .collect::<Env>();
+        let keys = |env: Env| {
+            let mut keys = env.into_keys().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
         assert_eq!(
-            env(FunctionConfiguration {
-                environment: Some(EnvironmentResponse {
-                    variables: Some(vars.clone()),
-                    ..EnvironmentResponse::default()
-                }),
-                ..FunctionConfiguration::default()
-            }),
-            vars
+            keys(filtered(env.clone(), &["DB_".into()], &None)),
+            vec!["DB_URL", "DB_USER"]
+        );
+        assert_eq!(
+            keys(filtered(
+                env.clone(),
+                &[],
+                &Some(Regex::new("URL$").unwrap())
+            )),
+            vec!["CACHE_URL", "DB_URL"]
+        );
+        assert_eq!(
+            keys(filtered(
+                env,
+                &["DB_".into()],
+                &Some(Regex::new("URL$").unwrap())
+            )),
+            vec!["DB_URL"]
         )
     }
 
+    #[test]
+    fn printed_masks_values_by_where_they_go() {
+        redact::set_keys(&["API_KEY".into()]).unwrap();
+        let env = [("API_KEY", "s3cr3t"), ("LOG_LEVEL", "info")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Env>();
+        let mask = redact::MASK;
+        for (show_values, show_redacted, terminal, expected) in [
+            (false, false, true, [mask, mask]),
+            (false, false, false, [mask, "info"]),
+            (true, false, true, [mask, "info"]),
+            (true, false, false, [mask, "info"]),
+            (false, true, true, ["s3cr3t", "info"]),
+            (false, true, false, ["s3cr3t", "info"]),
+            (true, true, true, ["s3cr3t", "info"]),
+            (true, true, false, ["s3cr3t", "info"]),
+        ] {
+            let env = printed(env.clone(), show_values, show_redacted, terminal);
+            assert_eq!(
+                [env["API_KEY"].as_str(), env["LOG_LEVEL"].as_str()],
+                expected,
+                "show_values {} show_redacted {} terminal {}",
+                show_values,
+                show_redacted,
+                terminal
+            );
+        }
+        redact::set_keys(&[]).unwrap();
+    }
+
     #[test]
     fn get_options() {
         assert_eq!(
             Options::Get {
-                function: "foo".into()
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                qualifier: None,
+                output: Output {
+                    format: Some("json".into()),
+                    name: None,
+                    raw: false,
+                    print0: false,
+                },
+                no_decrypt: false,
+                export_to: None,
+                decode_base64: Vec::new(),
+                show_values: false,
+                show_redacted: false,
+                prefixes: Vec::new(),
+                matching: None,
             },
-            Options::from_iter(&["lev", "get", "-f", "foo"])
+            Options::from_iter(&["lev", "get", "-f", "foo", "--output", "json"])
+        )
+    }
+
+    #[test]
+    fn get_tagged_options() {
+        assert_eq!(
+            Options::Get {
+                target: Target {
+                    tags: vec![("team".into(), "payments".into())],
+                    ..Target::default()
+                },
+                qualifier: None,
+                output: Output::default(),
+                no_decrypt: true,
+                export_to: Some("s3://envs/payments.env".into()),
+                decode_base64: vec!["CERT".into()],
+                show_values: true,
+                show_redacted: false,
+                prefixes: vec!["DB_".into()],
+                matching: Some("URL$".into()),
+            },
+            Options::from_iter(&[
+                "lev",
+                "get",
+                "--tag",
+                "team=payments",
+                "--no-decrypt",
+                "--export-to",
+                "s3://envs/payments.env",
+                "--decode-base64",
+                "CERT",
+                "--show-values",
+                "--prefix",
+                "DB_",
+                "--match",
+                "URL$"
+            ])
         )
     }
 
@@ -225,21 +2651,721 @@ mod tests {
     fn set_options() {
         assert_eq!(
             Options::Set {
-                function: "foo".into(),
-                vars: vec![("bar".into(), "baz".into()), ("boom".into(), "zoom".into())],
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                release: Release::default(),
+                wait: false,
+                kms_key_arn: None,
+                secrets: Vec::new(),
+                stdin: false,
+                files: Vec::new(),
+                template_vars: Vec::new(),
+                prefix: None,
+                encode_base64: Vec::new(),
+                json_merges: Vec::new(),
+                force: false,
+                sanitize: false,
+                vars: vec![
+                    ("bar".into(), Some("baz".into())),
+                    ("boom".into(), Some("zoom".into()))
+                ],
             },
             Options::from_iter(&["lev", "set", "-f", "foo", "bar=baz", "boom=zoom"])
         )
     }
 
+    #[test]
+    fn set_inherited_options() {
+        match Options::from_iter(&["lev", "set", "-f", "foo", "DATABASE_URL", "A=b=c"]) {
+            Options::Set { vars, .. } => assert_eq!(
+                vars,
+                vec![
+                    ("DATABASE_URL".into(), None),
+                    ("A".into(), Some("b=c".into()))
+                ]
+            ),
+            other => panic!("expected set options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_secret_options() {
+        assert_eq!(
+            Options::Set {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                release: Release::default(),
+                wait: false,
+                kms_key_arn: None,
+                secrets: vec!["API_KEY".into()],
+                stdin: false,
+                files: Vec::new(),
+                template_vars: Vec::new(),
+                prefix: None,
+                encode_base64: vec!["API_KEY".into()],
+                json_merges: vec![("CONFIG".into(), serde_json::json!({"feature": {"x": true}}))],
+                force: false,
+                sanitize: false,
+                vars: vec![("bar".into(), Some("baz".into()))],
+            },
+            Options::from_iter(&[
+                "lev",
+                "set",
+                "-f",
+                "foo",
+                "--secret",
+                "API_KEY",
+                "--encode-base64",
+                "API_KEY",
+                "--json-merge",
+                r#"CONFIG={"feature":{"x":true}}"#,
+                "bar=baz"
+            ])
+        )
+    }
+
+    #[test]
+    fn set_tagged_options() {
+        assert_eq!(
+            Options::Set {
+                target: Target {
+                    tags: vec![("team".into(), "payments".into())],
+                    ..Target::default()
+                },
+                release: Release::default(),
+                wait: false,
+                kms_key_arn: None,
+                secrets: Vec::new(),
+                stdin: false,
+                files: vec!["app.env".into()],
+                template_vars: Vec::new(),
+                prefix: Some("MYAPP_".into()),
+                encode_base64: Vec::new(),
+                json_merges: Vec::new(),
+                force: false,
+                sanitize: false,
+                vars: vec![("bar".into(), Some("baz".into()))],
+            },
+            Options::from_iter(&[
+                "lev",
+                "set",
+                "--tag",
+                "team=payments",
+                "--file",
+                "app.env",
+                "--prefix",
+                "MYAPP_",
+                "bar=baz"
+            ])
+        )
+    }
+
+    #[test]
+    fn set_release_options() {
+        assert_eq!(
+            Options::Set {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                release: Release {
+                    qualifier: Some("live".into()),
+                    publish: true,
+                    repoint: true,
+                },
+                wait: true,
+                kms_key_arn: Some("arn:aws:kms:us-east-1:123456789012:key/abc".into()),
+                secrets: Vec::new(),
+                stdin: false,
+                files: Vec::new(),
+                template_vars: Vec::new(),
+                prefix: None,
+                encode_base64: Vec::new(),
+                json_merges: Vec::new(),
+                force: true,
+                sanitize: true,
+                vars: vec![("bar".into(), Some("baz".into()))],
+            },
+            Options::from_iter(&[
+                "lev",
+                "set",
+                "-f",
+                "foo",
+                "--qualifier",
+                "live",
+                "--publish",
+                "--repoint",
+                "--wait",
+                "--kms-key-arn",
+                "arn:aws:kms:us-east-1:123456789012:key/abc",
+                "--force",
+                "--sanitize",
+                "bar=baz"
+            ])
+        )
+    }
+
     #[test]
     fn unset_options() {
         assert_eq!(
             Options::Unset {
-                function: "foo".into(),
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                release: Release::default(),
+                wait: false,
+                prefixes: vec!["LEGACY_".into()],
+                force: false,
                 names: vec!["bar".into(), "baz".into()],
             },
-            Options::from_iter(&["lev", "unset", "-f", "foo", "bar", "baz"])
+            Options::from_iter(&["lev", "unset", "-f", "foo", "--prefix", "LEGACY_", "bar", "baz"])
+        )
+    }
+
+    #[test]
+    fn apply_options() {
+        assert_eq!(
+            Options::Apply {
+                manifest: "lev.yaml".into(),
+                parallel: 8,
+                plan: false,
+                wait: false,
+                force: true,
+            },
+            Options::from_iter(&["lev", "apply", "lev.yaml", "--parallel", "8", "--force"])
+        )
+    }
+
+    #[test]
+    fn plan_options() {
+        assert_eq!(
+            Options::Plan {
+                manifest: "lev.yaml".into(),
+                parallel: 4,
+                exit_code: 2,
+            },
+            Options::from_iter(&["lev", "plan", "lev.yaml"])
+        )
+    }
+
+    #[test]
+    fn diff_options() {
+        assert_eq!(
+            Options::Diff {
+                manifest: "lev.yaml".into(),
+                parallel: 4,
+                exit_code: 0,
+            },
+            Options::from_iter(&["lev", "diff", "lev.yaml", "--exit-code", "0"])
+        )
+    }
+
+    #[test]
+    fn versions_options() {
+        assert_eq!(
+            Options::Versions {
+                function: "foo".into(),
+                with_env_diff: true,
+            },
+            Options::from_iter(&["lev", "versions", "-f", "foo", "--with-env-diff"])
+        )
+    }
+
+    #[test]
+    fn history_options() {
+        assert_eq!(
+            Options::History {
+                function: "foo".into(),
+                limit: Some(5),
+                from_versions: true,
+            },
+            Options::from_iter(&[
+                "lev",
+                "history",
+                "-f",
+                "foo",
+                "--limit",
+                "5",
+                "--from-versions"
+            ])
+        )
+    }
+
+    #[test]
+    fn verify_options() {
+        assert_eq!(
+            Options::Verify {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                manifest: Some("lev.manifest.json".into()),
+                exit_code: 2,
+            },
+            Options::from_iter(&[
+                "lev",
+                "verify",
+                "-f",
+                "foo",
+                "--manifest",
+                "lev.manifest.json"
+            ])
+        )
+    }
+
+    #[test]
+    fn validate_options() {
+        assert_eq!(
+            Options::Validate {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                policy: Some("policy.yaml".into()),
+                exit_code: 0,
+            },
+            Options::from_iter(&[
+                "lev",
+                "validate",
+                "-f",
+                "foo",
+                "--policy",
+                "policy.yaml",
+                "--exit-code",
+                "0"
+            ])
+        )
+    }
+
+    #[test]
+    fn exec_options() {
+        assert_eq!(
+            Options::Exec {
+                function: "foo".into(),
+                qualifier: None,
+                no_decrypt: false,
+                command: vec!["npm".into(), "test".into(), "--".into(), "-x".into()],
+            },
+            Options::from_iter(&["lev", "exec", "-f", "foo", "--", "npm", "test", "--", "-x"])
+        );
+        assert!(Options::from_iter_safe(&["lev", "exec", "-f", "foo"]).is_err())
+    }
+
+    #[test]
+    fn exec_passes_env_and_status() {
+        let mut env = Env::new();
+        env.insert("LEV_EXEC_TEST".into(), "prod".into());
+        let command = |script: &str| vec!["sh".to_string(), "-c".into(), script.into()];
+        assert!(exec(&command(r#"test "$LEV_EXEC_TEST" = prod"#), env.clone()).is_ok());
+        match exec(&command("exit 3"), env) {
+            Err(Error::Exit(code)) => assert_eq!(code, 3),
+            other => panic!("expected exit status 3, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn edit_options() {
+        assert_eq!(
+            Options::Edit {
+                function: "foo".into(),
+                force: false,
+            },
+            Options::from_iter(&["lev", "edit", "-f", "foo"])
         )
     }
+
+    #[test]
+    fn tui_options() {
+        assert_eq!(
+            Options::Tui {
+                refresh: true,
+                force: false,
+            },
+            Options::from_iter(&["lev", "tui", "--refresh"])
+        )
+    }
+
+    #[test]
+    fn size_options() {
+        assert_eq!(
+            Options::Size {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+            },
+            Options::from_iter(&["lev", "size", "-f", "foo"])
+        )
+    }
+
+    #[test]
+    fn summary_options() {
+        assert_eq!(
+            Options::Summary {
+                stage: "prod".into(),
+                manifest: Some("prod.yml".into()),
+                parallel: 4,
+            },
+            Options::from_iter(&[
+                "lev",
+                "summary",
+                "--stage",
+                "prod",
+                "--manifest",
+                "prod.yml"
+            ])
+        )
+    }
+
+    #[test]
+    fn gc_options() {
+        assert_eq!(
+            Options::Gc {
+                max_age: Some(chrono::Duration::days(30)),
+                keep: Some(10),
+                types: vec![gc::Artifact::Journal],
+                dry_run: false,
+            },
+            Options::from_iter(&[
+                "lev",
+                "gc",
+                "--max-age",
+                "30d",
+                "--keep",
+                "10",
+                "--type",
+                "journal"
+            ])
+        )
+    }
+
+    #[test]
+    fn watch_options() {
+        assert_eq!(
+            Options::Watch {
+                target: Target {
+                    function: Some("orders-*".into()),
+                    ..Target::default()
+                },
+                interval: 10,
+                notify: true,
+            },
+            Options::from_iter(&[
+                "lev",
+                "watch",
+                "-f",
+                "orders-*",
+                "--interval",
+                "10",
+                "--notify"
+            ])
+        )
+    }
+
+    #[test]
+    fn rollback_options() {
+        assert_eq!(
+            Options::Rollback {
+                function: "foo".into(),
+                to: Some("2019-07-01T12:00:00Z".parse().unwrap()),
+                force: false,
+            },
+            Options::from_iter(&[
+                "lev",
+                "rollback",
+                "-f",
+                "foo",
+                "--to",
+                "2019-07-01T12:00:00+00:00"
+            ])
+        )
+    }
+
+    #[test]
+    fn backups_options() {
+        assert_eq!(
+            Options::Backups {
+                command: Backups::List {
+                    function: "foo".into()
+                },
+            },
+            Options::from_iter(&["lev", "backups", "list", "-f", "foo"])
+        );
+        assert_eq!(
+            Options::Backups {
+                command: Backups::Restore {
+                    function: "foo".into(),
+                    to: None,
+                    force: true,
+                },
+            },
+            Options::from_iter(&["lev", "backups", "restore", "-f", "foo", "--force"])
+        )
+    }
+
+    #[test]
+    fn move_options() {
+        assert_eq!(
+            Options::Move {
+                from: "foo".into(),
+                to: "bar".into(),
+                keys: vec!["A".into(), "B".into()],
+                force: false,
+            },
+            Options::from_iter(&["lev", "move", "--from", "foo", "--to", "bar", "A", "B"])
+        );
+        assert!(Options::from_iter_safe(&["lev", "move", "--from", "foo", "--to", "bar"]).is_err())
+    }
+
+    #[test]
+    fn copy_options() {
+        assert_eq!(
+            Options::Copy {
+                from: "foo".into(),
+                target: Target {
+                    function: Some("bar".into()),
+                    ..Target::default()
+                },
+                keys: vec![Pattern::new("DB_*").unwrap()],
+                exclude: vec![Pattern::new("*_SECRET").unwrap()],
+            },
+            Options::from_iter(&[
+                "lev",
+                "copy",
+                "--from",
+                "foo",
+                "-f",
+                "bar",
+                "--keys",
+                "DB_*",
+                "--exclude",
+                "*_SECRET"
+            ])
+        )
+    }
+
+    #[test]
+    fn import_options() {
+        assert_eq!(
+            Options::Import {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                secret: Some("foo/prod".into()),
+                url: None,
+                references: true,
+                source: None,
+            },
+            Options::from_iter(&[
+                "lev",
+                "import",
+                "-f",
+                "foo",
+                "--from-secret",
+                "foo/prod",
+                "--as-references"
+            ])
+        )
+    }
+
+    #[test]
+    fn import_url_options() {
+        assert_eq!(
+            Options::Import {
+                target: Target {
+                    function: Some("foo".into()),
+                    ..Target::default()
+                },
+                secret: None,
+                url: Some("https://config.example.com/foo.json".into()),
+                references: false,
+                source: None,
+            },
+            Options::from_iter(&[
+                "lev",
+                "import",
+                "-f",
+                "foo",
+                "--from-url",
+                "https://config.example.com/foo.json"
+            ])
+        );
+        assert!(Options::from_iter_safe(&["lev", "import", "-f", "foo"]).is_err())
+    }
+
+    #[test]
+    fn import_ecs_options() {
+        assert_eq!(
+            Options::Import {
+                target: Target::default(),
+                secret: None,
+                url: None,
+                references: false,
+                source: Some(ImportSource::Ecs {
+                    task_definition: "my-task:12".into(),
+                    container: Some("app".into()),
+                    to: "foo".into(),
+                    references: false,
+                }),
+            },
+            Options::from_iter(&[
+                "lev",
+                "import",
+                "ecs",
+                "--task-def",
+                "my-task:12",
+                "--container",
+                "app",
+                "--to",
+                "foo"
+            ])
+        );
+        assert!(
+            Options::from_iter_safe(&["lev", "import", "ecs", "--task-def", "my-task:12"]).is_err()
+        )
+    }
+
+    #[test]
+    fn import_serverless_options() {
+        assert_eq!(
+            Options::Import {
+                target: Target::default(),
+                secret: None,
+                url: None,
+                references: false,
+                source: Some(ImportSource::Serverless {
+                    file: "serverless.yml".into(),
+                    function: "handler".into(),
+                    stage: Some("prod".into()),
+                    to: None,
+                    references: false,
+                }),
+            },
+            Options::from_iter(&[
+                "lev",
+                "import",
+                "serverless",
+                "--stage",
+                "prod",
+                "--function",
+                "handler"
+            ])
+        );
+        let lev = Lev::from_iter(&[
+            "lev",
+            "--stage",
+            "prod",
+            "import",
+            "serverless",
+            "--function",
+            "handler",
+        ]);
+        assert_eq!(lev.stage, Some("prod".into()));
+        match lev.command {
+            Options::Import {
+                source: Some(ImportSource::Serverless { stage, .. }),
+                ..
+            } => assert_eq!(stage, None),
+            other => panic!("expected serverless import options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_sam_options() {
+        assert_eq!(
+            Options::Import {
+                target: Target::default(),
+                secret: None,
+                url: None,
+                references: false,
+                source: Some(ImportSource::Sam {
+                    template: "template.yaml".into(),
+                    logical_id: "MyFunction".into(),
+                    parameters: vec![("Stage".into(), "prod".into())],
+                    to: None,
+                    references: false,
+                }),
+            },
+            Options::from_iter(&[
+                "lev",
+                "import",
+                "sam",
+                "template.yaml",
+                "--logical-id",
+                "MyFunction",
+                "--parameter",
+                "Stage=prod"
+            ])
+        );
+        assert!(Options::from_iter_safe(&["lev", "import", "sam", "template.yaml"]).is_err())
+    }
+
+    #[test]
+    fn migrate_to_ssm_options() {
+        assert_eq!(
+            Options::MigrateToSsm {
+                function: "foo".into(),
+                prefix: "/app/prod/".into(),
+                keys: vec![Pattern::new("DB_*").unwrap()],
+            },
+            Options::from_iter(&[
+                "lev",
+                "migrate-to-ssm",
+                "-f",
+                "foo",
+                "--prefix",
+                "/app/prod/",
+                "--keys",
+                "DB_*"
+            ])
+        )
+    }
+
+    #[test]
+    fn with_function_goes_ahead_of_trailing_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            with_function(args(&["lev", "get"]), "foo"),
+            args(&["lev", "get", "-f", "foo"])
+        );
+        assert_eq!(
+            with_function(args(&["lev", "exec", "--", "env", "-f"]), "foo"),
+            args(&["lev", "exec", "-f", "foo", "--", "env", "-f"])
+        )
+    }
+
+    #[test]
+    fn migrate_to_secrets_options() {
+        assert_eq!(
+            Options::MigrateToSecrets {
+                function: "foo".into(),
+                secret: "foo/prod".into(),
+                keys: vec![Pattern::new("*_SECRET").unwrap()],
+                dry_run: true,
+                rollback: false,
+            },
+            Options::from_iter(&[
+                "lev",
+                "migrate-to-secrets",
+                "-f",
+                "foo",
+                "--secret",
+                "foo/prod",
+                "--keys",
+                "*_SECRET",
+                "--dry-run"
+            ])
+        )
+    }
+
+    #[test]
+    fn whoami_options() {
+        assert_eq!(Options::Whoami, Options::from_iter(&["lev", "whoami"]))
+    }
 }
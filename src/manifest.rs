@@ -0,0 +1,143 @@
+use crate::{
+    aws::{self, LambdaClient},
+    diff::{diff, Change},
+    each,
+    error::Error,
+    get, source, update, Env,
+};
+use serde::{de, Deserialize, Deserializer};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+/// Desired state for a set of functions
+#[derive(Deserialize, Debug, PartialEq, Default)]
+pub struct Manifest {
+    #[serde(default)]
+    pub functions: BTreeMap<String, Function>,
+}
+
+/// Desired state of a single function
+#[derive(Deserialize, Debug, PartialEq, Default)]
+pub struct Function {
+    #[serde(default, deserialize_with = "scalars")]
+    pub environment: Env,
+}
+
+/// Accepts yaml strings, numbers and booleans as env values
+fn scalars<'de, D>(deserializer: D) -> Result<Env, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(s) => Ok((key, s)),
+            Value::Number(n) => Ok((key, n.to_string())),
+            Value::Bool(b) => Ok((key, b.to_string())),
+            _ => Err(de::Error::custom(format!(
+                "{} must be a string, number or boolean",
+                key
+            ))),
+        })
+        .collect()
+}
+
+impl Manifest {
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(contents).map_err(Error::from)
+    }
+
+    /// Reads a manifest from a local path, an s3://bucket/key or an https:// url
+    pub async fn fetch(source: &str) -> Result<Self, Error> {
+        Manifest::parse(&String::from_utf8_lossy(&source::read(source).await?))
+    }
+}
+
+/// Changes required to bring a function's env in line with `desired`
+pub async fn plan(
+    lambda: LambdaClient,
+    function: String,
+    desired: Env,
+) -> Result<Vec<Change>, Error> {
+    let current = get(lambda, function).await?;
+    Ok(diff(&current, &desired))
+}
+
+/// Brings a function's env in line with `desired`, adding, changing and
+/// removing vars as needed, though protected vars only when `force`d.
+/// Functions already in sync are left untouched
+pub async fn reconcile(
+    lambda: LambdaClient,
+    function: String,
+    desired: Env,
+    force: bool,
+) -> Result<Vec<Change>, Error> {
+    let current = get(lambda.clone(), function.clone()).await?;
+    let changes = diff(&current, &desired);
+    if !changes.is_empty() {
+        update(lambda, function, &current, desired, force).await?;
+    }
+    Ok(changes)
+}
+
+/// Plans, or when `mutate` is set reconciles, every function in the manifest,
+/// removing protected vars only when `force`d
+pub async fn run(
+    manifest: Manifest,
+    parallel: usize,
+    mutate: bool,
+    force: bool,
+) -> Result<Vec<(String, Vec<Change>)>, Error> {
+    let functions = manifest.functions.keys().cloned().collect::<Vec<_>>();
+    let mut declared = manifest.functions;
+    each(functions, parallel, move |function| {
+        let desired = declared
+            .remove(&function)
+            .map(|declared| declared.environment)
+            .unwrap_or_default();
+        async move {
+            let lambda = aws::lambda_client().await;
+            if mutate {
+                reconcile(lambda, function, desired, force).await
+            } else {
+                plan(lambda, function, desired).await
+            }
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stringifies_scalar_values() {
+        let manifest = Manifest::parse(
+            "functions:\n  my-func:\n    environment:\n      FOO: bar\n      PORT: 8080\n      DEBUG: true\n",
+        )
+        .unwrap();
+        let env = &manifest.functions["my-func"].environment;
+        assert_eq!(env["FOO"], "bar");
+        assert_eq!(env["PORT"], "8080");
+        assert_eq!(env["DEBUG"], "true");
+    }
+
+    #[test]
+    fn parse_rejects_nested_values() {
+        assert!(
+            Manifest::parse("functions:\n  my-func:\n    environment:\n      FOO: [1, 2]\n")
+                .is_err()
+        )
+    }
+
+    #[test]
+    fn parse_defaults_missing_environment() {
+        assert_eq!(
+            Manifest::parse("functions:\n  my-func: {}\n")
+                .unwrap()
+                .functions["my-func"],
+            Function::default()
+        )
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{
+    aws::{self, LambdaClient, SecretsManagerClient, SsmClient},
+    configuration, env,
+    error::Error,
+    source, update, Env,
+};
+use aws_sdk_secretsmanager::operation::get_secret_value::GetSecretValueError;
+use aws_sdk_ssm::types::ParameterType;
+use futures::future;
+use glob::Pattern;
+use serde_json::{Map, Value};
+
+type Fields = Map<String, Value>;
+
+/// An env var whose value was moved into an external store
+#[derive(Debug, PartialEq)]
+pub struct Migrated {
+    pub key: String,
+    pub reference: String,
+}
+
+/// Parameter Store name for `key` nested under `prefix`
+fn parameter_name(
+    prefix: &str,
+    key: &str,
+) -> String {
+    format!("/{}/{}", prefix.trim_matches('/'), key).replace("//", "/")
+}
+
+/// `(region, account)` components of an ARN
+fn arn_scope(arn: &str) -> Option<(&str, &str)> {
+    let parts = arn.split(':').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["arn", _, _, region, account, ..] => Some((region, account)),
+        _ => None,
+    }
+}
+
+/// Values which already point at an external store are left in place
+fn is_reference(value: &str) -> bool {
+    value.starts_with("ssm://") || value.starts_with("secretsmanager://")
+}
+
+/// Env reference to a field of a consolidated secret
+fn secret_reference(
+    secret: &str,
+    key: &str,
+) -> String {
+    format!("secretsmanager://{}#{}", secret, key)
+}
+
+/// `current` with migrated values replaced by their references
+fn referenced(
+    current: Env,
+    migrated: &[Migrated],
+) -> Env {
+    current
+        .into_iter()
+        .map(|(k, v)| match migrated.iter().find(|m| m.key == k) {
+            Some(m) => (k, m.reference.clone()),
+            None => (k, v),
+        })
+        .collect()
+}
+
+/// Plain string form of a secret field
+pub fn field_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Env vars whose names match any of the provided patterns
+fn select(
+    env: &Env,
+    keys: &[Pattern],
+) -> Vec<(String, String)> {
+    let mut selected = env
+        .iter()
+        .filter(|(k, v)| keys.iter().any(|p| p.matches(k)) && !is_reference(v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<Vec<_>>();
+    selected.sort();
+    selected
+}
+
+/// Moves matching env values into Parameter Store as SecureStrings,
+/// replacing them with `ssm://` references. Yields the function's arn
+/// and the vars that were migrated
+pub async fn to_ssm(
+    lambda: LambdaClient,
+    ssm: SsmClient,
+    function: String,
+    prefix: String,
+    keys: Vec<Pattern>,
+) -> Result<(String, Vec<Migrated>), Error> {
+    let conf = configuration(lambda.clone(), function.clone()).await?;
+    let arn = conf.function_arn.clone().unwrap_or_default();
+    let current = env(conf);
+    let selected = select(&current, &keys);
+    let migrated = future::try_join_all(selected.into_iter().map(|(key, value)| {
+        let name = parameter_name(&prefix, &key);
+        let request = ssm
+            .put_parameter()
+            .name(name.clone())
+            .value(value)
+            .r#type(ParameterType::SecureString)
+            .overwrite(true);
+        async move {
+            aws::retry(|| request.clone().send()).await?;
+            Ok::<_, Error>(Migrated {
+                reference: format!("ssm://{}", name),
+                key,
+            })
+        }
+    }))
+    .await?;
+    if !migrated.is_empty() {
+        let updated = referenced(current.clone(), &migrated);
+        update(lambda, function, &current, updated, false).await?;
+    }
+    Ok((arn, migrated))
+}
+
+/// Current fields of a JSON secret, if the secret exists
+async fn secret_fields(
+    secrets: SecretsManagerClient,
+    secret: String,
+) -> Result<Option<Fields>, Error> {
+    let request = secrets.get_secret_value().secret_id(secret.clone());
+    match aws::retry(|| request.clone().send()).await {
+        Ok(value) => serde_json::from_str::<Fields>(&value.secret_string.unwrap_or_default())
+            .map(Some)
+            .map_err(|_| Error::InvalidSecret(secret)),
+        Err(err)
+            if err
+                .as_service_error()
+                .map(GetSecretValueError::is_resource_not_found_exception)
+                .unwrap_or(false) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes `fields` as the secret's current value, creating the secret if needed
+async fn write_secret(
+    secrets: SecretsManagerClient,
+    secret: String,
+    fields: Fields,
+    exists: bool,
+) -> Result<(), Error> {
+    let body = Value::Object(fields).to_string();
+    if exists {
+        let request = secrets
+            .put_secret_value()
+            .secret_id(secret)
+            .secret_string(body);
+        aws::retry(|| request.clone().send()).await?;
+    } else {
+        let request = secrets.create_secret().name(secret).secret_string(body);
+        aws::retry(|| request.clone().send()).await?;
+    }
+    Ok(())
+}
+
+/// Reverts a secret to its state before a failed migration
+async fn restore_secret(
+    secrets: SecretsManagerClient,
+    secret: String,
+    previous: Option<Fields>,
+) -> Result<(), Error> {
+    match previous {
+        Some(fields) => write_secret(secrets, secret, fields, true).await,
+        None => {
+            let request = secrets
+                .delete_secret()
+                .secret_id(secret)
+                .force_delete_without_recovery(true);
+            aws::retry(|| request.clone().send()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Moves matching env values into fields of a single JSON secret,
+/// replacing them with `secretsmanager://` references. If the function
+/// update fails, the secret is reverted. Yields the function's arn
+/// and the vars that were (or in a dry run, would be) migrated
+pub async fn to_secrets(
+    lambda: LambdaClient,
+    secrets: SecretsManagerClient,
+    function: String,
+    secret: String,
+    keys: Vec<Pattern>,
+    dry_run: bool,
+) -> Result<(String, Vec<Migrated>), Error> {
+    let (conf, previous) = future::try_join(
+        async { Ok(configuration(lambda.clone(), function.clone()).await?) },
+        secret_fields(secrets.clone(), secret.clone()),
+    )
+    .await?;
+    let arn = conf.function_arn.clone().unwrap_or_default();
+    let current = env(conf);
+    let selected = select(&current, &keys);
+    let migrated = selected
+        .iter()
+        .map(|(key, _)| Migrated {
+            key: key.clone(),
+            reference: secret_reference(&secret, key),
+        })
+        .collect::<Vec<_>>();
+    if dry_run || migrated.is_empty() {
+        return Ok((arn, migrated));
+    }
+    let mut fields = previous.clone().unwrap_or_default();
+    for (key, value) in selected {
+        fields.insert(key, Value::String(value));
+    }
+    let updated = referenced(current.clone(), &migrated);
+    write_secret(secrets.clone(), secret.clone(), fields, previous.is_some()).await?;
+    if let Err(err) = update(lambda, function, &current, updated, false).await {
+        let _ = restore_secret(secrets, secret, previous).await;
+        return Err(err);
+    }
+    Ok((arn, migrated))
+}
+
+/// Env vars for each field of a JSON secret, either holding the field's
+/// value or a `secretsmanager://` reference to it
+pub async fn secret_vars(
+    secrets: SecretsManagerClient,
+    secret: String,
+    references: bool,
+) -> Result<Vec<(String, String)>, Error> {
+    let fields = secret_fields(secrets, secret.clone())
+        .await?
+        .ok_or_else(|| Error::SecretNotFound(secret.clone()))?;
+    let mut vars = fields
+        .iter()
+        .map(|(key, value)| {
+            if references {
+                (key.clone(), secret_reference(&secret, key))
+            } else {
+                (key.clone(), field_value(value))
+            }
+        })
+        .collect::<Vec<_>>();
+    vars.sort();
+    Ok(vars)
+}
+
+/// Env vars from a JSON object read from a local path, an s3://bucket/key or
+/// an https:// url
+pub async fn source_vars(source: String) -> Result<Vec<(String, String)>, Error> {
+    let fields = serde_json::from_slice::<Fields>(&source::read(&source).await?)?;
+    let mut vars = fields
+        .iter()
+        .map(|(key, value)| (key.clone(), field_value(value)))
+        .collect::<Vec<_>>();
+    vars.sort();
+    Ok(vars)
+}
+
+/// Reverses a secrets migration, inlining secret field values back into
+/// the env for matching keys which reference `secret`. Yields the names
+/// of the vars that were (or in a dry run, would be) restored
+pub async fn from_secrets(
+    lambda: LambdaClient,
+    secrets: SecretsManagerClient,
+    function: String,
+    secret: String,
+    keys: Vec<Pattern>,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let (conf, fields) = future::try_join(
+        async { Ok(configuration(lambda.clone(), function.clone()).await?) },
+        secret_fields(secrets, secret.clone()),
+    )
+    .await?;
+    let fields = fields.unwrap_or_default();
+    let current = env(conf);
+    let mut restored = current
+        .iter()
+        .filter(|(k, v)| keys.iter().any(|p| p.matches(k)) && **v == secret_reference(&secret, k))
+        .filter_map(|(k, _)| fields.get(k).map(|v| (k.clone(), field_value(v))))
+        .collect::<Vec<_>>();
+    restored.sort();
+    let names = restored.iter().map(|(k, _)| k.clone()).collect();
+    if dry_run || restored.is_empty() {
+        return Ok(names);
+    }
+    let updated = current.clone().into_iter().chain(restored).collect();
+    update(lambda, function, &current, updated, false).await?;
+    Ok(names)
+}
+
+/// The manual follow up work required for function code to read
+/// migrated values from Parameter Store
+pub fn ssm_checklist(
+    arn: &str,
+    migrated: &[Migrated],
+) -> Vec<String> {
+    let (region, account) = arn_scope(arn).unwrap_or(("*", "*"));
+    let mut items = migrated
+        .iter()
+        .map(|m| {
+            format!(
+                "resolve {} in code by fetching parameter {} with WithDecryption=true",
+                m.key,
+                m.reference.trim_start_matches("ssm://")
+            )
+        })
+        .collect::<Vec<_>>();
+    if !migrated.is_empty() {
+        let resources = migrated
+            .iter()
+            .map(|m| {
+                format!(
+                    "arn:aws:ssm:{}:{}:parameter{}",
+                    region,
+                    account,
+                    m.reference.trim_start_matches("ssm://")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        items.push(format!(
+            "grant the function's execution role ssm:GetParameter on {}",
+            resources
+        ));
+        items.push(
+            "grant the function's execution role kms:Decrypt on the key encrypting the parameters (alias/aws/ssm by default)".into(),
+        );
+        items.push("deploy the code change before relying on the new env references".into());
+    }
+    items
+}
+
+/// The manual follow up work required for function code to read
+/// migrated values from a consolidated secret
+pub fn secrets_checklist(
+    arn: &str,
+    secret: &str,
+    migrated: &[Migrated],
+) -> Vec<String> {
+    let (region, account) = arn_scope(arn).unwrap_or(("*", "*"));
+    let mut items = migrated
+        .iter()
+        .map(|m| {
+            format!(
+                "resolve {} in code by reading field {} of secret {}",
+                m.key, m.key, secret
+            )
+        })
+        .collect::<Vec<_>>();
+    if !migrated.is_empty() {
+        items.push(format!(
+            "grant the function's execution role secretsmanager:GetSecretValue on arn:aws:secretsmanager:{}:{}:secret:{}-*",
+            region, account, secret
+        ));
+        items.push("deploy the code change before relying on the new env references".into());
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parameter_name_normalizes_slashes() {
+        assert_eq!(parameter_name("/app/prod/", "DB_URL"), "/app/prod/DB_URL");
+        assert_eq!(parameter_name("app/prod", "DB_URL"), "/app/prod/DB_URL");
+        assert_eq!(parameter_name("", "DB_URL"), "/DB_URL");
+    }
+
+    #[test]
+    fn select_matches_patterns_and_skips_references() {
+        let mut env = HashMap::new();
+        env.insert("DB_HOST".to_string(), "localhost".to_string());
+        env.insert("DB_PASS".to_string(), "ssm:///app/DB_PASS".to_string());
+        env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+        assert_eq!(
+            select(&env, &[Pattern::new("DB_*").unwrap()]),
+            vec![("DB_HOST".to_string(), "localhost".to_string())]
+        )
+    }
+
+    #[test]
+    fn referenced_replaces_only_migrated_values() {
+        let mut env = HashMap::new();
+        env.insert("API_SECRET".to_string(), "shh".to_string());
+        env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+        let updated = referenced(
+            env,
+            &[Migrated {
+                key: "API_SECRET".into(),
+                reference: secret_reference("my-func/prod", "API_SECRET"),
+            }],
+        );
+        assert_eq!(
+            updated.get("API_SECRET").map(String::as_str),
+            Some("secretsmanager://my-func/prod#API_SECRET")
+        );
+        assert_eq!(updated.get("LOG_LEVEL").map(String::as_str), Some("debug"))
+    }
+
+    #[test]
+    fn field_value_stringifies_non_strings() {
+        assert_eq!(field_value(&Value::String("foo".into())), "foo");
+        assert_eq!(field_value(&Value::Bool(true)), "true")
+    }
+
+    #[test]
+    fn ssm_checklist_scopes_grants_to_function_account() {
+        let items = ssm_checklist(
+            "arn:aws:lambda:us-east-1:123456789012:function:my-func",
+            &[Migrated {
+                key: "DB_HOST".into(),
+                reference: "ssm:///app/prod/DB_HOST".into(),
+            }],
+        );
+        assert_eq!(items.len(), 4);
+        assert!(items[1].contains("arn:aws:ssm:us-east-1:123456789012:parameter/app/prod/DB_HOST"))
+    }
+}
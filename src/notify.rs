@@ -0,0 +1,38 @@
+use crate::error::Error;
+use reqwest::Client;
+use serde_json::json;
+
+/// Posts messages to an incoming webhook accepting `{"text": ...}` payloads
+#[derive(Clone)]
+pub struct Notifier {
+    client: Client,
+    url: String,
+}
+
+impl Notifier {
+    pub fn new<U>(url: U) -> Self
+    where
+        U: Into<String>,
+    {
+        Notifier {
+            client: Client::new(),
+            url: url.into(),
+        }
+    }
+
+    pub async fn send<M>(
+        &self,
+        message: M,
+    ) -> Result<(), Error>
+    where
+        M: Into<String>,
+    {
+        self.client
+            .post(&self.url)
+            .json(&json!({ "text": message.into() }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
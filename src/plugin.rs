@@ -0,0 +1,71 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name an executable extends lev with, if named `<prefix><name>`
+fn plugin_name(
+    prefix: &str,
+    path: &Path,
+) -> Option<String> {
+    let name = path.file_stem()?.to_str()?.strip_prefix(prefix)?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+fn search(
+    prefix: &str,
+    path: Option<OsString>,
+) -> Vec<(String, PathBuf)> {
+    let dirs = path.map(|p| env::split_paths(&p).collect::<Vec<_>>());
+    dirs.unwrap_or_default()
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|program| plugin_name(prefix, &program).map(|name| (name, program)))
+        .collect()
+}
+
+/// Executables on the PATH named `<prefix><name>`, along with their names
+pub fn discover(prefix: &str) -> Vec<(String, PathBuf)> {
+    search(prefix, env::var_os("PATH"))
+}
+
+/// The executable on the PATH named `<prefix><name>`, if installed. Cheaper
+/// than discovering every plugin when the name is already known
+pub fn locate(
+    prefix: &str,
+    name: &str,
+) -> Option<PathBuf> {
+    let program = format!("{}{}{}", prefix, name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&program))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_name_strips_prefix() {
+        assert_eq!(
+            plugin_name(
+                "lev-resolve-",
+                Path::new("/usr/local/bin/lev-resolve-vault")
+            ),
+            Some("vault".into())
+        );
+        assert_eq!(
+            plugin_name("lev-resolve-", Path::new("/usr/bin/lev-resolve-")),
+            None
+        );
+        assert_eq!(plugin_name("lev-resolve-", Path::new("/usr/bin/lev")), None)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{error::Error, source, Env};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Policy every update of an env is checked against before it is made
+static POLICY: Mutex<Option<Arc<Policy>>> = Mutex::new(None);
+
+/// Has every command which changes an env refuse changes leaving it in
+/// violation of `policy`
+pub fn set(policy: Option<Arc<Policy>>) {
+    *POLICY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = policy
+}
+
+/// A policy as a yaml file declares it
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Declared {
+    required: Vec<String>,
+    forbidden: Vec<String>,
+    values: BTreeMap<String, String>,
+}
+
+/// What an organization requires of the env of every function
+#[derive(Debug)]
+pub struct Policy {
+    /// Vars every env must define
+    required: Vec<String>,
+    /// Vars no env may define
+    forbidden: Vec<String>,
+    /// Patterns the whole value of a var must match, when it is set
+    values: Vec<(String, Regex)>,
+}
+
+impl Policy {
+    pub fn parse(
+        source: &str,
+        contents: &[u8],
+    ) -> Result<Self, Error> {
+        let invalid = |reason: String| Error::InvalidPolicy(source.into(), reason);
+        let declared: Declared =
+            serde_yaml::from_slice(contents).map_err(|err| invalid(err.to_string()))?;
+        let values = declared
+            .values
+            .into_iter()
+            .map(|(key, pattern)| {
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .map(|regex| (key.clone(), regex))
+                    .map_err(|err| invalid(format!("pattern of {}: {}", key, err)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Policy {
+            required: declared.required,
+            forbidden: declared.forbidden,
+            values,
+        })
+    }
+
+    /// Reads a policy from a local path, an s3://bucket/key or an https:// url
+    pub async fn fetch(source: &str) -> Result<Self, Error> {
+        Policy::parse(source, &source::read(source).await?)
+    }
+
+    /// Every way an env falls short of the policy
+    pub fn violations(
+        &self,
+        env: &Env,
+    ) -> Vec<String> {
+        let missing = self
+            .required
+            .iter()
+            .filter(|key| !env.contains_key(*key))
+            .map(|key| format!("missing required {}", key));
+        let forbidden = self
+            .forbidden
+            .iter()
+            .filter(|key| env.contains_key(*key))
+            .map(|key| format!("sets forbidden {}", key));
+        let mismatched = self.values.iter().filter_map(|(key, regex)| {
+            env.get(key)
+                .filter(|value| !regex.is_match(value))
+                .map(|_| format!("{} does not match `{}`", key, pattern(regex)))
+        });
+        missing.chain(forbidden).chain(mismatched).collect()
+    }
+}
+
+/// The pattern a value regex was declared with, without the anchors added
+/// to have it match whole values
+fn pattern(regex: &Regex) -> &str {
+    let anchored = regex.as_str();
+    anchored
+        .strip_prefix("^(?:")
+        .and_then(|pattern| pattern.strip_suffix(")$"))
+        .unwrap_or(anchored)
+}
+
+/// Refuses updates leaving a function's env in violation of the policy set,
+/// if there is one
+pub fn check(
+    function: &str,
+    updated: &Env,
+) -> Result<(), Error> {
+    let policy = POLICY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    match policy.map(|policy| policy.violations(updated)) {
+        Some(violations) if !violations.is_empty() => Err(Error::PolicyViolation(
+            function.into(),
+            violations.join(", "),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    const POLICY: &[u8] = b"
+required: [LOG_LEVEL, SERVICE]
+forbidden: [DEBUG]
+values:
+  LOG_LEVEL: debug|info|warn|error
+";
+
+    #[test]
+    fn violations_names_every_shortfall() {
+        let policy = Policy::parse("policy.yaml", POLICY).unwrap();
+        assert!(policy
+            .violations(&test_env(&[("LOG_LEVEL", "info"), ("SERVICE", "api")]))
+            .is_empty());
+        assert_eq!(
+            policy.violations(&test_env(&[("LOG_LEVEL", "verbose"), ("DEBUG", "1")])),
+            vec![
+                "missing required SERVICE".to_string(),
+                "sets forbidden DEBUG".to_string(),
+                "LOG_LEVEL does not match `debug|info|warn|error`".to_string(),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_refuses_invalid_policies() {
+        match Policy::parse("policy.yaml", b"values:\n  LOG_LEVEL: \"(\"\n") {
+            Err(Error::InvalidPolicy(source, _)) => assert_eq!(source, "policy.yaml"),
+            other => panic!("expected an invalid policy error, got {:?}", other),
+        }
+        match Policy::parse("policy.yaml", b"banned: [DEBUG]\n") {
+            Err(Error::InvalidPolicy(source, _)) => assert_eq!(source, "policy.yaml"),
+            other => panic!("expected an invalid policy error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{error::Error, Env};
+use regex::{Regex, RegexBuilder};
+use std::sync::Mutex;
+
+/// What a masked value is printed as
+pub const MASK: &str = "****";
+
+/// Keys whose values are masked wherever lev prints them
+static KEYS: Mutex<Vec<Regex>> = Mutex::new(Vec::new());
+
+/// Masks the values of keys matching any of `patterns` in everything lev
+/// prints. Patterns match whole keys, ignoring case
+pub fn set_keys(patterns: &[String]) -> Result<(), Error> {
+    let keys = patterns
+        .iter()
+        .map(|pattern| {
+            RegexBuilder::new(&format!("^(?:{})$", pattern))
+                .case_insensitive(true)
+                .build()
+                .map_err(|err| Error::InvalidPattern(pattern.clone(), err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    *KEYS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = keys;
+    Ok(())
+}
+
+/// Whether a key's value is masked wherever it is printed
+pub fn redacted(key: &str) -> bool {
+    KEYS.lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .any(|pattern| pattern.is_match(key))
+}
+
+/// A value as it may be printed
+pub fn value<'a>(
+    key: &str,
+    value: &'a str,
+) -> &'a str {
+    if redacted(key) && !value.is_empty() {
+        MASK
+    } else {
+        value
+    }
+}
+
+/// An env with every value masked, leaving empty values as they are since
+/// they have nothing to hide
+pub fn mask(mut env: Env) -> Env {
+    for value in env.values_mut() {
+        if !value.is_empty() {
+            *value = MASK.into();
+        }
+    }
+    env
+}
+
+/// An env with the values of redacted keys masked
+pub fn redact(mut env: Env) -> Env {
+    for (key, value) in env.iter_mut() {
+        if redacted(key) && !value.is_empty() {
+            *value = MASK.into();
+        }
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_hides_values() {
+        let mut env = Env::new();
+        env.insert("API_KEY".into(), "s3cr3t".into());
+        env.insert("EMPTY".into(), "".into());
+        let masked = mask(env);
+        assert_eq!(masked["API_KEY"], MASK);
+        assert_eq!(masked["EMPTY"], "")
+    }
+
+    #[test]
+    fn set_keys_masks_matching_keys() {
+        set_keys(&[".*_LEV_REDACT_TEST|OTHER_LEV_REDACT_TEST".into()]).unwrap();
+        assert!(redacted("api_lev_redact_test"));
+        assert!(!redacted("LEV_REDACT_TEST_NOT"));
+        assert_eq!(value("MY_LEV_REDACT_TEST", "s3cr3t"), MASK);
+        assert_eq!(value("LOG_LEVEL", "debug"), "debug");
+        set_keys(&[]).unwrap();
+        assert!(!redacted("MY_LEV_REDACT_TEST"))
+    }
+
+    #[test]
+    fn set_keys_rejects_invalid_patterns() {
+        match set_keys(&["(TOKEN".into()]) {
+            Err(Error::InvalidPattern(pattern, _)) => assert_eq!(pattern, "(TOKEN"),
+            other => panic!("expected an invalid pattern error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,978 @@
+use crate::{edit, error::Error, plugin, Env};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+use structopt::StructOpt;
+
+/// Name prefix of executables which render envs in an extra format
+const PLUGIN_PREFIX: &str = "lev-render-";
+
+/// Selects the format envs are printed in
+#[derive(StructOpt, PartialEq, Debug, Clone, Default)]
+pub struct Output {
+    /// Output format: plain, print0, dotenv, shell, json, yaml, csv,
+    /// k8s-configmap, k8s-secret, tf, tfvars, docker-args, or that of a
+    /// lev-render-<format> plugin. Defaults to plain, or the format of the
+    /// `[output]` of a .lev.toml
+    #[structopt(long = "output", short = "o")]
+    pub format: Option<String>,
+    /// Name of the ConfigMap or Secret k8s formats emit, defaulting to the
+    /// function's name
+    #[structopt(long = "name")]
+    pub name: Option<String>,
+    /// Prints plain values as they are, rather than double quoting and
+    /// escaping those spanning lines
+    #[structopt(long = "raw")]
+    pub raw: bool,
+    /// Prints `KEY=value` records ending in a NUL rather than a newline, in
+    /// place of --output, to be read by `xargs -0` and the like
+    #[structopt(long = "print0", short = "0")]
+    pub print0: bool,
+}
+
+/// Formats the envs of one or more functions for printing
+pub trait Renderer: Send + Sync {
+    /// Name `--output` selects this renderer by
+    fn name(&self) -> &str;
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error>;
+
+    /// Caveats about how faithfully `render` represents the envs, printed to
+    /// stderr alongside them
+    fn warnings(
+        &self,
+        _envs: &[(String, Env)],
+    ) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// An env in key order, so that output is the same from one run to the next
+fn sorted(env: &Env) -> BTreeMap<&String, &String> {
+    env.iter().collect()
+}
+
+/// Envs by function name, each in key order
+fn by_function(envs: &[(String, Env)]) -> BTreeMap<&str, BTreeMap<&String, &String>> {
+    envs.iter()
+        .map(|(function, env)| (function.as_str(), sorted(env)))
+        .collect()
+}
+
+/// Why a value can not be told apart from its neighbours in `KEY=value`
+/// lines, if it can't
+fn ambiguities(value: &str) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+    if value.contains('\n') || value.contains('\r') {
+        reasons.push("a newline");
+    }
+    if value.contains('=') {
+        reasons.push("`=`");
+    }
+    if value.trim() != value {
+        reasons.push("leading or trailing whitespace");
+    } else if value.contains(char::is_whitespace) {
+        reasons.push("whitespace");
+    }
+    reasons
+}
+
+/// `KEY=value` lines, headed by the function's name when there are several.
+/// Values spanning lines, or which would not otherwise read back as they
+/// are, are double quoted and escaped as dotenv values are, unless `raw`
+pub struct Plain {
+    pub raw: bool,
+}
+
+impl Renderer for Plain {
+    fn name(&self) -> &str {
+        "plain"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (i, (function, env)) in envs.iter().enumerate() {
+            if envs.len() > 1 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("# {}\n", function));
+            }
+            for (k, v) in sorted(env) {
+                if self.raw {
+                    out.push_str(&format!("{}={}\n", k, v));
+                } else {
+                    out.push_str(&edit::line(k, v));
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn warnings(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (function, env) in envs {
+            for (key, value) in sorted(env) {
+                if !self.raw && edit::needs_quotes(value) {
+                    continue;
+                }
+                let reasons = ambiguities(value);
+                if reasons.is_empty() {
+                    continue;
+                }
+                let name = if envs.len() > 1 {
+                    format!("{}: {}", function, key)
+                } else {
+                    key.clone()
+                };
+                warnings.push(format!(
+                    "{} has a value containing {}",
+                    name,
+                    reasons.join(", ")
+                ));
+            }
+        }
+        if !warnings.is_empty() {
+            warnings.push(
+                "plain output can not represent these values unambiguously, \
+                 use --output dotenv, shell, json or yaml to read them reliably"
+                    .into(),
+            );
+        }
+        warnings
+    }
+}
+
+/// `KEY=value` records, values as they are, each ending in a NUL, which
+/// neither keys nor values can hold. Records of several functions are
+/// prefixed with `<function>:`, as function names can't hold a colon either
+pub struct Print0;
+
+impl Renderer for Print0 {
+    fn name(&self) -> &str {
+        "print0"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (function, env) in envs {
+            for (k, v) in sorted(env) {
+                if envs.len() > 1 {
+                    out.push_str(&format!("{}:", function));
+                }
+                out.push_str(&format!("{}={}\0", k, v));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// A single function's env as an object, or several keyed by function name
+pub struct Json;
+
+impl Renderer for Json {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let json = match envs {
+            [(_, env)] => serde_json::to_string_pretty(&sorted(env))?,
+            _ => serde_json::to_string_pretty(&by_function(envs))?,
+        };
+        Ok(json + "\n")
+    }
+}
+
+/// A single function's env as a mapping, or several keyed by function name
+pub struct Yaml;
+
+impl Renderer for Yaml {
+    fn name(&self) -> &str {
+        "yaml"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let yaml = match envs {
+            [(_, env)] => serde_yaml::to_string(&sorted(env)),
+            _ => serde_yaml::to_string(&by_function(envs)),
+        };
+        yaml.map(|yaml| yaml + "\n")
+            .map_err(|err| Error::Render(self.name().into(), err.to_string()))
+    }
+}
+
+/// Kind of kubernetes object an env is emitted as
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    ConfigMap,
+    /// Holds base64 encoded values
+    Secret,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Object<'a> {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: Metadata,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    secret_type: Option<&'static str>,
+    data: BTreeMap<&'a str, String>,
+}
+
+#[derive(Serialize)]
+struct Metadata {
+    name: String,
+}
+
+/// A valid kubernetes object name derived from a function name or arn
+fn object_name(function: &str) -> String {
+    let name = function.rsplit(':').next().unwrap_or(function);
+    name.to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .trim_matches(|c| c == '-' || c == '.')
+        .to_string()
+}
+
+/// A ready to apply ConfigMap or Secret manifest per function, as yaml
+/// documents. Several functions sharing a `--name` are told apart by their
+/// names being appended to it
+pub struct Kubernetes {
+    pub kind: Kind,
+    pub name: Option<String>,
+}
+
+impl Renderer for Kubernetes {
+    fn name(&self) -> &str {
+        match self.kind {
+            Kind::ConfigMap => "k8s-configmap",
+            Kind::Secret => "k8s-secret",
+        }
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (function, env) in envs {
+            let name = match &self.name {
+                Some(name) if envs.len() > 1 => format!("{}-{}", name, object_name(function)),
+                Some(name) => name.clone(),
+                None => object_name(function),
+            };
+            let data = env
+                .iter()
+                .map(|(key, value)| {
+                    let value = match self.kind {
+                        Kind::ConfigMap => value.clone(),
+                        Kind::Secret => base64::encode(value),
+                    };
+                    (key.as_str(), value)
+                })
+                .collect();
+            let object = Object {
+                api_version: "v1",
+                kind: match self.kind {
+                    Kind::ConfigMap => "ConfigMap",
+                    Kind::Secret => "Secret",
+                },
+                metadata: Metadata { name },
+                secret_type: match self.kind {
+                    Kind::ConfigMap => None,
+                    Kind::Secret => Some("Opaque"),
+                },
+                data,
+            };
+            let yaml = serde_yaml::to_string(&object)
+                .map_err(|err| Error::Render(self.name().into(), err.to_string()))?;
+            out.push_str(&yaml);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// A single quoted word a posix shell reads back as `value`, whatever it holds
+fn single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// A word a posix shell reads back as `value`, single quoted unless it only
+/// holds characters the shell leaves alone
+fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c));
+    if safe {
+        value.to_string()
+    } else {
+        single_quote(value)
+    }
+}
+
+/// `export KEY='value'` lines safe to `eval`, headed by the function's name
+/// when there are several
+pub struct Shell;
+
+impl Renderer for Shell {
+    fn name(&self) -> &str {
+        "shell"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (i, (function, env)) in envs.iter().enumerate() {
+            if envs.len() > 1 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("# {}\n", function));
+            }
+            for (key, value) in sorted(env) {
+                out.push_str(&format!("export {}={}\n", key, single_quote(value)));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Sorted dotenv lines `set --stdin` reads back as they are, headed by the
+/// function's name when there are several
+pub struct Dotenv;
+
+impl Renderer for Dotenv {
+    fn name(&self) -> &str {
+        "dotenv"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (i, (function, env)) in envs.iter().enumerate() {
+            if envs.len() > 1 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("# {}\n", function));
+            }
+            for (key, value) in sorted(env) {
+                out.push_str(&edit::line(key, value));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// A csv field, double quoted with its quotes doubled when it holds a comma,
+/// quote or line break, as RFC 4180 has it
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| ",\"\r\n".contains(c)) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A `function,key,value` header followed by a row per var of every function,
+/// for spreadsheets and inventories
+pub struct Csv;
+
+impl Renderer for Csv {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::from("function,key,value\r\n");
+        for (function, env) in by_function(envs) {
+            for (key, value) in env {
+                out.push_str(&format!(
+                    "{},{},{}\r\n",
+                    csv_field(function),
+                    csv_field(key),
+                    csv_field(value)
+                ));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// `docker run` flags passing a function's env to a container, a line per
+/// function headed by its name when there are several
+pub struct DockerArgs;
+
+impl Renderer for DockerArgs {
+    fn name(&self) -> &str {
+        "docker-args"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (i, (function, env)) in envs.iter().enumerate() {
+            if envs.len() > 1 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("# {}\n", function));
+            }
+            let sorted = sorted(env);
+            let flags = sorted
+                .into_iter()
+                .map(|(key, value)| format!("-e {}", shell_quote(&format!("{}={}", key, value))))
+                .collect::<Vec<_>>();
+            out.push_str(&flags.join(" "));
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// A quoted HCL string, escaping what terraform would otherwise interpolate
+fn hcl_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                quoted.push(c);
+                quoted.push(c)
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// An HCL object key, quoted unless it is a valid identifier
+fn hcl_key(key: &str) -> String {
+    let identifier = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if identifier {
+        key.to_string()
+    } else {
+        hcl_string(key)
+    }
+}
+
+/// A sorted env as the lines of an HCL object
+fn hcl_object(
+    env: &BTreeMap<&String, &String>,
+    indent: usize,
+) -> String {
+    let mut out = String::from("{\n");
+    for (key, value) in env {
+        out.push_str(&format!(
+            "{:indent$}{} = {}\n",
+            "",
+            hcl_key(key),
+            hcl_string(value),
+            indent = indent + 2
+        ));
+    }
+    out.push_str(&format!("{:indent$}}}", "", indent = indent));
+    out
+}
+
+/// A terraform `environment` block of an `aws_lambda_function` resource per
+/// function, headed by the function's name when there are several
+pub struct Terraform;
+
+impl Renderer for Terraform {
+    fn name(&self) -> &str {
+        "tf"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let mut out = String::new();
+        for (i, (function, env)) in envs.iter().enumerate() {
+            if envs.len() > 1 {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("# {}\n", function));
+            }
+            out.push_str(&format!(
+                "environment {{\n  variables = {}\n}}\n",
+                hcl_object(&sorted(env), 2)
+            ));
+        }
+        Ok(out)
+    }
+}
+
+/// A tfvars `environment` map, or an `environments` map of them keyed by
+/// function name when there are several
+pub struct Tfvars;
+
+impl Renderer for Tfvars {
+    fn name(&self) -> &str {
+        "tfvars"
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        if let [(_, env)] = envs {
+            return Ok(format!("environment = {}\n", hcl_object(&sorted(env), 0)));
+        }
+        let mut out = String::from("environments = {\n");
+        for (function, env) in by_function(envs) {
+            out.push_str(&format!(
+                "  {} = {}\n",
+                hcl_string(function),
+                hcl_object(&env, 2)
+            ));
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+}
+
+/// An executable named `lev-render-<format>`. It is passed a JSON object of
+/// envs keyed by function name on stdin and prints them in its format
+pub struct Plugin {
+    name: String,
+    program: PathBuf,
+}
+
+impl Renderer for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn render(
+        &self,
+        envs: &[(String, Env)],
+    ) -> Result<String, Error> {
+        let input = serde_json::to_vec(&by_function(envs))?;
+        let mut child = Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&input)?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(Error::Render(
+                self.name.clone(),
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Renderers by name. The first renderer registered under a name wins, and
+/// plugins are only looked for when no registered renderer has the name
+#[derive(Clone, Default)]
+pub struct Renderers {
+    renderers: Vec<Arc<dyn Renderer>>,
+}
+
+impl Renderers {
+    /// Every built in renderer, configured by an output's options
+    pub fn new(output: &Output) -> Self {
+        let mut renderers = Renderers::default();
+        renderers.register(Plain { raw: output.raw });
+        renderers.register(Print0);
+        renderers.register(Shell);
+        renderers.register(Dotenv);
+        renderers.register(Json);
+        renderers.register(Yaml);
+        renderers.register(Terraform);
+        renderers.register(Tfvars);
+        renderers.register(DockerArgs);
+        renderers.register(Csv);
+        for kind in &[Kind::ConfigMap, Kind::Secret] {
+            renderers.register(Kubernetes {
+                kind: *kind,
+                name: output.name.clone(),
+            });
+        }
+        renderers
+    }
+
+    pub fn register<R>(
+        &mut self,
+        renderer: R,
+    ) where
+        R: Renderer + 'static,
+    {
+        self.renderers.push(Arc::new(renderer))
+    }
+
+    /// The renderer an output selects
+    pub fn find(
+        &self,
+        output: &Output,
+    ) -> Result<Arc<dyn Renderer>, Error> {
+        let name = if output.print0 {
+            "print0"
+        } else {
+            output.format.as_deref().unwrap_or("plain")
+        };
+        if let Some(renderer) = self.renderers.iter().find(|r| r.name() == name) {
+            return Ok(renderer.clone());
+        }
+        if let Some(program) = plugin::locate(PLUGIN_PREFIX, name) {
+            return Ok(Arc::new(Plugin {
+                name: name.to_string(),
+                program,
+            }));
+        }
+        let mut names = self
+            .renderers
+            .iter()
+            .map(|renderer| renderer.name().to_string())
+            .chain(
+                plugin::discover(PLUGIN_PREFIX)
+                    .into_iter()
+                    .map(|(name, _)| name),
+            )
+            .collect::<Vec<_>>();
+        names.dedup();
+        Err(Error::UnknownOutput(name.to_string(), names.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envs(functions: &[&str]) -> Vec<(String, Env)> {
+        functions
+            .iter()
+            .map(|function| {
+                let mut env = Env::new();
+                env.insert("FOO".into(), "bar".into());
+                (function.to_string(), env)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plain_headers_only_multiple_functions() {
+        let plain = Plain { raw: false };
+        assert_eq!(plain.render(&envs(&["a"])).unwrap(), "FOO=bar\n");
+        assert_eq!(
+            plain.render(&envs(&["a", "b"])).unwrap(),
+            "# a\nFOO=bar\n\n# b\nFOO=bar\n"
+        )
+    }
+
+    #[test]
+    fn renders_keys_in_order() {
+        let env = ["C", "A", "B", "E", "D"]
+            .iter()
+            .map(|key| (key.to_string(), "x".to_string()))
+            .collect::<Env>();
+        let envs = [("a".into(), env.clone()), ("b".into(), env)];
+        let single = &envs[..1];
+        assert_eq!(
+            Plain { raw: false }.render(single).unwrap(),
+            "A=x\nB=x\nC=x\nD=x\nE=x\n"
+        );
+        assert_eq!(
+            Json.render(single).unwrap(),
+            format!(
+                "{}\n",
+                serde_json::to_string_pretty(&serde_json::json!({
+                    "A": "x", "B": "x", "C": "x", "D": "x", "E": "x"
+                }))
+                .unwrap()
+            )
+        );
+        let yaml = Yaml.render(&envs).unwrap();
+        let keys = |function: &str| {
+            yaml.split(&format!("{}:", function))
+                .nth(1)
+                .unwrap()
+                .lines()
+                .filter_map(|line| line.trim().split(':').next())
+                .filter(|key| key.len() == 1)
+                .take(5)
+                .collect::<String>()
+        };
+        assert_eq!(keys("a"), "ABCDE");
+        assert_eq!(keys("b"), "ABCDE")
+    }
+
+    #[test]
+    fn print0_ends_records_in_nul() {
+        let mut env = Env::new();
+        env.insert("B".into(), "line\nbreak".into());
+        env.insert("A".into(), "plain".into());
+        assert_eq!(
+            Print0.render(&[("a".into(), env.clone())]).unwrap(),
+            "A=plain\0B=line\nbreak\0"
+        );
+        assert_eq!(
+            Print0
+                .render(&[("a".into(), env.clone()), ("b".into(), env)])
+                .unwrap(),
+            "a:A=plain\0a:B=line\nbreak\0b:A=plain\0b:B=line\nbreak\0"
+        );
+        let output = Output {
+            print0: true,
+            ..Output::default()
+        };
+        assert_eq!(
+            Renderers::new(&output).find(&output).unwrap().name(),
+            "print0"
+        )
+    }
+
+    #[test]
+    fn dotenv_quotes_values_which_would_not_read_back() {
+        let mut env = Env::new();
+        env.insert("B".into(), "line\nbreak".into());
+        env.insert("A".into(), "plain".into());
+        let rendered = Dotenv.render(&[("a".into(), env.clone())]).unwrap();
+        assert_eq!(rendered, "A=plain\nB=\"line\\nbreak\"\n");
+        assert_eq!(edit::parse(&rendered).ok(), Some(env))
+    }
+
+    #[test]
+    fn plain_escapes_values_spanning_lines_unless_raw() {
+        let mut env = Env::new();
+        env.insert("CERT".into(), "-----BEGIN-----\nMII\n".into());
+        let envs = [("a".into(), env)];
+        assert_eq!(
+            Plain { raw: false }.render(&envs).unwrap(),
+            "CERT=\"-----BEGIN-----\\nMII\\n\"\n"
+        );
+        assert_eq!(
+            Plain { raw: true }.render(&envs).unwrap(),
+            "CERT=-----BEGIN-----\nMII\n\n"
+        )
+    }
+
+    #[test]
+    fn plain_warns_of_ambiguous_values() {
+        let mut env = Env::new();
+        env.insert("A".into(), "plain".into());
+        env.insert("B".into(), "a=b c".into());
+        env.insert("C".into(), "line\nbreak ".into());
+        let warnings = Plain { raw: true }.warnings(&[("a".into(), env.clone())]);
+        assert_eq!(
+            warnings[..2],
+            [
+                "B has a value containing `=`, whitespace".to_string(),
+                "C has a value containing a newline, leading or trailing whitespace".to_string(),
+            ]
+        );
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(Plain { raw: false }.warnings(&[("a".into(), env)]).len(), 2);
+        assert!(Plain { raw: true }.warnings(&envs(&["a"])).is_empty());
+        assert!(Json
+            .warnings(&[(
+                "a".into(),
+                [("B".into(), "a b".into())].iter().cloned().collect()
+            )])
+            .is_empty())
+    }
+
+    #[test]
+    fn json_keys_multiple_functions_by_name() {
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&Json.render(&envs(&["a"])).unwrap())
+                .unwrap(),
+            serde_json::json!({ "FOO": "bar" })
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&Json.render(&envs(&["a", "b"])).unwrap())
+                .unwrap(),
+            serde_json::json!({ "a": { "FOO": "bar" }, "b": { "FOO": "bar" } })
+        )
+    }
+
+    #[test]
+    fn csv_quotes_fields_which_need_it() {
+        let mut env = Env::new();
+        env.insert("B".into(), "say \"hi\", twice".into());
+        env.insert("A".into(), "line\nbreak".into());
+        env.insert("C".into(), "plain".into());
+        assert_eq!(
+            Csv.render(&[("foo".into(), env)]).unwrap(),
+            "function,key,value\r\n\
+             foo,A,\"line\nbreak\"\r\n\
+             foo,B,\"say \"\"hi\"\", twice\"\r\n\
+             foo,C,plain\r\n"
+        )
+    }
+
+    #[test]
+    fn shell_quote_quotes_only_when_needed() {
+        assert_eq!(shell_quote("FOO=bar"), "FOO=bar");
+        assert_eq!(shell_quote("FOO=a b"), "'FOO=a b'");
+        assert_eq!(shell_quote("FOO=it's"), r"'FOO=it'\''s'");
+        assert_eq!(shell_quote(""), "''")
+    }
+
+    #[test]
+    fn shell_exports_quoted_values() {
+        let mut env = Env::new();
+        env.insert("B".into(), "it's $HOME\n".into());
+        env.insert("A".into(), "plain".into());
+        assert_eq!(
+            Shell.render(&[("a".into(), env)]).unwrap(),
+            "export A='plain'\nexport B='it'\\''s $HOME\n'\n"
+        )
+    }
+
+    #[test]
+    fn docker_args_pass_every_var() {
+        let mut env = Env::new();
+        env.insert("B".into(), "two words".into());
+        env.insert("A".into(), "1".into());
+        assert_eq!(
+            DockerArgs.render(&[("a".into(), env)]).unwrap(),
+            "-e A=1 -e 'B=two words'\n"
+        )
+    }
+
+    #[test]
+    fn hcl_string_escapes_interpolation() {
+        assert_eq!(hcl_string("plain"), r#""plain""#);
+        assert_eq!(
+            hcl_string("say \"hi\"\n${USER} %{if} $5"),
+            r#""say \"hi\"\n$${USER} %%{if} $5""#
+        )
+    }
+
+    #[test]
+    fn terraform_renders_environment_blocks() {
+        assert_eq!(
+            Terraform.render(&envs(&["a"])).unwrap(),
+            "environment {\n  variables = {\n    FOO = \"bar\"\n  }\n}\n"
+        );
+        assert_eq!(
+            Tfvars.render(&envs(&["a"])).unwrap(),
+            "environment = {\n  FOO = \"bar\"\n}\n"
+        );
+        assert_eq!(
+            Tfvars.render(&envs(&["a", "b"])).unwrap(),
+            "environments = {\n  \"a\" = {\n    FOO = \"bar\"\n  }\n  \"b\" = {\n    FOO = \"bar\"\n  }\n}\n"
+        )
+    }
+
+    #[test]
+    fn kubernetes_emits_config_maps_and_secrets() {
+        let config_map = Kubernetes {
+            kind: Kind::ConfigMap,
+            name: Some("my-config".into()),
+        };
+        let rendered = config_map.render(&envs(&["a"])).unwrap();
+        let object = serde_yaml::from_str::<serde_yaml::Value>(&rendered).unwrap();
+        assert_eq!(object["kind"].as_str(), Some("ConfigMap"));
+        assert_eq!(object["metadata"]["name"].as_str(), Some("my-config"));
+        assert_eq!(object["data"]["FOO"].as_str(), Some("bar"));
+        let secret = Kubernetes {
+            kind: Kind::Secret,
+            name: None,
+        };
+        let rendered = secret
+            .render(&envs(&[
+                "arn:aws:lambda:us-east-1:123456789012:function:My_Func",
+            ]))
+            .unwrap();
+        let object = serde_yaml::from_str::<serde_yaml::Value>(&rendered).unwrap();
+        assert_eq!(object["kind"].as_str(), Some("Secret"));
+        assert_eq!(object["type"].as_str(), Some("Opaque"));
+        assert_eq!(object["metadata"]["name"].as_str(), Some("my-func"));
+        assert_eq!(object["data"]["FOO"].as_str(), Some("YmFy"))
+    }
+
+    #[test]
+    fn kubernetes_names_several_functions_apart() {
+        let rendered = Kubernetes {
+            kind: Kind::ConfigMap,
+            name: Some("cfg".into()),
+        }
+        .render(&envs(&["a", "b"]))
+        .unwrap();
+        assert!(rendered.contains("name: cfg-a"));
+        assert!(rendered.contains("name: cfg-b"))
+    }
+
+    #[test]
+    fn find_rejects_unknown_formats() {
+        let renderers = Renderers::new(&Output::default());
+        assert_eq!(
+            renderers
+                .find(&Output {
+                    format: Some("yaml".into()),
+                    ..Output::default()
+                })
+                .map(|renderer| renderer.name().to_string())
+                .ok(),
+            Some("yaml".into())
+        );
+        assert!(renderers
+            .find(&Output {
+                format: Some("nope".into()),
+                ..Output::default()
+            })
+            .is_err())
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{
+    aws::{self, SecretsManagerClient, SsmClient},
+    error::Error,
+    migrate, plugin,
+};
+use futures::future;
+use serde_json::{Map, Value};
+use std::{future::Future, path::PathBuf, pin::Pin, process::Command, sync::Arc};
+
+/// Name prefix of executables which resolve references of an extra scheme
+const PLUGIN_PREFIX: &str = "lev-resolve-";
+
+/// The value a reference refers to, once a resolver has looked it up
+pub type Resolved = Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>;
+
+/// Resolves values marked with a prefix, such as `ssm://`, into the values
+/// they refer to
+pub trait Resolver: Send + Sync {
+    /// Prefix marking values this resolver handles
+    fn prefix(&self) -> &str;
+
+    /// The value a reference, including its prefix, refers to
+    fn resolve(
+        &self,
+        reference: &str,
+    ) -> Resolved;
+}
+
+/// An executable named `lev-resolve-<scheme>` resolving `<scheme>://`
+/// references. It is passed the reference as its only argument and prints
+/// the value to stdout
+pub struct Plugin {
+    prefix: String,
+    program: PathBuf,
+}
+
+impl Resolver for Plugin {
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn resolve(
+        &self,
+        reference: &str,
+    ) -> Resolved {
+        let program = self.program.clone();
+        let reference = reference.to_string();
+        Box::pin(async move {
+            let output = Command::new(&program).arg(&reference).output()?;
+            if !output.status.success() {
+                return Err(Error::Resolve(
+                    reference,
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ));
+            }
+            let value = String::from_utf8_lossy(&output.stdout);
+            Ok(value.trim_end_matches(&['\r', '\n'][..]).to_string())
+        })
+    }
+}
+
+/// Resolves `ssm://<name>` references to the values of Parameter Store
+/// parameters, decrypting secure strings
+pub struct Parameters {
+    ssm: SsmClient,
+}
+
+impl Parameters {
+    pub fn new(ssm: SsmClient) -> Self {
+        Parameters { ssm }
+    }
+}
+
+impl Resolver for Parameters {
+    fn prefix(&self) -> &str {
+        "ssm://"
+    }
+
+    fn resolve(
+        &self,
+        reference: &str,
+    ) -> Resolved {
+        let name = &reference[self.prefix().len()..];
+        if name.is_empty() {
+            let err = Error::Resolve(reference.into(), "no parameter name".into());
+            return Box::pin(future::err(err));
+        }
+        let request = self.ssm.get_parameter().name(name).with_decryption(true);
+        let reference = reference.to_string();
+        Box::pin(async move {
+            let result = aws::retry(|| request.clone().send()).await?;
+            result
+                .parameter
+                .and_then(|parameter| parameter.value)
+                .ok_or_else(|| Error::Resolve(reference, "parameter has no value".into()))
+        })
+    }
+}
+
+/// Resolves `secretsmanager://<secret-id>[#<key>]` references to the value of
+/// a secret, or to a field of the JSON object it holds when a key is given
+pub struct Secrets {
+    secrets: SecretsManagerClient,
+}
+
+impl Secrets {
+    pub fn new(secrets: SecretsManagerClient) -> Self {
+        Secrets { secrets }
+    }
+}
+
+/// The secret id and, if any, the key of a reference without its prefix
+fn secret_field(reference: &str) -> (&str, Option<&str>) {
+    match reference.split_once('#') {
+        Some((secret, key)) => (secret, Some(key)),
+        None => (reference, None),
+    }
+}
+
+impl Resolver for Secrets {
+    fn prefix(&self) -> &str {
+        "secretsmanager://"
+    }
+
+    fn resolve(
+        &self,
+        reference: &str,
+    ) -> Resolved {
+        let (secret, key) = secret_field(&reference[self.prefix().len()..]);
+        if secret.is_empty() {
+            let err = Error::Resolve(reference.into(), "no secret id".into());
+            return Box::pin(future::err(err));
+        }
+        let request = self.secrets.get_secret_value().secret_id(secret);
+        let (reference, secret, key) = (
+            reference.to_string(),
+            secret.to_string(),
+            key.map(String::from),
+        );
+        Box::pin(async move {
+            let value = aws::retry(|| request.clone().send()).await?;
+            let value = value.secret_string.ok_or_else(|| {
+                Error::Resolve(reference.clone(), "secret has no string value".into())
+            })?;
+            let key = match key {
+                None => return Ok(value),
+                Some(key) => key,
+            };
+            let mut fields = serde_json::from_str::<Map<String, Value>>(&value)
+                .map_err(|_| Error::InvalidSecret(secret))?;
+            fields
+                .remove(&key)
+                .map(|field| migrate::field_value(&field))
+                .ok_or_else(|| Error::Resolve(reference, format!("no field {}", key)))
+        })
+    }
+}
+
+/// Scheme of a `<scheme>://` reference
+fn scheme(value: &str) -> Option<&str> {
+    let (scheme, _) = value.split_once("://")?;
+    let valid = !scheme.is_empty()
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '+' || c == '.');
+    if valid {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+/// Resolvers by the prefixes they handle. The first resolver registered for
+/// a prefix wins, and plugins are only looked for when no registered resolver
+/// handles a value
+#[derive(Clone, Default)]
+pub struct Registry {
+    resolvers: Vec<Arc<dyn Resolver>>,
+}
+
+impl Registry {
+    /// Registry of every built in resolver
+    pub async fn new() -> Self {
+        let mut registry = Registry::default();
+        registry.register(Parameters::new(aws::ssm_client().await));
+        registry.register(Secrets::new(aws::secrets_client().await));
+        registry
+    }
+
+    pub fn register<R>(
+        &mut self,
+        resolver: R,
+    ) where
+        R: Resolver + 'static,
+    {
+        self.resolvers.push(Arc::new(resolver))
+    }
+
+    fn find(
+        &self,
+        value: &str,
+    ) -> Option<Arc<dyn Resolver>> {
+        if let Some(resolver) = self
+            .resolvers
+            .iter()
+            .find(|r| value.starts_with(r.prefix()))
+        {
+            return Some(resolver.clone());
+        }
+        let scheme = scheme(value)?;
+        let program = plugin::locate(PLUGIN_PREFIX, scheme)?;
+        Some(Arc::new(Plugin {
+            prefix: format!("{}://", scheme),
+            program,
+        }))
+    }
+
+    /// Replaces every referencing value with the value it refers to, leaving
+    /// plain values as they are
+    pub async fn resolve(
+        &self,
+        vars: Vec<(String, String)>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        future::try_join_all(vars.into_iter().map(|(key, value)| {
+            let resolver = self.find(&value);
+            async move {
+                match resolver {
+                    Some(resolver) => Ok((key, resolver.resolve(&value).await?)),
+                    None => Ok((key, value)),
+                }
+            }
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Upper(&'static str);
+
+    impl Resolver for Upper {
+        fn prefix(&self) -> &str {
+            self.0
+        }
+
+        fn resolve(
+            &self,
+            reference: &str,
+        ) -> Resolved {
+            Box::pin(future::ok(reference[self.0.len()..].to_uppercase()))
+        }
+    }
+
+    #[test]
+    fn scheme_extracts_from_references() {
+        assert_eq!(scheme("vault://secret/token"), Some("vault"));
+        assert_eq!(scheme("plain"), None);
+        assert_eq!(scheme("a b://c"), None)
+    }
+
+    #[tokio::test]
+    async fn resolve_replaces_only_references() {
+        let mut registry = Registry::default();
+        registry.register(Upper("up://"));
+        assert_eq!(
+            registry
+                .resolve(vec![
+                    ("A".into(), "up://secret".into()),
+                    ("B".into(), "plain".into()),
+                ])
+                .await
+                .ok(),
+            Some(vec![
+                ("A".into(), "SECRET".into()),
+                ("B".into(), "plain".into()),
+            ])
+        )
+    }
+
+    #[tokio::test]
+    async fn parameters_require_a_name() {
+        match Parameters::new(aws::ssm_client().await)
+            .resolve("ssm://")
+            .await
+        {
+            Err(Error::Resolve(reference, _)) => assert_eq!(reference, "ssm://"),
+            other => panic!("expected a resolve error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn secret_field_splits_off_keys() {
+        assert_eq!(
+            secret_field("my-func/prod#DB_PASSWORD"),
+            ("my-func/prod", Some("DB_PASSWORD"))
+        );
+        assert_eq!(secret_field("my-func/token"), ("my-func/token", None))
+    }
+
+    #[tokio::test]
+    async fn first_registered_resolver_wins() {
+        let mut registry = Registry::default();
+        registry.register(Upper("up://"));
+        registry.register(Upper("up:"));
+        assert_eq!(
+            registry
+                .resolve(vec![("A".into(), "up://x".into())])
+                .await
+                .ok(),
+            Some(vec![("A".into(), "X".into())])
+        )
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{error::Error, source};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use yaml_rust::{
+    parser::{MarkedEventReceiver, Parser},
+    scanner::{Marker, TokenType},
+    Event,
+};
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidTemplate(reason)
+}
+
+/// A mapping or sequence being read, along with the anchor it is stored
+/// under once complete
+enum Frame {
+    Sequence(usize, Vec<Value>),
+    Mapping(usize, Mapping, Option<Value>),
+}
+
+/// Builds a template from yaml events. Unlike serde_yaml, which drops tags,
+/// short form intrinsics like `!Ref Name` on scalars are kept as the long
+/// form `{"Ref": "Name"}` mappings they abbreviate. Every scalar is read as a
+/// string, as that's all env values can be
+#[derive(Default)]
+struct Builder {
+    stack: Vec<Frame>,
+    anchors: HashMap<usize, Value>,
+    root: Option<Value>,
+}
+
+impl Builder {
+    fn complete(
+        &mut self,
+        anchor: usize,
+        value: Value,
+    ) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            None => self.root = Some(value),
+            Some(Frame::Sequence(_, values)) => values.push(value),
+            Some(Frame::Mapping(_, mapping, key)) => match key.take() {
+                None => *key = Some(value),
+                Some(key) => {
+                    mapping.insert(key, value);
+                }
+            },
+        }
+    }
+}
+
+/// Name of the intrinsic function a short form tag abbreviates
+fn intrinsic(tag: &str) -> String {
+    match tag {
+        "Ref" | "Condition" => tag.into(),
+        _ => format!("Fn::{}", tag),
+    }
+}
+
+impl MarkedEventReceiver for Builder {
+    fn on_event(
+        &mut self,
+        event: Event,
+        _: Marker,
+    ) {
+        match event {
+            Event::SequenceStart(anchor) => self.stack.push(Frame::Sequence(anchor, Vec::new())),
+            Event::MappingStart(anchor) => {
+                self.stack
+                    .push(Frame::Mapping(anchor, Mapping::new(), None))
+            }
+            Event::SequenceEnd => {
+                if let Some(Frame::Sequence(anchor, values)) = self.stack.pop() {
+                    self.complete(anchor, Value::Sequence(values))
+                }
+            }
+            Event::MappingEnd => {
+                if let Some(Frame::Mapping(anchor, mapping, _)) = self.stack.pop() {
+                    self.complete(anchor, Value::Mapping(mapping))
+                }
+            }
+            Event::Scalar(value, _, anchor, tag) => {
+                let value = match tag {
+                    Some(TokenType::Tag(ref handle, ref suffix)) if handle == "!" => {
+                        let mut mapping = Mapping::new();
+                        mapping.insert(Value::String(intrinsic(suffix)), Value::String(value));
+                        Value::Mapping(mapping)
+                    }
+                    _ => Value::String(value),
+                };
+                self.complete(anchor, value)
+            }
+            Event::Alias(anchor) => {
+                let value = self.anchors.get(&anchor).cloned().unwrap_or(Value::Null);
+                self.complete(0, value)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Parses a yaml or JSON SAM/CloudFormation template
+fn parse(contents: &str) -> Result<Value, Error> {
+    let mut builder = Builder::default();
+    Parser::new(contents.chars())
+        .load(&mut builder, false)
+        .map_err(|err| invalid(err.to_string()))?;
+    Ok(builder.root.unwrap_or(Value::Null))
+}
+
+fn path<'a>(
+    value: &'a Value,
+    path: &[&str],
+) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(*key))
+}
+
+/// A template parameter's provided value, or else its default
+fn parameter(
+    template: &Value,
+    parameters: &[(String, String)],
+    name: &str,
+) -> Result<String, Error> {
+    if let Some((_, value)) = parameters.iter().find(|(key, _)| key == name) {
+        return Ok(value.clone());
+    }
+    if path(template, &["Resources", name]).is_some() {
+        return Err(invalid(format!(
+            "Ref to resource {} can not be resolved before deploying",
+            name
+        )));
+    }
+    path(template, &["Parameters", name, "Default"])
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| invalid(format!("no value for parameter {}, pass --parameter", name)))
+}
+
+/// A Fn::Sub string with its `${Name}` variables substituted, and `${!Name}`
+/// literals unescaped
+fn substitute(
+    string: &str,
+    template: &Value,
+    parameters: &[(String, String)],
+    variables: &Mapping,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut rest = string;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid(format!("unterminated variable in {}", string)))?;
+        let name = &rest[start + 2..end];
+        if let Some(literal) = name.strip_prefix('!') {
+            out.push_str(&format!("${{{}}}", literal));
+        } else if let Some(value) = variables.get(&Value::String(name.into())) {
+            out.push_str(&evaluate(value, template, parameters)?);
+        } else {
+            out.push_str(&parameter(template, parameters, name)?);
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The string a template value evaluates to, resolving Ref and Fn::Sub
+fn evaluate(
+    value: &Value,
+    template: &Value,
+    parameters: &[(String, String)],
+) -> Result<String, Error> {
+    let unsupported = || invalid(format!("unsupported value {:?}", value));
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Mapping(mapping) if mapping.len() == 1 => {
+            let (function, argument) = mapping.iter().next().ok_or_else(unsupported)?;
+            match (function.as_str(), argument) {
+                (Some("Ref"), Value::String(name)) => parameter(template, parameters, name),
+                (Some("Fn::Sub"), Value::String(string)) => {
+                    substitute(string, template, parameters, &Mapping::new())
+                }
+                (Some("Fn::Sub"), Value::Sequence(arguments)) => match arguments.as_slice() {
+                    [Value::String(string), Value::Mapping(variables)] => {
+                        substitute(string, template, parameters, variables)
+                    }
+                    _ => Err(unsupported()),
+                },
+                _ => Err(unsupported()),
+            }
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Env vars a template configures a function resource with: those of the
+/// template's `Globals`, overridden by the resource's own
+pub fn variables(
+    template: &Value,
+    logical_id: &str,
+    parameters: &[(String, String)],
+) -> Result<Vec<(String, String)>, Error> {
+    let resource = path(template, &["Resources", logical_id])
+        .ok_or_else(|| Error::NoSuchResource(logical_id.into()))?;
+    let empty = Mapping::new();
+    let sections = [
+        path(
+            template,
+            &["Globals", "Function", "Environment", "Variables"],
+        ),
+        path(resource, &["Properties", "Environment", "Variables"]),
+    ];
+    let mut vars = Vec::<(String, String)>::new();
+    for section in sections.iter() {
+        for (key, value) in section.and_then(Value::as_mapping).unwrap_or(&empty) {
+            let key = key
+                .as_str()
+                .ok_or_else(|| invalid("env names must be strings".into()))?
+                .to_string();
+            let value = evaluate(value, template, parameters)?;
+            vars.retain(|(k, _)| *k != key);
+            vars.push((key, value));
+        }
+    }
+    vars.sort();
+    Ok(vars)
+}
+
+/// The `FunctionName` a template gives a function resource, when it gives
+/// one that resolves before deploying
+pub fn function_name(
+    template: &Value,
+    logical_id: &str,
+    parameters: &[(String, String)],
+) -> Option<String> {
+    path(
+        template,
+        &["Resources", logical_id, "Properties", "FunctionName"],
+    )
+    .and_then(|name| evaluate(name, template, parameters).ok())
+}
+
+/// The name a template read from a local path or url gives a function
+/// resource, if any, and the env vars it configures the resource with
+pub async fn template_vars(
+    source: String,
+    logical_id: String,
+    parameters: Vec<(String, String)>,
+) -> Result<(Option<String>, Vec<(String, String)>), Error> {
+    let template = parse(&String::from_utf8_lossy(&source::read(&source).await?))?;
+    Ok((
+        function_name(&template, &logical_id, &parameters),
+        variables(&template, &logical_id, &parameters)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "\
+Parameters:
+  Stage:
+    Type: String
+    Default: dev
+  TableName:
+    Type: String
+Globals:
+  Function:
+    Environment:
+      Variables:
+        LOG_LEVEL: info
+        STAGE: !Ref Stage
+Resources:
+  Table:
+    Type: AWS::DynamoDB::Table
+  MyFunction:
+    Type: AWS::Serverless::Function
+    Properties:
+      Environment:
+        Variables:
+          LOG_LEVEL: debug
+          TABLE: !Sub '${TableName}-${Stage}'
+          LITERAL: !Sub '${!NotAVariable}'
+          PORT: 8080
+          LONG:
+            Fn::Sub:
+              - '${Prefix}-api'
+              - Prefix: !Ref Stage
+";
+
+    #[test]
+    fn parse_keeps_short_form_intrinsics() {
+        let template = parse(TEMPLATE).unwrap();
+        assert_eq!(
+            path(
+                &template,
+                &[
+                    "Globals",
+                    "Function",
+                    "Environment",
+                    "Variables",
+                    "STAGE",
+                    "Ref"
+                ]
+            )
+            .and_then(Value::as_str),
+            Some("Stage")
+        )
+    }
+
+    #[test]
+    fn variables_resolve_refs_and_subs() {
+        let template = parse(TEMPLATE).unwrap();
+        assert_eq!(
+            variables(
+                &template,
+                "MyFunction",
+                &[("TableName".into(), "orders".into())]
+            )
+            .ok(),
+            Some(vec![
+                ("LITERAL".into(), "${NotAVariable}".into()),
+                ("LOG_LEVEL".into(), "debug".into()),
+                ("LONG".into(), "dev-api".into()),
+                ("PORT".into(), "8080".into()),
+                ("STAGE".into(), "dev".into()),
+                ("TABLE".into(), "orders-dev".into()),
+            ])
+        )
+    }
+
+    #[test]
+    fn variables_require_parameter_values() {
+        let template = parse(TEMPLATE).unwrap();
+        assert!(variables(&template, "MyFunction", &[]).is_err());
+        assert!(variables(&template, "Missing", &[]).is_err());
+        let template = parse(
+            "Resources:\n  Table: {}\n  Fn:\n    Properties:\n      Environment:\n        Variables:\n          T: !Ref Table\n",
+        )
+        .unwrap();
+        assert!(variables(&template, "Fn", &[]).is_err())
+    }
+
+    #[test]
+    fn function_name_resolves_when_it_can() {
+        let template = parse(
+            "Resources:\n  Table: {}\n  Named:\n    Properties:\n      FunctionName: !Sub '${Stage}-api'\n  \
+             Generated:\n    Properties:\n      FunctionName: !Ref Table\n  Unnamed:\n    Properties: {}\n",
+        )
+        .unwrap();
+        let parameters = [("Stage".to_string(), "prod".to_string())];
+        assert_eq!(
+            function_name(&template, "Named", &parameters),
+            Some("prod-api".into())
+        );
+        assert_eq!(function_name(&template, "Generated", &parameters), None);
+        assert_eq!(function_name(&template, "Unnamed", &parameters), None)
+    }
+}
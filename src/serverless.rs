@@ -0,0 +1,261 @@
+use crate::{error::Error, source};
+use serde_yaml::{Mapping, Value};
+use std::env;
+
+/// Deepest variables may reference other variables through `${self:...}`
+const MAX_DEPTH: usize = 10;
+
+fn invalid(reason: String) -> Error {
+    Error::ServerlessConfig(reason)
+}
+
+/// Plain string form of a yaml scalar
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// The value at a dotted path of the config, as `${self:a.b.c}` names it
+fn lookup<'a>(
+    config: &'a Value,
+    path: &str,
+) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|part| !part.is_empty())
+        .try_fold(config, |value, part| value.get(part))
+}
+
+/// What a single variable source, like `opt:stage` or `'fallback'`, refers
+/// to, if it can be resolved
+fn source_value(
+    expression: &str,
+    config: &Value,
+    stage: &str,
+    depth: usize,
+) -> Result<Option<String>, Error> {
+    let expression = expression.trim();
+    if let Some(quoted) = expression
+        .strip_prefix('\'')
+        .and_then(|e| e.strip_suffix('\''))
+        .or_else(|| {
+            expression
+                .strip_prefix('"')
+                .and_then(|e| e.strip_suffix('"'))
+        })
+    {
+        return Ok(Some(quoted.to_string()));
+    }
+    let (kind, name) = expression
+        .split_once(':')
+        .ok_or_else(|| invalid(format!("unsupported variable ${{{}}}", expression)))?;
+    match kind {
+        "opt" | "sls" if name == "stage" => Ok(Some(stage.to_string())),
+        "self" if name == "provider.stage" => Ok(Some(stage.to_string())),
+        "self" => match lookup(config, name).and_then(scalar) {
+            Some(value) => interpolate(&value, config, stage, depth + 1).map(Some),
+            None => Ok(None),
+        },
+        "env" => Ok(env::var(name).ok()),
+        _ => Err(invalid(format!("unsupported variable ${{{}}}", expression))),
+    }
+}
+
+/// A value with its `${...}` variables substituted. Variables may fall back
+/// on further sources, as in `${env:LOG_LEVEL, 'info'}`
+fn interpolate(
+    value: &str,
+    config: &Value,
+    stage: &str,
+    depth: usize,
+) -> Result<String, Error> {
+    if depth > MAX_DEPTH {
+        return Err(invalid(format!("variables of {} nest too deeply", value)));
+    }
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid(format!("unterminated variable in {}", value)))?;
+        let expression = &rest[start + 2..end];
+        let mut resolved = None;
+        for source in expression.split(',') {
+            resolved = source_value(source, config, stage, depth)?;
+            if resolved.is_some() {
+                break;
+            }
+        }
+        let resolved =
+            resolved.ok_or_else(|| invalid(format!("${{{}}} does not resolve", expression)))?;
+        out.push_str(&resolved);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// An env value, with `${ssm:<name>}` values turned into `ssm://` references
+/// for set time resolution
+fn env_value(
+    key: &str,
+    value: &Value,
+    config: &Value,
+    stage: &str,
+) -> Result<String, Error> {
+    let value = scalar(value)
+        .ok_or_else(|| invalid(format!("{} must be a string, number or boolean", key)))?;
+    if let Some(name) = value
+        .strip_prefix("${ssm:")
+        .and_then(|name| name.strip_suffix('}'))
+    {
+        let name = interpolate(name, config, stage, 0)?;
+        return Ok(format!("ssm://{}", name));
+    }
+    interpolate(&value, config, stage, 0)
+}
+
+/// The env a function of a serverless config is deployed with for a stage:
+/// the provider's environment overridden by the function's own
+pub fn environment(
+    config: &Value,
+    stage: &str,
+    function: &str,
+) -> Result<Vec<(String, String)>, Error> {
+    let declared = lookup(config, "functions")
+        .and_then(|functions| functions.get(function))
+        .ok_or_else(|| Error::NoServerlessFunction(function.into()))?;
+    let empty = Mapping::new();
+    let sections = [
+        lookup(config, "provider.environment"),
+        declared.get("environment"),
+    ];
+    let mut vars = Vec::<(String, String)>::new();
+    for section in sections.iter() {
+        let section = section.and_then(Value::as_mapping).unwrap_or(&empty);
+        for (key, value) in section {
+            let key = scalar(key).ok_or_else(|| invalid("env names must be strings".into()))?;
+            let value = env_value(&key, value, config, stage)?;
+            vars.retain(|(k, _)| *k != key);
+            vars.push((key, value));
+        }
+    }
+    vars.sort();
+    Ok(vars)
+}
+
+/// The name a function of a serverless config is deployed as for a stage:
+/// its own `name`, or else serverless' `<service>-<stage>-<function>`
+pub fn deployed_name(
+    config: &Value,
+    stage: &str,
+    function: &str,
+) -> Result<String, Error> {
+    let declared = lookup(config, "functions")
+        .and_then(|functions| functions.get(function))
+        .ok_or_else(|| Error::NoServerlessFunction(function.into()))?;
+    if let Some(name) = declared.get("name").and_then(scalar) {
+        return interpolate(&name, config, stage, 0);
+    }
+    let service = lookup(config, "service")
+        .and_then(|service| scalar(service).or_else(|| service.get("name").and_then(scalar)))
+        .ok_or_else(|| invalid("no service name".into()))?;
+    Ok(format!(
+        "{}-{}-{}",
+        interpolate(&service, config, stage, 0)?,
+        stage,
+        function
+    ))
+}
+
+/// The name a function of a serverless config read from a local path or url
+/// is deployed as for a stage, and the env vars it is deployed with
+pub async fn config_vars(
+    source: String,
+    stage: String,
+    function: String,
+) -> Result<(String, Vec<(String, String)>), Error> {
+    let contents = source::read(&source).await?;
+    let config =
+        serde_yaml::from_slice::<Value>(&contents).map_err(|err| invalid(err.to_string()))?;
+    Ok((
+        deployed_name(&config, &stage, &function)?,
+        environment(&config, &stage, &function)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn environment_overrides_provider_with_function() {
+        let config = config(
+            "service: orders\n\
+             provider:\n  environment:\n    LOG_LEVEL: info\n    PORT: 8080\n\
+             functions:\n  handler:\n    environment:\n      LOG_LEVEL: debug\n",
+        );
+        assert_eq!(
+            environment(&config, "prod", "handler").ok(),
+            Some(vec![
+                ("LOG_LEVEL".into(), "debug".into()),
+                ("PORT".into(), "8080".into()),
+            ])
+        );
+        assert!(environment(&config, "prod", "worker").is_err())
+    }
+
+    #[test]
+    fn environment_resolves_variables() {
+        let config = config(
+            "service: orders\n\
+             custom:\n  table: ${self:service}-${opt:stage}\n\
+             functions:\n  handler:\n    environment:\n      \
+             TABLE: ${self:custom.table}\n      \
+             LEVEL: ${env:LEV_SERVERLESS_TEST_UNSET, 'warn'}\n      \
+             DB_URL: ${ssm:/orders/${sls:stage}/db_url}\n",
+        );
+        assert_eq!(
+            environment(&config, "prod", "handler").ok(),
+            Some(vec![
+                ("DB_URL".into(), "ssm:///orders/prod/db_url".into()),
+                ("LEVEL".into(), "warn".into()),
+                ("TABLE".into(), "orders-prod".into()),
+            ])
+        )
+    }
+
+    #[test]
+    fn deployed_name_follows_serverless_naming() {
+        let config = config(
+            "service: orders\n\
+             functions:\n  handler: {}\n  worker:\n    name: ${self:service}-${sls:stage}-jobs\n",
+        );
+        assert_eq!(
+            deployed_name(&config, "prod", "handler").ok(),
+            Some("orders-prod-handler".into())
+        );
+        assert_eq!(
+            deployed_name(&config, "prod", "worker").ok(),
+            Some("orders-prod-jobs".into())
+        );
+        assert!(deployed_name(&config, "prod", "missing").is_err())
+    }
+
+    #[test]
+    fn interpolate_rejects_unresolvable_variables() {
+        let config = config("service: orders\n");
+        assert!(interpolate("${self:custom.missing}", &config, "prod", 0).is_err());
+        assert!(interpolate("${cf:stack.output}", &config, "prod", 0).is_err());
+        assert!(interpolate("${self:service", &config, "prod", 0).is_err())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{aws, error::Error};
+use aws_sdk_s3::primitives::ByteStream;
+use reqwest::Client;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    sync::Mutex,
+};
+
+/// Bearer token sent along with requests for https sources
+static TOKEN: Mutex<Option<String>> = Mutex::new(None);
+
+/// Authenticates requests for https sources with `Authorization: Bearer <token>`
+pub fn set_token(token: Option<String>) {
+    *TOKEN
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = token
+}
+
+fn token() -> Option<String> {
+    TOKEN
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Whether a source names a url rather than a local file
+fn is_url(source: &str) -> bool {
+    source.contains("://")
+}
+
+/// The bucket and key an s3://bucket/key url names
+fn s3_object(source: &str) -> Option<(String, String)> {
+    let (bucket, key) = source.strip_prefix("s3://")?.split_once('/')?;
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some((bucket.into(), key.into()))
+}
+
+async fn get_object(
+    bucket: String,
+    key: String,
+) -> Result<Vec<u8>, Error> {
+    let request = aws::s3_client().await.get_object().bucket(bucket).key(key);
+    let object = aws::retry(|| request.clone().send()).await?;
+    let body = object
+        .body
+        .collect()
+        .await
+        .map_err(|err| Error::from(io::Error::from(err)))?;
+    Ok(body.into_bytes().to_vec())
+}
+
+/// Reads what a source names: an s3://bucket/key object, the body served at
+/// an https:// url, or else the contents of a local file. Plain http is
+/// refused, as it would expose both the token and what is served to the
+/// network
+pub async fn read(source: &str) -> Result<Vec<u8>, Error> {
+    if !is_url(source) {
+        return Ok(fs::read(source)?);
+    }
+    if source.starts_with("s3://") {
+        return match s3_object(source) {
+            Some((bucket, key)) => get_object(bucket, key).await,
+            None => Err(Error::InvalidLocation(source.into())),
+        };
+    }
+    if !source.starts_with("https://") {
+        return Err(Error::InsecureSource(source.into()));
+    }
+    let mut request = Client::new().get(source);
+    if let Some(token) = token() {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Writes a local file only its owner can read, as what is exported holds
+/// the values of envs
+fn write_private(
+    path: &str,
+    contents: &[u8],
+) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // files exported to before exports were kept private are made so
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+/// Writes to an s3://bucket/key object, or else a local file kept to its
+/// owner
+pub async fn write(
+    destination: &str,
+    contents: Vec<u8>,
+) -> Result<(), Error> {
+    if !is_url(destination) {
+        return write_private(destination, &contents);
+    }
+    let (bucket, key) =
+        s3_object(destination).ok_or_else(|| Error::InvalidLocation(destination.into()))?;
+    let s3 = aws::s3_client().await;
+    aws::retry(|| {
+        s3.put_object()
+            .bucket(bucket.clone())
+            .key(key.clone())
+            .body(ByteStream::from(contents.clone()))
+            .send()
+    })
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn write_keeps_local_files_to_their_owner() {
+        let path = std::env::temp_dir().join(format!("lev-test-export-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, b"stale").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o644)).unwrap();
+        write(path, b"A=1\n".to_vec()).await.unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"A=1\n");
+        assert_eq!(
+            fs::metadata(path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        fs::remove_file(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_refuses_plain_http() {
+        match read("http://example.com/lev.yaml").await {
+            Err(Error::InsecureSource(source)) => assert_eq!(source, "http://example.com/lev.yaml"),
+            other => panic!("expected an insecure source error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn s3_object_splits_bucket_and_key() {
+        assert_eq!(
+            s3_object("s3://envs/prod/lev.yaml"),
+            Some(("envs".into(), "prod/lev.yaml".into()))
+        );
+        assert_eq!(s3_object("s3://envs"), None);
+        assert_eq!(s3_object("s3://envs/"), None)
+    }
+
+    #[tokio::test]
+    async fn read_reads_local_files() {
+        let path = std::env::temp_dir().join(format!("lev-source-test-{}", std::process::id()));
+        fs::write(&path, "functions: {}").unwrap();
+        assert_eq!(
+            read(&path.display().to_string()).await.ok(),
+            Some(b"functions: {}".to_vec())
+        );
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    aws::{self, SdkError},
+    error::Error,
+};
+use aws_sdk_lambda::operation::get_function_configuration::{
+    GetFunctionConfigurationError, GetFunctionConfigurationOutput,
+};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Delay between polls of a function's update status
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest time an update is waited on before giving up
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Progress of the last update of a function's configuration
+#[derive(Debug, PartialEq, Default)]
+struct Status {
+    last_update_status: Option<String>,
+    last_update_status_reason: Option<String>,
+}
+
+impl From<GetFunctionConfigurationOutput> for Status {
+    fn from(output: GetFunctionConfigurationOutput) -> Self {
+        Status {
+            last_update_status: output
+                .last_update_status
+                .map(|status| status.as_str().to_string()),
+            last_update_status_reason: output.last_update_status_reason,
+        }
+    }
+}
+
+impl Status {
+    /// Updates are complete unless lambda reports them as in progress
+    fn in_progress(&self) -> bool {
+        self.last_update_status.as_deref() == Some("InProgress")
+    }
+
+    fn failed(&self) -> bool {
+        self.last_update_status.as_deref() == Some("Failed")
+    }
+}
+
+async fn status(function: &str) -> Result<Status, SdkError<GetFunctionConfigurationError>> {
+    let request = aws::lambda_client()
+        .await
+        .get_function_configuration()
+        .function_name(function);
+    aws::retry(|| request.clone().send())
+        .await
+        .map(Status::from)
+}
+
+/// Status of the last update of a function's configuration, `None` when lambda
+/// does not report one
+pub async fn last_update(function: &str) -> Result<Option<String>, Error> {
+    Ok(status(function).await?.last_update_status)
+}
+
+/// Resolves once the last update of a function's configuration has completed,
+/// failing if lambda could not apply it
+pub async fn wait(function: String) -> Result<(), Error> {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let status = status(&function).await?;
+        if status.failed() {
+            let reason = status.last_update_status_reason.unwrap_or_default();
+            return Err(Error::UpdateFailed(function, reason));
+        }
+        if !status.in_progress() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::UpdateTimeout(function));
+        }
+        time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_lambda::types::LastUpdateStatus;
+
+    #[test]
+    fn status_reads_configuration() {
+        let status = Status::from(
+            GetFunctionConfigurationOutput::builder()
+                .function_name("my-func")
+                .last_update_status(LastUpdateStatus::InProgress)
+                .build(),
+        );
+        assert!(status.in_progress());
+        assert!(!status.failed())
+    }
+
+    #[test]
+    fn status_is_complete_when_unreported() {
+        assert!(!Status::default().in_progress())
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    aws::{self, SdkError},
+    backup,
+    error::Error,
+    Env,
+};
+use aws_sdk_lambda::{
+    operation::{
+        get_function_configuration::GetFunctionConfigurationError,
+        update_function_configuration::UpdateFunctionConfigurationError,
+    },
+    types::{
+        error::ResourceNotFoundException, Environment, EnvironmentResponse, FunctionConfiguration,
+    },
+    Client as LambdaClient,
+};
+use aws_smithy_types::error::ErrorMetadata;
+use std::{
+    collections::HashMap,
+    future::{self, Future},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+/// Configurations as a store yields them
+pub type Configured<E> =
+    Pin<Box<dyn Future<Output = Result<FunctionConfiguration, SdkError<E>>> + Send>>;
+
+/// Snapshots as a store takes them
+pub type Snapshotted = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Where functions' configurations, and with them their envs, are read from
+/// and written to. Lambda itself is one, and `MemoryStore` stands in for it
+/// where talking to aws is out of the question
+pub trait LambdaEnvStore: Clone + Send + Sync + 'static {
+    /// Keeps a function's env somewhere it can be restored from, ahead of
+    /// replacing it
+    fn snapshot(
+        &self,
+        function: String,
+        env: Env,
+    ) -> Snapshotted;
+
+    fn get_configuration(
+        &self,
+        function: String,
+    ) -> Configured<GetFunctionConfigurationError>;
+
+    /// Replaces a function's env, along with the KMS key it is encrypted with
+    /// when one is given
+    fn update_configuration(
+        &self,
+        function: String,
+        env: Env,
+        kms_key_arn: Option<String>,
+    ) -> Configured<UpdateFunctionConfigurationError>;
+}
+
+/// The configuration of a function as a `FunctionConfiguration`, from the
+/// output of an operation answering with one's fields
+macro_rules! configuration {
+    ($output:expr) => {{
+        let output = $output;
+        FunctionConfiguration::builder()
+            .set_function_name(output.function_name)
+            .set_function_arn(output.function_arn)
+            .set_version(output.version)
+            .set_revision_id(output.revision_id)
+            .set_last_modified(output.last_modified)
+            .set_kms_key_arn(output.kms_key_arn)
+            .set_environment(output.environment)
+            .set_state(output.state)
+            .set_last_update_status(output.last_update_status)
+            .build()
+    }};
+}
+
+impl LambdaEnvStore for LambdaClient {
+    fn snapshot(
+        &self,
+        function: String,
+        env: Env,
+    ) -> Snapshotted {
+        Box::pin(async move { backup::snapshot(&function, &env).await.map(|_| ()) })
+    }
+
+    fn get_configuration(
+        &self,
+        function: String,
+    ) -> Configured<GetFunctionConfigurationError> {
+        let request = self.get_function_configuration().function_name(function);
+        Box::pin(async move { request.send().await.map(|output| configuration!(output)) })
+    }
+
+    fn update_configuration(
+        &self,
+        function: String,
+        env: Env,
+        kms_key_arn: Option<String>,
+    ) -> Configured<UpdateFunctionConfigurationError> {
+        let request = self
+            .update_function_configuration()
+            .function_name(function)
+            .environment(Environment::builder().set_variables(Some(env)).build())
+            .set_kms_key_arn(kms_key_arn);
+        Box::pin(async move { request.send().await.map(|output| configuration!(output)) })
+    }
+}
+
+/// Envs of functions kept in memory, each clone sharing the same ones.
+/// Functions it has no env for do not exist, as far as it lets on
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    envs: Arc<Mutex<HashMap<String, Env>>>,
+    /// Snapshots fail, as when the backup bucket can't be written to
+    failing_snapshots: bool,
+}
+
+impl MemoryStore {
+    /// A store holding a function with `env` for its env
+    pub fn with(
+        self,
+        function: &str,
+        env: Env,
+    ) -> Self {
+        self.envs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(function.into(), env);
+        self
+    }
+
+    /// A store whose snapshots fail
+    pub fn failing_snapshots(self) -> Self {
+        MemoryStore {
+            failing_snapshots: true,
+            ..self
+        }
+    }
+
+    /// The env a function has, if it exists
+    pub fn env(
+        &self,
+        function: &str,
+    ) -> Option<Env> {
+        self.envs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(function)
+            .cloned()
+    }
+}
+
+fn configuration(
+    function: String,
+    env: Env,
+) -> FunctionConfiguration {
+    FunctionConfiguration::builder()
+        .function_name(function)
+        .environment(
+            EnvironmentResponse::builder()
+                .set_variables(Some(env))
+                .build(),
+        )
+        .build()
+}
+
+/// Lambda's answer for functions which do not exist
+fn not_found(function: &str) -> ResourceNotFoundException {
+    let message = format!("Function not found: {}", function);
+    ResourceNotFoundException::builder()
+        .r#type("User")
+        .message(message.clone())
+        .meta(
+            ErrorMetadata::builder()
+                .code("ResourceNotFoundException")
+                .message(message)
+                .build(),
+        )
+        .build()
+}
+
+impl LambdaEnvStore for MemoryStore {
+    /// Takes no snapshots, leaving the backups of real functions be
+    fn snapshot(
+        &self,
+        _function: String,
+        _env: Env,
+    ) -> Snapshotted {
+        Box::pin(future::ready(if self.failing_snapshots {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "snapshots fail").into())
+        } else {
+            Ok(())
+        }))
+    }
+
+    fn get_configuration(
+        &self,
+        function: String,
+    ) -> Configured<GetFunctionConfigurationError> {
+        Box::pin(future::ready(match self.env(&function) {
+            Some(env) => Ok(configuration(function, env)),
+            None => Err(aws::service_error(
+                GetFunctionConfigurationError::ResourceNotFoundException(not_found(&function)),
+                404,
+            )),
+        }))
+    }
+
+    fn update_configuration(
+        &self,
+        function: String,
+        env: Env,
+        _kms_key_arn: Option<String>,
+    ) -> Configured<UpdateFunctionConfigurationError> {
+        let mut envs = self
+            .envs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Box::pin(future::ready(match envs.get_mut(&function) {
+            Some(current) => {
+                *current = env;
+                Ok(configuration(function, current.clone()))
+            }
+            None => Err(aws::service_error(
+                UpdateFunctionConfigurationError::ResourceNotFoundException(not_found(&function)),
+                404,
+            )),
+        }))
+    }
+}
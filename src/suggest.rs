@@ -0,0 +1,145 @@
+use crate::{
+    aws::{self, LambdaClient},
+    error::Error,
+};
+
+/// Most names suggested in place of one which does not exist
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Prefix of the message lambda refuses requests for missing functions with
+const NOT_FOUND: &str = "Function not found: ";
+
+/// Levenshtein distance: the fewest single character insertions, deletions
+/// and substitutions turning `a` into `b`
+pub fn distance(
+    a: &str,
+    b: &str,
+) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The names closest to `name`, nearest first, leaving out those too far off
+/// to be what was meant
+pub fn closest(
+    name: &str,
+    names: Vec<String>,
+) -> Vec<String> {
+    let max = (name.chars().count() / 3).max(2);
+    let mut near = names
+        .into_iter()
+        .map(|candidate| (distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .collect::<Vec<_>>();
+    near.sort();
+    near.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// The name of the function lambda reported missing, if that is why it
+/// refused a request
+pub fn missing(err: &Error) -> Option<String> {
+    let message = err.to_string();
+    // the message follows the code aws gives the error
+    let (_, arn) = message.split_once(NOT_FOUND)?;
+    let name = arn
+        .split(':')
+        .skip_while(|part| *part != "function")
+        .nth(1)?;
+    Some(name.to_string())
+}
+
+/// The failure to find a `missing` function, naming the functions closest to
+/// it when there are any, otherwise `err` as it was
+pub async fn suggest(
+    lambda: LambdaClient,
+    function: String,
+    err: Error,
+) -> Result<(), Error> {
+    // failing to list functions leaves the original failure to report
+    let names = aws::list_functions(lambda)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|function| function.function_name)
+        .collect();
+    match closest(&function, names) {
+        suggestions if suggestions.is_empty() => Err(err),
+        suggestions => Err(Error::NoSuchFunction(
+            function,
+            suggestions
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(" or "),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_lambda::{
+        operation::get_function_configuration::GetFunctionConfigurationError,
+        types::error::ResourceNotFoundException,
+    };
+    use aws_smithy_types::error::ErrorMetadata;
+
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("orders", "orders"), 0)
+    }
+
+    #[test]
+    fn closest_orders_near_names_first() {
+        assert_eq!(
+            closest(
+                "orders-prod-handlr",
+                vec![
+                    "billing-prod-handler".into(),
+                    "orders-dev-handler".into(),
+                    "orders-prod-handler".into(),
+                ]
+            ),
+            vec![
+                "orders-prod-handler".to_string(),
+                "orders-dev-handler".to_string()
+            ]
+        );
+        assert!(closest("api", vec!["worker".into()]).is_empty())
+    }
+
+    #[test]
+    fn missing_reads_function_from_not_found() {
+        let message = "Function not found: arn:aws:lambda:us-east-1:123456789012:function:ordrs";
+        let err = Error::from(aws::service_error(
+            GetFunctionConfigurationError::ResourceNotFoundException(
+                ResourceNotFoundException::builder()
+                    .message(message)
+                    .meta(
+                        ErrorMetadata::builder()
+                            .code("ResourceNotFoundException")
+                            .message(message)
+                            .build(),
+                    )
+                    .build(),
+            ),
+            404,
+        ));
+        assert_eq!(missing(&err), Some("ordrs".into()));
+        assert_eq!(missing(&Error::AuditDisabled), None)
+    }
+}
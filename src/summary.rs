@@ -0,0 +1,298 @@
+use crate::{aws::LambdaClient, diff::diff, error::Error, get, keys, policy::Policy, status, Env};
+use futures::future;
+
+/// Most bytes lambda accepts across the keys and values of a function's env
+pub const ENV_LIMIT: usize = 4096;
+
+/// Bytes a function's env counts against `ENV_LIMIT`
+pub fn size(env: &Env) -> usize {
+    env.iter().map(|(key, value)| key.len() + value.len()).sum()
+}
+
+/// Most keys `excess` names
+const MAX_OFFENDERS: usize = 5;
+
+/// The size of an updated env over `ENV_LIMIT`, along with the keys to blame:
+/// those the update grew the most, or else the largest
+pub fn excess(
+    previous: &Env,
+    updated: &Env,
+) -> Option<(usize, Vec<String>)> {
+    let total = size(updated);
+    if total <= ENV_LIMIT {
+        return None;
+    }
+    let sizes = |env: &Env, key: &str| env.get(key).map_or(0, |value| key.len() + value.len());
+    let mut grown = updated
+        .keys()
+        .map(|key| {
+            (
+                key,
+                sizes(updated, key).saturating_sub(sizes(previous, key)),
+            )
+        })
+        .filter(|(_, growth)| *growth > 0)
+        .map(|(key, growth)| (growth, format!("{} +{}", key, growth)))
+        .collect::<Vec<_>>();
+    if grown.is_empty() {
+        grown = updated
+            .keys()
+            .map(|key| {
+                (
+                    sizes(updated, key),
+                    format!("{} {}", key, sizes(updated, key)),
+                )
+            })
+            .collect();
+    }
+    grown.sort_by(|(a, a_key), (b, b_key)| b.cmp(a).then(a_key.cmp(b_key)));
+    Some((
+        total,
+        grown
+            .into_iter()
+            .take(MAX_OFFENDERS)
+            .map(|(_, offender)| offender)
+            .collect(),
+    ))
+}
+
+/// Aligned rows of a key's share of `ENV_LIMIT`, largest first, after a
+/// header
+pub fn breakdown(env: &Env) -> Vec<String> {
+    let mut sizes = env
+        .iter()
+        .map(|(key, value)| (key, key.len() + value.len()))
+        .collect::<Vec<_>>();
+    sizes.sort_by(|(a, a_size), (b, b_size)| b_size.cmp(a_size).then(a.cmp(b)));
+    let width = sizes
+        .iter()
+        .map(|(key, _)| key.len())
+        .chain(Some("KEY".len()))
+        .max()
+        .unwrap_or_default();
+    let row = |key: &str, size: String, share: String| {
+        format!("{:width$} {:>5} {:>5}", key, size, share, width = width)
+    };
+    std::iter::once(row("KEY", "BYTES".into(), "SHARE".into()))
+        .chain(sizes.into_iter().map(|(key, size)| {
+            row(
+                key,
+                size.to_string(),
+                format!("{}%", size * 100 / ENV_LIMIT),
+            )
+        }))
+        .collect()
+}
+
+/// Health of a single function of a stage
+#[derive(Debug, PartialEq)]
+pub struct Health {
+    pub size: usize,
+    /// Number of vars differing from those declared, `None` when the
+    /// function is not declared
+    pub drift: Option<usize>,
+    pub status: Option<String>,
+    /// Protected keys the env lacks a value for, and the ways it breaks the
+    /// policy
+    pub violations: Vec<String>,
+}
+
+impl Health {
+    /// Whether anything about the function deserves a closer look
+    pub fn attention(&self) -> bool {
+        self.size > ENV_LIMIT
+            || self.drift.unwrap_or_default() > 0
+            || matches!(self.status.as_deref(), Some(status) if status != "Successful")
+            || !self.violations.is_empty()
+    }
+}
+
+/// Sizes up a function's env, comparing it to `declared` when there is one
+/// and checking it against the protected keys and `policy`
+pub async fn health(
+    lambda: LambdaClient,
+    function: String,
+    declared: Option<Env>,
+    policy: Option<&Policy>,
+) -> Result<Health, Error> {
+    let (env, status) = future::try_join(
+        async { Ok(get(lambda, function.clone()).await?) },
+        status::last_update(&function),
+    )
+    .await?;
+    Ok(Health {
+        size: size(&env),
+        drift: declared.map(|declared| diff(&env, &declared).len()),
+        status,
+        violations: keys::unprotected(&env)
+            .into_iter()
+            .chain(
+                policy
+                    .map(|policy| policy.violations(&env))
+                    .unwrap_or_default(),
+            )
+            .collect(),
+    })
+}
+
+/// Aligned table rows, one per function after a header, with functions
+/// needing attention marked by a leading `!`
+pub fn table(healths: &[(String, Health)]) -> Vec<String> {
+    let cells = healths
+        .iter()
+        .map(|(function, health)| {
+            [
+                if health.attention() { "!" } else { " " }.to_string(),
+                function.clone(),
+                format!("{}/{}", health.size, ENV_LIMIT),
+                health
+                    .drift
+                    .map_or_else(|| "-".into(), |drift| drift.to_string()),
+                health.status.clone().unwrap_or_else(|| "-".into()),
+                match health.violations.as_slice() {
+                    [] => "-".into(),
+                    violations => violations.join(", "),
+                },
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = [
+        " ".to_string(),
+        "FUNCTION".into(),
+        "SIZE".into(),
+        "DRIFT".into(),
+        "STATUS".into(),
+        "VIOLATIONS".into(),
+    ];
+    let rows = std::iter::once(&header).chain(&cells).collect::<Vec<_>>();
+    let widths = (0..header.len())
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_counts_keys_and_values() {
+        let mut env = Env::new();
+        env.insert("AB".into(), "cde".into());
+        env.insert("F".into(), "".into());
+        assert_eq!(size(&env), 6)
+    }
+
+    #[test]
+    fn excess_blames_the_keys_an_update_grew() {
+        let mut previous = Env::new();
+        previous.insert("A".into(), "x".repeat(3000));
+        previous.insert("B".into(), "x".repeat(9));
+        assert_eq!(excess(&previous, &previous), None);
+        let mut updated = previous.clone();
+        updated.insert("B".into(), "x".repeat(99));
+        updated.insert("CERT".into(), "x".repeat(1996));
+        assert_eq!(
+            excess(&previous, &updated),
+            Some((5101, vec!["CERT +2000".to_string(), "B +90".to_string()]))
+        );
+        assert_eq!(
+            excess(&updated, &updated),
+            Some((
+                5101,
+                vec!["A 3001".to_string(), "CERT 2000".into(), "B 100".into()]
+            ))
+        )
+    }
+
+    #[test]
+    fn breakdown_lists_largest_keys_first() {
+        let mut env = Env::new();
+        env.insert("B".into(), "x".repeat(2047));
+        env.insert("A".into(), "x".repeat(99));
+        env.insert("CERT".into(), "x".repeat(96));
+        assert_eq!(
+            breakdown(&env),
+            vec![
+                "KEY  BYTES SHARE",
+                "B     2048   50%",
+                "A      100    2%",
+                "CERT   100    2%",
+            ]
+        )
+    }
+
+    #[test]
+    fn attention_flags_oversized_drifted_and_pending_functions() {
+        let healthy = || Health {
+            size: 10,
+            drift: None,
+            status: Some("Successful".into()),
+            violations: Vec::new(),
+        };
+        assert!(!healthy().attention());
+        assert!(Health {
+            size: ENV_LIMIT + 1,
+            ..healthy()
+        }
+        .attention());
+        assert!(Health {
+            drift: Some(2),
+            status: None,
+            ..healthy()
+        }
+        .attention());
+        assert!(Health {
+            drift: Some(0),
+            status: Some("InProgress".into()),
+            ..healthy()
+        }
+        .attention());
+        assert!(Health {
+            violations: vec!["missing protected DATABASE_URL".into()],
+            ..healthy()
+        }
+        .attention())
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        assert_eq!(
+            table(&[
+                (
+                    "orders".into(),
+                    Health {
+                        size: 120,
+                        drift: Some(0),
+                        status: Some("Successful".into()),
+                        violations: Vec::new(),
+                    }
+                ),
+                (
+                    "payments-handler".into(),
+                    Health {
+                        size: 5000,
+                        drift: None,
+                        status: None,
+                        violations: vec!["sets forbidden DEBUG".into()],
+                    }
+                ),
+            ]),
+            vec![
+                "  FUNCTION         SIZE      DRIFT STATUS     VIOLATIONS",
+                "  orders           120/4096  0     Successful -",
+                "! payments-handler 5000/4096 -     -          sets forbidden DEBUG",
+            ]
+        )
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{edit, error::Error, source, template, value, Env};
+use futures::future;
+use std::collections::HashMap;
+
+/// The env a file holds, as a JSON object or else dotenv lines, with the
+/// placeholders of its values filled in from `vars`. Masked values are
+/// refused
+fn parse(
+    file: &str,
+    contents: &[u8],
+    vars: &HashMap<String, String>,
+) -> Result<Env, Error> {
+    let invalid = |reason: String| Error::InvalidEnvFile(file.into(), reason);
+    let contents =
+        String::from_utf8(contents.to_vec()).map_err(|_| invalid("expected utf-8 text".into()))?;
+    let env: Env = if contents.trim_start().starts_with('{') {
+        serde_json::from_str(&contents).map_err(|err| invalid(err.to_string()))?
+    } else {
+        edit::parse(&contents).map_err(|err| invalid(err.to_string()))?
+    };
+    env.into_iter()
+        .map(|(key, value)| {
+            value::unmasked(&key, &value)?;
+            template::render(&value, vars)
+                .map(|value| (key, value))
+                .map_err(|err| invalid(err.to_string()))
+        })
+        .collect()
+}
+
+/// Merges envs in order, the vars of each overriding those of the ones
+/// before it
+fn merge(layers: Vec<Env>) -> Env {
+    layers.into_iter().flatten().collect()
+}
+
+/// The env layered env files compose, each read from a local path, an
+/// s3://bucket/key or an https:// url, and filled in from `vars`
+pub async fn layered(
+    files: Vec<String>,
+    vars: HashMap<String, String>,
+) -> Result<Env, Error> {
+    let vars = &vars;
+    let layers = future::try_join_all(
+        files
+            .iter()
+            .map(|file| async move { parse(file, &source::read(file).await?, vars) }),
+    )
+    .await?;
+    Ok(merge(layers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_env;
+
+    #[test]
+    fn parse_reads_dotenv_and_json() {
+        assert_eq!(
+            parse(
+                "base.env",
+                b"# shared\nLOG_LEVEL=info\nREGION=\"us-east-1\"\n",
+                &HashMap::new()
+            )
+            .ok(),
+            Some(test_env(&[("LOG_LEVEL", "info"), ("REGION", "us-east-1")]))
+        );
+        assert_eq!(
+            parse("prod.json", br#"{"LOG_LEVEL": "warn"}"#, &HashMap::new()).ok(),
+            Some(test_env(&[("LOG_LEVEL", "warn")]))
+        );
+        match parse("broken.env", b"LOG_LEVEL\n", &HashMap::new()) {
+            Err(Error::InvalidEnvFile(file, _)) => assert_eq!(file, "broken.env"),
+            other => panic!("expected an invalid env file error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_fills_in_values() {
+        let mut vars = HashMap::new();
+        vars.insert("stage".to_string(), "prod".to_string());
+        assert_eq!(
+            parse("base.env", b"API_URL=https://${stage}.example.com\n", &vars).ok(),
+            Some(test_env(&[("API_URL", "https://prod.example.com")]))
+        );
+        match parse("base.env", b"API_URL=${LEV_SYNC_TEST_UNSET}\n", &vars) {
+            Err(Error::InvalidEnvFile(file, _)) => assert_eq!(file, "base.env"),
+            other => panic!("expected an invalid env file error, got {:?}", other),
+        }
+        match parse("base.env", b"API_KEY=****\n", &vars) {
+            Err(Error::MaskedValue(key, _)) => assert_eq!(key, "API_KEY"),
+            other => panic!("expected a masked value error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merge_lets_later_layers_override() {
+        assert_eq!(
+            merge(vec![
+                test_env(&[("LOG_LEVEL", "info"), ("REGION", "us-east-1")]),
+                test_env(&[("LOG_LEVEL", "warn"), ("DEBUG", "false")]),
+            ]),
+            test_env(&[
+                ("LOG_LEVEL", "warn"),
+                ("REGION", "us-east-1"),
+                ("DEBUG", "false")
+            ])
+        )
+    }
+}
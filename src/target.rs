@@ -0,0 +1,324 @@
+use crate::{
+    aws::{self, LambdaClient, TaggingClient},
+    error::Error,
+    parse_key_val,
+};
+use aws_sdk_resourcegroupstagging::types::TagFilter;
+use glob::Pattern;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+use structopt::StructOpt;
+
+pub fn parse_parallelism(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!(
+            "invalid parallelism `{}`: expected a positive number",
+            s
+        )),
+        Ok(n) => Ok(n),
+    }
+}
+
+/// Selects the functions a command applies to, either by name or by tags
+#[derive(StructOpt, PartialEq, Debug)]
+pub struct Target {
+    /// Function name, or a glob pattern matching function names
+    #[structopt(
+        short = "f",
+        long = "function",
+        raw(required_unless_one = r#"&["tags", "group"]"#)
+    )]
+    pub function: Option<String>,
+    /// Selects all functions carrying a tag, may be repeated
+    #[structopt(
+        long = "tag",
+        name = "tags",
+        conflicts_with = "function",
+        number_of_values = 1,
+        parse(try_from_str = "parse_key_val")
+    )]
+    pub tags: Vec<(String, String)>,
+    /// Selects the functions of a group of the `[groups]` of a .lev.toml
+    #[structopt(
+        long = "group",
+        name = "group",
+        raw(conflicts_with_all = r#"&["function", "tags"]"#)
+    )]
+    pub group: Option<String>,
+    /// Maximum number of functions to operate on concurrently
+    #[structopt(
+        long = "parallel",
+        default_value = "4",
+        parse(try_from_str = "parse_parallelism")
+    )]
+    pub parallel: usize,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target {
+            function: None,
+            tags: Vec::new(),
+            group: None,
+            parallel: 4,
+        }
+    }
+}
+
+/// Groups of functions `--group` selects by name
+static GROUPS: Mutex<Option<HashMap<String, Vec<String>>>> = Mutex::new(None);
+
+/// Has `--group` select the functions of these groups
+pub fn set_groups(groups: HashMap<String, Vec<String>>) {
+    *GROUPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(groups)
+}
+
+/// The functions of a group, or `None` when there is no such group
+fn members(group: &str) -> Option<Vec<String>> {
+    GROUPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()?
+        .get(group)
+        .cloned()
+}
+
+/// Function name component of a function arn
+fn function_name(arn: &str) -> String {
+    arn.rsplit(':').next().unwrap_or(arn).to_string()
+}
+
+/// The `(region, account)` a full or partial function arn names, as lambda
+/// reads them: `arn:aws:lambda:<region>:<account>:function:<name>`, with
+/// everything ahead of the name optional
+fn arn(function: &str) -> (Option<String>, Option<String>) {
+    static ARN: OnceLock<Regex> = OnceLock::new();
+    let arn = ARN.get_or_init(|| {
+        Regex::new(
+            r"^(?:arn:aws[a-zA-Z-]*:lambda:)?(?:([a-z]{2}(?:-gov)?-[a-z]+-\d):)?(?:(\d{12}):)?(?:function:)?[a-zA-Z0-9._-]+(?::[a-zA-Z0-9$_-]+)?$",
+        )
+        .expect("invalid arn pattern")
+    });
+    match arn.captures(function) {
+        Some(captures) => (
+            captures.get(1).map(|region| region.as_str().to_string()),
+            captures.get(2).map(|account| account.as_str().to_string()),
+        ),
+        None => (None, None),
+    }
+}
+
+/// The region a function arn pins a function to, full or partial
+pub fn region(function: &str) -> Option<String> {
+    arn(function).0
+}
+
+/// The `(account, region)` a full or partial function arn pins a function
+/// to. Functions named without either live in the account or region lev is
+/// configured for
+pub fn partition(function: &str) -> (Option<String>, Option<String>) {
+    let (region, account) = arn(function);
+    (account, region)
+}
+
+fn tag_filters(tags: &[(String, String)]) -> Vec<TagFilter> {
+    tags.iter()
+        .map(|(key, value)| {
+            TagFilter::builder()
+                .key(key.clone())
+                .values(value.clone())
+                .build()
+        })
+        .collect()
+}
+
+/// Names of all functions carrying every one of the provided tags
+async fn tagged(
+    tagging: TaggingClient,
+    tags: Vec<(String, String)>,
+) -> Result<Vec<String>, Error> {
+    aws::paginate(|token| {
+        let request = tagging
+            .get_resources()
+            .set_pagination_token(token)
+            .resource_type_filters("lambda:function")
+            .set_tag_filters(Some(tag_filters(&tags)));
+        async move {
+            let page = aws::retry(|| request.clone().send()).await?;
+            let names = page
+                .resource_tag_mapping_list
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|mapping| mapping.resource_arn)
+                .map(|arn| function_name(&arn))
+                .collect();
+            Ok((names, page.pagination_token))
+        }
+    })
+    .await
+}
+
+/// Function names containing glob metacharacters select every matching function
+fn is_pattern(name: &str) -> bool {
+    name.contains(&['*', '?', '['][..])
+}
+
+/// Names of all functions matching a glob pattern
+async fn matching(
+    lambda: LambdaClient,
+    pattern: String,
+) -> Result<Vec<String>, Error> {
+    let compiled =
+        Pattern::new(&pattern).map_err(|_| Error::NoMatchingFunctions(pattern.clone()))?;
+    Ok(aws::list_functions(lambda)
+        .await?
+        .into_iter()
+        .filter_map(|conf| conf.function_name)
+        .filter(|name| compiled.matches(name))
+        .collect())
+}
+
+/// Sorted names, or `none` if nothing was selected
+fn nonempty(
+    mut names: Vec<String>,
+    none: Error,
+) -> Result<Vec<String>, Error> {
+    if names.is_empty() {
+        return Err(none);
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Resolves a target into the names of the functions it selects
+pub async fn functions(
+    lambda: LambdaClient,
+    tagging: TaggingClient,
+    target: Target,
+) -> Result<Vec<String>, Error> {
+    if let Some(group) = target.group {
+        let names = members(&group).ok_or_else(|| Error::NoSuchGroup(group.clone()))?;
+        return nonempty(names, Error::EmptyGroup(group));
+    }
+    match target.function {
+        Some(function) => {
+            if !is_pattern(&function) {
+                return Ok(vec![function]);
+            }
+            let names = matching(lambda, function.clone()).await?;
+            nonempty(names, Error::NoMatchingFunctions(function))
+        }
+        None => {
+            let description = target
+                .tags
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let names = tagged(tagging, target.tags).await?;
+            nonempty(names, Error::NoTaggedFunctions(description))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_parallelism_rejects_zero() {
+        assert_eq!(parse_parallelism("8"), Ok(8));
+        assert!(parse_parallelism("0").is_err());
+        assert!(parse_parallelism("many").is_err())
+    }
+
+    #[test]
+    fn function_name_extracts_from_arn() {
+        assert_eq!(
+            function_name("arn:aws:lambda:us-east-1:123456789012:function:my-func"),
+            "my-func"
+        )
+    }
+
+    #[test]
+    fn partition_extracts_account_and_region() {
+        assert_eq!(
+            partition("arn:aws:lambda:eu-west-1:123456789012:function:my-func:live"),
+            (Some("123456789012".into()), Some("eu-west-1".into()))
+        );
+        assert_eq!(
+            partition("eu-west-1:my-func"),
+            (None, Some("eu-west-1".into()))
+        );
+        assert_eq!(partition("my-func"), (None, None))
+    }
+
+    #[test]
+    fn region_reads_full_and_partial_arns() {
+        assert_eq!(
+            region("arn:aws:lambda:eu-west-1:123456789012:function:my-func"),
+            Some("eu-west-1".into())
+        );
+        assert_eq!(
+            region("us-gov-west-1:123456789012:function:my-func:live"),
+            Some("us-gov-west-1".into())
+        );
+        assert_eq!(region("123456789012:function:my-func"), None);
+        assert_eq!(region("123456789012:my-func"), None);
+        assert_eq!(region("my-func"), None)
+    }
+
+    #[tokio::test]
+    async fn functions_selects_group_members() {
+        let mut groups = HashMap::new();
+        groups.insert(
+            "backend".to_string(),
+            vec!["worker".to_string(), "api".to_string()],
+        );
+        groups.insert("nobody".to_string(), Vec::new());
+        set_groups(groups);
+        async fn selected(group: &str) -> Result<Vec<String>, Error> {
+            functions(
+                aws::lambda_client().await,
+                aws::tagging_client().await,
+                Target {
+                    group: Some(group.into()),
+                    ..Target::default()
+                },
+            )
+            .await
+        }
+        assert_eq!(
+            selected("backend").await.ok(),
+            Some(vec!["api".to_string(), "worker".to_string()])
+        );
+        match selected("frontend").await {
+            Err(Error::NoSuchGroup(group)) => assert_eq!(group, "frontend"),
+            other => panic!("expected a missing group error, got {:?}", other),
+        }
+        match selected("nobody").await {
+            Err(Error::EmptyGroup(group)) => assert_eq!(group, "nobody"),
+            other => panic!("expected an empty group error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn is_pattern_detects_glob_metacharacters() {
+        assert!(is_pattern("orders-*"));
+        assert!(is_pattern("orders-?"));
+        assert!(!is_pattern("orders-prod-handler"))
+    }
+
+    #[test]
+    fn tag_filters_match_single_values() {
+        assert_eq!(
+            tag_filters(&[("team".into(), "payments".into())]),
+            vec![TagFilter::builder().key("team").values("payments").build()]
+        )
+    }
+}
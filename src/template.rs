@@ -0,0 +1,126 @@
+use crate::{error::Error, Env};
+use std::{collections::HashMap, env};
+
+/// Whether a name could be that of an env var, as `{NAME}` references go
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.is_ascii_alphabetic() || first == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A value with its `{NAME}` references to vars of `env` filled in with
+/// their values. Braces around anything else, such as names `env` lacks or
+/// JSON, are left as they are, and `{{NAME}}` stands for a literal `{NAME}`
+pub fn referencing(
+    value: &str,
+    env: &Env,
+) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let escaped = rest
+            .strip_prefix("{{")
+            .and_then(|inner| inner.split_once("}}"))
+            .filter(|(name, _)| is_name(name));
+        if let Some((name, after)) = escaped {
+            out.push_str(&format!("{{{}}}", name));
+            rest = after;
+            continue;
+        }
+        let referenced = rest[1..]
+            .split_once('}')
+            .filter(|(name, _)| is_name(name))
+            .and_then(|(name, after)| Some((env.get(name)?, after)));
+        match referenced {
+            Some((value, after)) => {
+                out.push_str(value);
+                rest = after;
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A value with its `${NAME}` placeholders filled in from `vars`, or else
+/// from the local env. `$${` stands for a literal `${`
+pub fn render(
+    value: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        if let Some(before) = rest[..start].strip_suffix('$') {
+            out.push_str(before);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| Error::InvalidTemplateValue(value.into()))?;
+        let name = &rest[start + 2..end];
+        let filled = vars
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+            .ok_or_else(|| Error::UndefinedTemplateVar(name.into()))?;
+        out.push_str(&filled);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn referencing_fills_in_current_vars() {
+        let mut env = Env::new();
+        env.insert("BASE_URL".into(), "https://api.example.com".into());
+        assert_eq!(
+            referencing("{BASE_URL}/v2", &env),
+            "https://api.example.com/v2"
+        );
+        assert_eq!(referencing("{{BASE_URL}}/v2", &env), "{BASE_URL}/v2");
+        assert_eq!(referencing("/users/{id}", &env), "/users/{id}");
+        assert_eq!(
+            referencing(r#"{"url": "{BASE_URL}"}"#, &env),
+            r#"{"url": "https://api.example.com"}"#
+        );
+        assert_eq!(referencing("{BASE_URL", &env), "{BASE_URL")
+    }
+
+    #[test]
+    fn render_fills_in_vars() {
+        let mut vars = HashMap::new();
+        vars.insert("stage".to_string(), "prod".to_string());
+        assert_eq!(
+            render("https://${stage}.example.com/$${stage}", &vars).ok(),
+            Some("https://prod.example.com/${stage}".into())
+        );
+        assert_eq!(render("$5 flat", &vars).ok(), Some("$5 flat".into()));
+        match render("${LEV_TEMPLATE_TEST_UNSET}", &vars) {
+            Err(Error::UndefinedTemplateVar(name)) => assert_eq!(name, "LEV_TEMPLATE_TEST_UNSET"),
+            other => panic!("expected an undefined var error, got {:?}", other),
+        }
+        match render("${stage", &vars) {
+            Err(Error::InvalidTemplateValue(value)) => assert_eq!(value, "${stage"),
+            other => panic!("expected an invalid template error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{aws::SdkError, console};
+use aws_config::Region;
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    client::{
+        interceptors::{
+            context::{BeforeTransmitInterceptorContextRef, FinalizerInterceptorContextRef},
+            Intercept,
+        },
+        orchestrator::Metadata as Operation,
+        runtime_components::RuntimeComponents,
+    },
+    http::Headers,
+};
+use aws_smithy_types::config_bag::{ConfigBag, Storable, StoreReplace};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::time::Instant;
+
+/// Header aws answers every request with the id it knows the request by
+const REQUEST_ID: &str = "x-amzn-requestid";
+
+/// Header s3 answers with in place of `x-amzn-requestid`
+const S3_REQUEST_ID: &str = "x-amz-request-id";
+
+/// Prints lev's own records to stderr, leaving those of its dependencies out
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(
+        &self,
+        metadata: &Metadata,
+    ) -> bool {
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(
+        &self,
+        record: &Record,
+    ) {
+        if self.enabled(record.metadata()) {
+            console::err(format_args!("lev: {}\n", record.args()))
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Logs each aws call and its retries with `-v`, and each http request
+/// made along with its aws request id with `-vv`
+pub fn set_verbosity(verbose: u8) {
+    static LOGGER: Logger = Logger;
+    // only the first logger set sticks, which is this one every time
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(match verbose {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    })
+}
+
+/// The operation an aws error type belongs to, `GetFunctionConfiguration`
+/// for a `GetFunctionConfigurationError`
+pub fn operation<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    let name = name.rsplit("::").next().unwrap_or(name);
+    name.strip_suffix("Error").unwrap_or(name)
+}
+
+/// The id aws knows a request by, from the headers of its response
+fn request_id(headers: &Headers) -> Option<&str> {
+    headers
+        .get(REQUEST_ID)
+        .or_else(|| headers.get(S3_REQUEST_ID))
+}
+
+/// The id aws knows a failed request by, when the failure kept the response
+pub fn failed_request_id<E>(err: &SdkError<E>) -> Option<&str> {
+    err.raw_response()
+        .and_then(|response| request_id(response.headers()))
+}
+
+/// When the request of an attempt was sent
+#[derive(Debug)]
+struct Sent(Instant);
+
+impl Storable for Sent {
+    type Storer = StoreReplace<Self>;
+}
+
+/// An interceptor which traces every request sent, and how it was answered
+#[derive(Debug)]
+pub struct Traced;
+
+impl Intercept for Traced {
+    fn name(&self) -> &'static str {
+        "Traced"
+    }
+
+    fn read_before_transmit(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        cfg.interceptor_state().store_put(Sent(Instant::now()));
+        Ok(())
+    }
+
+    fn read_after_attempt(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if !log::log_enabled!(Level::Trace) {
+            return Ok(());
+        }
+        let request = match context.request() {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        let described = format!(
+            "{} {} {} {}",
+            cfg.load::<Operation>()
+                .map(Operation::service)
+                .unwrap_or("aws"),
+            cfg.load::<Region>()
+                .map(Region::as_ref)
+                .unwrap_or("unknown"),
+            request.method(),
+            request.uri()
+        );
+        let elapsed = cfg
+            .load::<Sent>()
+            .map(|sent| sent.0.elapsed().as_millis())
+            .unwrap_or_default();
+        match (context.response(), context.output_or_error()) {
+            (Some(response), _) => log::trace!(
+                "{} {} in {}ms, request id {}",
+                described,
+                response.status(),
+                elapsed,
+                request_id(response.headers()).unwrap_or("unknown")
+            ),
+            (None, Some(Err(err))) => {
+                log::trace!("{} failed in {}ms: {}", described, elapsed, err)
+            }
+            (None, _) => log::trace!("{} failed in {}ms", described, elapsed),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_lambda::operation::get_function_configuration::GetFunctionConfigurationError;
+
+    #[test]
+    fn operation_names_error_types() {
+        assert_eq!(
+            operation::<GetFunctionConfigurationError>(),
+            "GetFunctionConfiguration"
+        );
+        assert_eq!(operation::<String>(), "String")
+    }
+}
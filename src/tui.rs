@@ -0,0 +1,634 @@
+use crate::{
+    diff::{diff, Change},
+    error::Error,
+    functions, redact, Env,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+use std::{
+    collections::BTreeSet,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
+};
+
+/// Most lines the pending changes pane takes up
+const MAX_DIFF_LINES: u16 = 10;
+
+/// How often pages of function names still being listed are looked for
+const LISTING_POLL: Duration = Duration::from_millis(100);
+
+/// Where browsed envs are read from and edits are applied to
+pub trait Envs {
+    fn load(
+        &mut self,
+        function: &str,
+    ) -> Result<Env, Error>;
+
+    fn apply(
+        &mut self,
+        function: &str,
+        current: Env,
+        updated: Env,
+    ) -> Result<Vec<Change>, Error>;
+}
+
+/// What the prompt line is collecting
+#[derive(Debug, PartialEq)]
+enum Prompt {
+    Filter,
+    /// `KEY=value` of a var to add
+    Add,
+    /// New value of an existing var
+    Edit(String),
+}
+
+/// A function's env being browsed, along with the edits staged to it
+struct Editor {
+    function: String,
+    current: Env,
+    pending: Env,
+    selected: usize,
+    /// The staged changes are shown, waiting on y or n
+    confirming: bool,
+    /// Leaving was asked for once with edits still staged
+    discarding: bool,
+}
+
+impl Editor {
+    /// Keys of both the current and pending env, in order
+    fn keys(&self) -> Vec<&String> {
+        self.current
+            .keys()
+            .chain(self.pending.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    fn changes(&self) -> Vec<Change> {
+        diff(&self.current, &self.pending)
+    }
+
+    fn selected_key(&self) -> Option<String> {
+        self.keys().get(self.selected).map(|key| key.to_string())
+    }
+}
+
+/// What the event loop is asked to do after a key press
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Load(String),
+    Apply,
+    Quit,
+}
+
+/// State of the browser: a list of functions, and the env of the function
+/// opened from it
+pub struct App {
+    functions: Vec<String>,
+    /// More functions are still being listed
+    listing: bool,
+    filter: String,
+    selected: usize,
+    editor: Option<Editor>,
+    prompt: Option<Prompt>,
+    input: String,
+    status: String,
+}
+
+/// Moves a selection within `len` items
+fn step(
+    selected: usize,
+    len: usize,
+    code: KeyCode,
+) -> usize {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => selected.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => (selected + 1).min(len.saturating_sub(1)),
+        _ => selected,
+    }
+}
+
+/// A value on a single line
+fn one_line(value: &str) -> String {
+    value.replace('\n', "\\n").replace('\r', "\\r")
+}
+
+impl App {
+    pub fn new(functions: Vec<String>) -> Self {
+        App {
+            functions,
+            listing: false,
+            filter: String::new(),
+            selected: 0,
+            editor: None,
+            prompt: None,
+            input: String::new(),
+            status: "enter opens a function, / filters, q quits".into(),
+        }
+    }
+
+    fn visible(&self) -> Vec<&String> {
+        functions::matching(&self.functions, &self.filter).collect()
+    }
+
+    /// Opens a loaded function's env
+    pub fn open(
+        &mut self,
+        function: String,
+        env: Env,
+    ) {
+        self.editor = Some(Editor {
+            function,
+            current: env.clone(),
+            pending: env,
+            selected: 0,
+            confirming: false,
+            discarding: false,
+        });
+        self.status = "a adds, e edits, d deletes, u undoes, w applies, esc goes back".into();
+    }
+
+    /// Adds a page of functions as they are listed, or with `None` notes
+    /// they all have been
+    pub fn listed(
+        &mut self,
+        page: Option<Vec<String>>,
+    ) {
+        match page {
+            Some(page) => self.functions.extend(page),
+            None => self.listing = false,
+        }
+    }
+
+    /// Records the outcome of applying the staged edits
+    pub fn applied(
+        &mut self,
+        result: Result<Vec<Change>, Error>,
+    ) {
+        if let Some(editor) = self.editor.as_mut() {
+            editor.confirming = false;
+            self.status = match result {
+                Ok(changes) => {
+                    editor.current = editor.pending.clone();
+                    format!("applied {} changes", changes.len())
+                }
+                Err(err) => format!("failed to apply: {}", err),
+            }
+        }
+    }
+
+    pub fn report(
+        &mut self,
+        err: &Error,
+    ) {
+        self.status = err.to_string()
+    }
+
+    pub fn key(
+        &mut self,
+        code: KeyCode,
+    ) -> Action {
+        if self.prompt.is_some() {
+            self.prompt_key(code);
+            return Action::None;
+        }
+        if self.editor.is_some() {
+            self.editor_key(code)
+        } else {
+            self.functions_key(code)
+        }
+    }
+
+    fn prompt_key(
+        &mut self,
+        code: KeyCode,
+    ) {
+        match code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Esc => {
+                if self.prompt == Some(Prompt::Filter) {
+                    self.filter.clear();
+                }
+                self.prompt = None;
+                return;
+            }
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                if let Some(prompt) = self.prompt.take() {
+                    self.submit(prompt, input);
+                }
+                return;
+            }
+            _ => (),
+        }
+        if self.prompt == Some(Prompt::Filter) {
+            self.filter = self.input.clone();
+            self.selected = 0;
+        }
+    }
+
+    fn submit(
+        &mut self,
+        prompt: Prompt,
+        input: String,
+    ) {
+        let editor = match (&prompt, self.editor.as_mut()) {
+            (Prompt::Filter, _) | (_, None) => return,
+            (_, Some(editor)) => editor,
+        };
+        match prompt {
+            Prompt::Add => match input.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    editor
+                        .pending
+                        .insert(key.trim().to_string(), value.to_string());
+                    self.status = format!("staged {}", key.trim());
+                }
+                _ => self.status = "expected KEY=value".into(),
+            },
+            Prompt::Edit(key) => {
+                self.status = format!("staged {}", key);
+                editor.pending.insert(key, input);
+            }
+            Prompt::Filter => (),
+        }
+    }
+
+    fn functions_key(
+        &mut self,
+        code: KeyCode,
+    ) -> Action {
+        let visible = self.visible().len();
+        match code {
+            KeyCode::Char('q') => return Action::Quit,
+            KeyCode::Esc if self.filter.is_empty() => return Action::Quit,
+            KeyCode::Esc => self.filter.clear(),
+            KeyCode::Char('/') => {
+                self.prompt = Some(Prompt::Filter);
+                self.input = self.filter.clone();
+            }
+            KeyCode::Enter => {
+                if let Some(function) = self.visible().get(self.selected) {
+                    return Action::Load(function.to_string());
+                }
+            }
+            code => self.selected = step(self.selected, visible, code),
+        }
+        Action::None
+    }
+
+    fn editor_key(
+        &mut self,
+        code: KeyCode,
+    ) -> Action {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return Action::None,
+        };
+        if editor.confirming {
+            match code {
+                KeyCode::Char('y') => return Action::Apply,
+                KeyCode::Char('n') | KeyCode::Esc => editor.confirming = false,
+                _ => (),
+            }
+            return Action::None;
+        }
+        let discarding = std::mem::replace(&mut editor.discarding, false);
+        match code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                if editor.changes().is_empty() || discarding {
+                    self.editor = None;
+                    self.status = "enter opens a function, / filters, q quits".into();
+                } else {
+                    editor.discarding = true;
+                    self.status =
+                        "edits are not applied yet, press esc again to discard them".into();
+                }
+            }
+            KeyCode::Char('a') => {
+                self.prompt = Some(Prompt::Add);
+                self.input.clear();
+            }
+            KeyCode::Char('e') | KeyCode::Enter => {
+                if let Some(key) = editor.selected_key() {
+                    // redacted values are typed afresh rather than shown
+                    self.input = if redact::redacted(&key) {
+                        String::new()
+                    } else {
+                        editor
+                            .pending
+                            .get(&key)
+                            .or_else(|| editor.current.get(&key))
+                            .cloned()
+                            .unwrap_or_default()
+                    };
+                    self.prompt = Some(Prompt::Edit(key));
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(key) = editor.selected_key() {
+                    if editor.pending.remove(&key).is_none() {
+                        if let Some(value) = editor.current.get(&key) {
+                            editor.pending.insert(key, value.clone());
+                        }
+                    }
+                }
+            }
+            KeyCode::Char('u') => editor.pending = editor.current.clone(),
+            KeyCode::Char('w') => {
+                if editor.changes().is_empty() {
+                    self.status = "no edits to apply".into();
+                } else {
+                    editor.confirming = true;
+                    self.status = "apply these changes? y/n".into();
+                }
+            }
+            code => editor.selected = step(editor.selected, editor.keys().len(), code),
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        frame: &mut Frame,
+    ) {
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+        let changes = self
+            .editor
+            .as_ref()
+            .map(|editor| editor.changes())
+            .unwrap_or_default();
+        let diff_height = if changes.is_empty() {
+            0
+        } else {
+            (changes.len() as u16 + 2).min(MAX_DIFF_LINES)
+        };
+        let [main, pending, footer] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(diff_height),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        match &self.editor {
+            None => {
+                let items = self
+                    .visible()
+                    .into_iter()
+                    .map(|function| ListItem::new(function.as_str()))
+                    .collect::<Vec<_>>();
+                let mut title = if self.filter.is_empty() {
+                    "functions".to_string()
+                } else {
+                    format!("functions matching {}", self.filter)
+                };
+                if self.listing {
+                    title.push_str(", listing more");
+                }
+                let mut state = ListState::default().with_selected(Some(self.selected));
+                frame.render_stateful_widget(
+                    List::new(items)
+                        .block(Block::default().borders(Borders::ALL).title(title))
+                        .highlight_style(highlight),
+                    main,
+                    &mut state,
+                );
+            }
+            Some(editor) => {
+                let items = editor
+                    .keys()
+                    .into_iter()
+                    .map(|key| {
+                        let (marker, value) =
+                            match (editor.current.get(key), editor.pending.get(key)) {
+                                (None, Some(value)) => ("+", value),
+                                (Some(value), None) => ("-", value),
+                                (Some(old), Some(new)) if old != new => ("~", new),
+                                (_, value) => (" ", value.unwrap_or(key)),
+                            };
+                        ListItem::new(format!(
+                            "{} {}={}",
+                            marker,
+                            key,
+                            one_line(redact::value(key, value))
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                let mut state = ListState::default().with_selected(Some(editor.selected));
+                frame.render_stateful_widget(
+                    List::new(items)
+                        .block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .title(editor.function.as_str()),
+                        )
+                        .highlight_style(highlight),
+                    main,
+                    &mut state,
+                );
+                let lines = changes
+                    .iter()
+                    .map(|change| one_line(&change.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let title = if editor.confirming {
+                    "apply these changes? y/n"
+                } else {
+                    "staged changes"
+                };
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .block(Block::default().borders(Borders::ALL).title(title)),
+                    pending,
+                );
+            }
+        }
+        let line = match &self.prompt {
+            None => self.status.clone(),
+            Some(Prompt::Filter) => format!("filter: {}", self.input),
+            Some(Prompt::Add) => format!("add KEY=value: {}", self.input),
+            Some(Prompt::Edit(key)) => format!("{}={}", key, self.input),
+        };
+        frame.render_widget(Paragraph::new(line), footer);
+    }
+}
+
+/// Adds the pages of functions listed so far
+fn receive(
+    app: &mut App,
+    pages: &Receiver<Result<Vec<String>, Error>>,
+) {
+    loop {
+        match pages.try_recv() {
+            Ok(Ok(page)) => app.listed(Some(page)),
+            Ok(Err(err)) => app.report(&err),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => return app.listed(None),
+        }
+    }
+}
+
+/// Browses functions and their envs in the terminal until asked to quit.
+/// Functions still being listed are added as `pages` of them arrive, the
+/// browser opening without waiting on them
+pub fn run<E>(
+    functions: Vec<String>,
+    pages: Option<Receiver<Result<Vec<String>, Error>>>,
+    envs: &mut E,
+) -> Result<(), Error>
+where
+    E: Envs,
+{
+    let mut app = App::new(functions);
+    app.listing = pages.is_some();
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<(), Error> {
+        loop {
+            if app.listing {
+                if let Some(pages) = &pages {
+                    receive(&mut app, pages);
+                }
+            }
+            terminal.draw(|frame| app.draw(frame))?;
+            if app.listing && !event::poll(LISTING_POLL)? {
+                continue;
+            }
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(());
+            }
+            match app.key(key.code) {
+                Action::None => (),
+                Action::Quit => return Ok(()),
+                Action::Load(function) => match envs.load(&function) {
+                    Ok(env) => app.open(function, env),
+                    Err(err) => app.report(&err),
+                },
+                Action::Apply => {
+                    if let Some(editor) = &app.editor {
+                        let result = envs.apply(
+                            &editor.function,
+                            editor.current.clone(),
+                            editor.pending.clone(),
+                        );
+                        app.applied(result);
+                    }
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(
+        app: &mut App,
+        text: &str,
+    ) {
+        for c in text.chars() {
+            app.key(KeyCode::Char(c));
+        }
+    }
+
+    fn opened() -> App {
+        let mut app = App::new(vec!["orders".into()]);
+        let mut env = Env::new();
+        env.insert("A".into(), "1".into());
+        env.insert("B".into(), "2".into());
+        app.open("orders".into(), env);
+        app
+    }
+
+    #[test]
+    fn filter_narrows_functions_to_load() {
+        let mut app = App::new(vec!["orders".into(), "payments".into()]);
+        app.key(KeyCode::Char('/'));
+        typed(&mut app, "pay");
+        app.key(KeyCode::Enter);
+        assert_eq!(app.key(KeyCode::Enter), Action::Load("payments".into()));
+        app.key(KeyCode::Esc);
+        app.key(KeyCode::Down);
+        assert_eq!(app.key(KeyCode::Enter), Action::Load("payments".into()));
+        assert_eq!(app.key(KeyCode::Char('q')), Action::Quit)
+    }
+
+    #[test]
+    fn listed_pages_are_added_as_they_arrive() {
+        let (sender, pages) = std::sync::mpsc::channel();
+        let mut app = App::new(Vec::new());
+        app.listing = true;
+        sender.send(Ok(vec!["orders".to_string()])).unwrap();
+        receive(&mut app, &pages);
+        assert_eq!(app.visible(), vec!["orders"]);
+        assert!(app.listing);
+        sender.send(Ok(vec!["payments".to_string()])).unwrap();
+        drop(sender);
+        receive(&mut app, &pages);
+        assert_eq!(app.visible(), vec!["orders", "payments"]);
+        assert!(!app.listing);
+        assert_eq!(app.key(KeyCode::Enter), Action::Load("orders".into()))
+    }
+
+    #[test]
+    fn edits_are_staged_until_confirmed() {
+        let mut app = opened();
+        app.key(KeyCode::Char('a'));
+        typed(&mut app, "C=3");
+        app.key(KeyCode::Enter);
+        app.key(KeyCode::Char('d'));
+        app.key(KeyCode::Down);
+        app.key(KeyCode::Char('e'));
+        app.key(KeyCode::Backspace);
+        typed(&mut app, "two");
+        app.key(KeyCode::Enter);
+        let editor = app.editor.as_ref().unwrap();
+        assert_eq!(
+            editor.changes(),
+            vec![
+                Change::Removed("A".into(), "1".into()),
+                Change::Changed("B".into(), "2".into(), "two".into()),
+                Change::Added("C".into(), "3".into()),
+            ]
+        );
+        app.key(KeyCode::Char('w'));
+        assert_eq!(app.key(KeyCode::Char('n')), Action::None);
+        app.key(KeyCode::Char('w'));
+        assert_eq!(app.key(KeyCode::Char('y')), Action::Apply);
+        app.applied(Ok(Vec::new()));
+        assert!(app.editor.as_ref().unwrap().changes().is_empty())
+    }
+
+    #[test]
+    fn leaving_with_staged_edits_needs_confirming() {
+        let mut app = opened();
+        app.key(KeyCode::Char('d'));
+        app.key(KeyCode::Esc);
+        assert!(app.editor.is_some());
+        app.key(KeyCode::Esc);
+        assert!(app.editor.is_none())
+    }
+
+    #[test]
+    fn deleting_twice_restores_the_var() {
+        let mut app = opened();
+        app.key(KeyCode::Char('d'));
+        app.key(KeyCode::Char('d'));
+        assert!(app.editor.as_ref().unwrap().changes().is_empty())
+    }
+}
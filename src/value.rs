@@ -0,0 +1,391 @@
+use crate::{edit, error::Error, redact, summary::ENV_LIMIT, Env};
+use std::{env, fs, io::Read};
+
+/// Prefix of values read from the file named after it
+const FILE: char = '@';
+
+/// Value standing for whatever is piped to stdin
+const STDIN: &str = "-";
+
+/// Refuses values which would take the env past lambda's limit on their own
+fn fits(
+    key: &str,
+    size: usize,
+) -> Result<(), Error> {
+    if key.len() + size > ENV_LIMIT {
+        return Err(Error::ValueTooLarge(
+            key.into(),
+            key.len() + size,
+            ENV_LIMIT,
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses the mask `lev get` prints in place of redacted values, which
+/// piping one env into another would otherwise set as if it were the value
+pub fn unmasked(
+    key: &str,
+    value: &str,
+) -> Result<(), Error> {
+    if value == redact::MASK {
+        return Err(Error::MaskedValue(key.into(), redact::MASK));
+    }
+    Ok(())
+}
+
+/// The bytes a var is set to: the contents of the file an `@path` value
+/// names, less the newline ending text as with stdin, or else the value as
+/// given. `@@` sets a value starting with a literal `@`
+fn read(
+    value: String,
+    text: bool,
+) -> Result<Vec<u8>, Error> {
+    let path = match value.strip_prefix(FILE) {
+        None => return Ok(value.into_bytes()),
+        Some(escaped) if escaped.starts_with(FILE) => return Ok(escaped.into()),
+        Some(path) => path,
+    };
+    fs::read(path)
+        .map(|value| trimmed(value, text))
+        .map_err(|err| Error::ValueFile(path.into(), err.to_string()))
+}
+
+/// Reads what is piped to stdin, as `trimmed`
+fn piped(
+    mut stdin: impl Read,
+    text: bool,
+) -> Result<Vec<u8>, Error> {
+    let mut value = Vec::new();
+    stdin.read_to_end(&mut value)?;
+    Ok(trimmed(value, text))
+}
+
+/// Drops the newline ending text, while bytes about to be base64 encoded are
+/// kept as they are
+fn trimmed(
+    mut value: Vec<u8>,
+    text: bool,
+) -> Vec<u8> {
+    if text && value.ends_with(b"\n") {
+        value.pop();
+        if value.ends_with(b"\r") {
+            value.pop();
+        }
+    }
+    value
+}
+
+/// The value of the local env var a var without a value inherits
+fn inherited(key: &str) -> Result<Vec<u8>, Error> {
+    env::var(key)
+        .map(String::into_bytes)
+        .map_err(|_| Error::NotInherited(key.into()))
+}
+
+/// The value a var is set to from the bytes read for it, base64 encoded when
+/// its key is one of `encoded`
+pub fn encode(
+    key: &str,
+    bytes: Vec<u8>,
+    encoded: &[String],
+) -> Result<String, Error> {
+    let value = if encoded.iter().any(|encoded| encoded == key) {
+        base64::encode(&bytes)
+    } else {
+        String::from_utf8(bytes).map_err(|_| Error::BinaryValue(key.into()))?
+    };
+    fits(key, value.len())?;
+    Ok(value)
+}
+
+/// Whether a value stands for whatever is piped to stdin
+pub fn is_stdin(value: &Option<String>) -> bool {
+    value.as_deref() == Some(STDIN)
+}
+
+/// Reads the values of each var, as `read` does, reading a value of `-` from
+/// stdin and inheriting those without one from the local env. Only one var
+/// can be read from stdin
+pub fn read_all(
+    vars: Vec<(String, Option<String>)>,
+    stdin: impl Read,
+    encoded: &[String],
+) -> Result<Vec<(String, String)>, Error> {
+    let mut piping = vars
+        .iter()
+        .filter(|(_, value)| is_stdin(value))
+        .map(|(key, _)| key);
+    if let (Some(first), Some(second)) = (piping.next(), piping.next()) {
+        return Err(Error::StdinTaken(first.clone(), second.clone()));
+    }
+    let mut stdin = Some(stdin);
+    vars.into_iter()
+        .map(|(key, value)| {
+            let text = !encoded.contains(&key);
+            let bytes = match value {
+                None => inherited(&key)?,
+                Some(value) => match stdin.take_if(|_| value == STDIN) {
+                    Some(stdin) => piped(stdin, text)?,
+                    None => read(value, text)?,
+                },
+            };
+            encode(&key, bytes, encoded).map(|value| (key, value))
+        })
+        .collect()
+}
+
+/// Vars piped to stdin as a JSON object, or else dotenv lines, with the
+/// values of `encoded` keys base64 encoded. Masked values are refused
+pub fn read_env(
+    mut stdin: impl Read,
+    encoded: &[String],
+) -> Result<Vec<(String, String)>, Error> {
+    let mut contents = String::new();
+    stdin.read_to_string(&mut contents)?;
+    let env: Env = if contents.trim_start().starts_with('{') {
+        serde_json::from_str(&contents)?
+    } else {
+        edit::parse(&contents)?
+    };
+    env.into_iter()
+        .map(|(key, value)| {
+            unmasked(&key, &value)?;
+            encode(&key, value.into_bytes(), encoded).map(|value| (key, value))
+        })
+        .collect()
+}
+
+/// Decodes the base64 values of `keys` a function's env defines
+pub fn decode(
+    function: &str,
+    mut env: Env,
+    keys: &[String],
+) -> Result<Env, Error> {
+    for key in keys {
+        if let Some(value) = env.get_mut(key) {
+            *value = base64::decode(value.as_str())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| Error::InvalidBase64(function.into(), key.clone()))?;
+        }
+    }
+    Ok(env)
+}
+
+/// Whether a value is given as it is on the command line and references
+/// current vars, rather than being read from a file or stdin or inherited,
+/// whose contents are set as they are
+pub fn is_template(value: &Option<String>) -> bool {
+    match value {
+        Some(value) => {
+            value != STDIN
+                && value
+                    .strip_prefix(FILE)
+                    .is_none_or(|rest| rest.starts_with(FILE))
+                && value.contains('{')
+        }
+        None => false,
+    }
+}
+
+/// Whether any var is read from stdin
+pub fn reads_stdin(vars: &[(String, Option<String>)]) -> bool {
+    vars.iter().any(|(_, value)| is_stdin(value))
+}
+
+/// Whether any var inherits its value from the local env
+pub fn inherits(vars: &[(String, Option<String>)]) -> bool {
+    vars.iter().any(|(_, value)| value.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(
+        name: &str,
+        contents: &[u8],
+    ) -> String {
+        let path =
+            std::env::temp_dir().join(format!("lev-value-test-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    fn given(
+        key: &str,
+        value: &str,
+    ) -> (String, Option<String>) {
+        (key.into(), Some(value.into()))
+    }
+
+    #[test]
+    fn read_all_loads_files() {
+        let path = file("cert", b"-----BEGIN CERTIFICATE-----\nMIIB\r\n");
+        assert_eq!(
+            read_all(
+                vec![given("CERT", &format!("@{}", path))],
+                std::io::empty(),
+                &[]
+            )
+            .ok(),
+            Some(vec![(
+                "CERT".into(),
+                "-----BEGIN CERTIFICATE-----\nMIIB".into()
+            )])
+        );
+        assert_eq!(
+            read_all(
+                vec![given("CERT", &format!("@{}", path))],
+                &b"-----BEGIN CERTIFICATE-----\nMIIB\r\n"[..],
+                &[]
+            )
+            .ok(),
+            read_all(
+                vec![given("CERT", "-")],
+                &b"-----BEGIN CERTIFICATE-----\nMIIB\r\n"[..],
+                &[]
+            )
+            .ok()
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn read_all_leaves_other_values_alone() {
+        assert_eq!(
+            read_all(
+                vec![given("A", "plain"), given("B", "@@handle")],
+                std::io::empty(),
+                &[]
+            )
+            .ok(),
+            Some(vec![
+                ("A".into(), "plain".into()),
+                ("B".into(), "@handle".into())
+            ])
+        )
+    }
+
+    #[test]
+    fn read_all_encodes_selected_values() {
+        let path = file("key", &[0, 159, 146, 150]);
+        let vars = vec![given("KEY", &format!("@{}", path)), given("A", "a")];
+        assert_eq!(
+            read_all(vars.clone(), std::io::empty(), &["KEY".into()]).ok(),
+            Some(vec![
+                ("KEY".into(), "AJ+Slg==".into()),
+                ("A".into(), "a".into())
+            ])
+        );
+        match read_all(vars, std::io::empty(), &[]) {
+            Err(Error::BinaryValue(key)) => assert_eq!(key, "KEY"),
+            other => panic!("expected a binary value error, got {:?}", other),
+        }
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn decode_decodes_selected_values() {
+        let mut env = Env::new();
+        env.insert("A".into(), "aGk=".into());
+        env.insert("B".into(), "aGk=".into());
+        let decoded = decode("foo", env.clone(), &["A".into(), "C".into()]).unwrap();
+        assert_eq!(decoded["A"], "hi");
+        assert_eq!(decoded["B"], "aGk=");
+        env.insert("A".into(), "not base64!".into());
+        match decode("foo", env, &["A".into()]) {
+            Err(Error::InvalidBase64(function, key)) => {
+                assert_eq!((function, key), ("foo".into(), "A".into()))
+            }
+            other => panic!("expected an invalid base64 error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_all_reads_one_value_from_stdin() {
+        let vars = vec![given("TOKEN", "-"), given("A", "1")];
+        assert_eq!(
+            read_all(vars, &b"s3cr3t\n"[..], &[]).ok(),
+            Some(vec![
+                ("TOKEN".into(), "s3cr3t".into()),
+                ("A".into(), "1".into())
+            ])
+        );
+        let vars = vec![given("A", "-"), given("B", "-")];
+        match read_all(vars, &b""[..], &[]) {
+            Err(Error::StdinTaken(first, second)) => {
+                assert_eq!((first, second), ("A".into(), "B".into()))
+            }
+            other => panic!("expected a stdin taken error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_all_inherits_vars_without_values() {
+        let key = format!("LEV_VALUE_TEST_{}", std::process::id());
+        env::set_var(&key, "postgres://localhost");
+        assert_eq!(
+            read_all(vec![(key.clone(), None)], std::io::empty(), &[]).ok(),
+            Some(vec![(key.clone(), "postgres://localhost".into())])
+        );
+        env::remove_var(&key);
+        match read_all(vec![(key.clone(), None)], std::io::empty(), &[]) {
+            Err(Error::NotInherited(missing)) => assert_eq!(missing, key),
+            other => panic!("expected a not inherited error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_env_detects_json_and_dotenv() {
+        let read =
+            |contents: &[u8]| read_env(contents, &[]).map(|vars| vars.into_iter().collect::<Env>());
+        let mut env = Env::new();
+        env.insert("A".into(), "1".into());
+        env.insert("B".into(), "two words".into());
+        assert_eq!(
+            read(b"{\"A\": \"1\", \"B\": \"two words\"}").ok(),
+            Some(env.clone())
+        );
+        assert_eq!(read(b"# a\nA=1\nB=two words\n").ok(), Some(env))
+    }
+
+    #[test]
+    fn is_template_takes_only_values_given_as_they_are() {
+        let template = |value: Option<&str>| is_template(&value.map(String::from));
+        assert!(template(Some("https://{STAGE}.example.com")));
+        assert!(template(Some("@@{HANDLE}")));
+        assert!(!template(Some("@{STAGE}.pem")));
+        assert!(!template(Some("-")));
+        assert!(!template(Some("plain")));
+        assert!(!template(None))
+    }
+
+    #[test]
+    fn read_env_refuses_masked_values() {
+        match read_env(&b"A=1\nAPI_KEY=****\n"[..], &[]) {
+            Err(Error::MaskedValue(key, _)) => assert_eq!(key, "API_KEY"),
+            other => panic!("expected a masked value error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_all_refuses_files_over_the_env_limit() {
+        let path = file("large", &[b'x'; ENV_LIMIT]);
+        match read_all(
+            vec![given("CERT", &format!("@{}", path))],
+            std::io::empty(),
+            &[],
+        ) {
+            Err(Error::ValueTooLarge(key, size, limit)) => {
+                assert_eq!(
+                    (key.as_str(), size, limit),
+                    ("CERT", ENV_LIMIT + 4, ENV_LIMIT)
+                )
+            }
+            other => panic!("expected a value too large error, got {:?}", other),
+        }
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    aws::{self, LambdaClient},
+    error::Error,
+    get, source, Env,
+};
+use futures::future;
+use reqwest::Client;
+use serde::Deserialize;
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    sync::Arc,
+};
+use zip::{result::ZipError, ZipArchive};
+
+/// Name of the file declaring a handler's expectations, at the root of its
+/// deployment package
+pub const MANIFEST: &str = "lev.manifest.json";
+
+/// Env vars a function's handler expects, as declared by a `lev.manifest.json`
+#[derive(Deserialize, Debug, PartialEq, Default)]
+pub struct Expectations {
+    /// Vars the handler can not run without
+    #[serde(default)]
+    pub required: Vec<String>,
+    /// Vars the handler reads when they are set
+    #[serde(default)]
+    pub optional: Vec<String>,
+}
+
+impl Expectations {
+    pub fn parse(contents: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(contents).map_err(Error::from)
+    }
+
+    /// Reads expectations from a local path, an s3://bucket/key or an https://
+    /// url
+    pub async fn fetch(source: &str) -> Result<Self, Error> {
+        Expectations::parse(&source::read(source).await?)
+    }
+
+    /// Required vars an env does not define
+    pub fn missing(
+        &self,
+        env: &Env,
+    ) -> Vec<String> {
+        self.required
+            .iter()
+            .filter(|k| !env.contains_key(*k))
+            .cloned()
+            .collect()
+    }
+}
+
+/// How a function's env measures up to its handler's expectations
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Satisfied,
+    Missing(Vec<String>),
+    /// The deployment package declares no expectations
+    Undeclared,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match self {
+            Verdict::Satisfied => write!(f, "ok"),
+            Verdict::Missing(keys) => write!(f, "missing required {}", keys.join(", ")),
+            Verdict::Undeclared => write!(f, "no {} in deployment package", MANIFEST),
+        }
+    }
+}
+
+/// The expectations a zip archive declares, if it has any
+fn packaged(archive: &[u8]) -> Result<Option<Expectations>, Error> {
+    let mut archive = ZipArchive::new(Cursor::new(archive))?;
+    let mut file = match archive.by_name(MANIFEST) {
+        Err(ZipError::FileNotFound) => return Ok(None),
+        other => other?,
+    };
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Expectations::parse(&contents).map(Some)
+}
+
+/// Downloads a function's deployment package, yielding the expectations it
+/// declares. Container images have no package to read them from
+async fn download(
+    lambda: LambdaClient,
+    function: String,
+) -> Result<Option<Expectations>, Error> {
+    let request = lambda.get_function().function_name(function);
+    let function = aws::retry(|| request.clone().send()).await?;
+    match function.code.and_then(|code| code.location) {
+        None => Ok(None),
+        Some(location) => {
+            let response = Client::new()
+                .get(&location)
+                .send()
+                .await?
+                .error_for_status()?;
+            packaged(&response.bytes().await?)
+        }
+    }
+}
+
+/// Checks a function's env against `expectations`, or those its deployment
+/// package declares when there are none
+pub async fn verify(
+    lambda: LambdaClient,
+    function: String,
+    expectations: Option<Arc<Expectations>>,
+) -> Result<Verdict, Error> {
+    let expected = async {
+        match expectations {
+            Some(expectations) => Ok(Some(expectations)),
+            None => download(lambda.clone(), function.clone())
+                .await
+                .map(|e| e.map(Arc::new)),
+        }
+    };
+    let (expectations, env) = future::try_join(expected, async {
+        Ok(get(lambda.clone(), function.clone()).await?)
+    })
+    .await?;
+    Ok(match expectations {
+        None => Verdict::Undeclared,
+        Some(expectations) => match expectations.missing(&env) {
+            missing if missing.is_empty() => Verdict::Satisfied,
+            missing => Verdict::Missing(missing),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn missing_lists_undefined_required_vars() {
+        let expectations = Expectations {
+            required: vec!["DB_HOST".into(), "DB_PORT".into()],
+            optional: vec!["LOG_LEVEL".into()],
+        };
+        let mut env = Env::new();
+        env.insert("DB_HOST".into(), "localhost".into());
+        assert_eq!(expectations.missing(&env), vec!["DB_PORT".to_string()])
+    }
+
+    #[test]
+    fn packaged_reads_manifest() {
+        assert_eq!(
+            packaged(&archive(&[
+                ("index.js", ""),
+                (MANIFEST, r#"{"required": ["DB_HOST"]}"#)
+            ]))
+            .ok(),
+            Some(Some(Expectations {
+                required: vec!["DB_HOST".into()],
+                optional: Vec::new(),
+            }))
+        );
+        assert_eq!(packaged(&archive(&[("index.js", "")])).ok(), Some(None))
+    }
+}
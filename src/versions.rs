@@ -0,0 +1,101 @@
+use crate::{
+    aws::{self, LambdaClient},
+    diff::{diff, Change},
+    env,
+    error::Error,
+    Env,
+};
+
+/// A function version and the env it was published with
+#[derive(Debug, PartialEq)]
+pub struct Version {
+    pub version: String,
+    pub last_modified: String,
+    pub env: Env,
+}
+
+/// Publication order, with $LATEST following every published version
+fn ordinal(version: &str) -> u64 {
+    version.parse().unwrap_or(u64::MAX)
+}
+
+/// A function's versions in publication order
+pub async fn versions(
+    lambda: LambdaClient,
+    function: String,
+) -> Result<Vec<Version>, Error> {
+    let mut versions = aws::list_versions(lambda, function)
+        .await?
+        .into_iter()
+        .map(|conf| Version {
+            version: conf.version.clone().unwrap_or_default(),
+            last_modified: conf.last_modified.clone().unwrap_or_default(),
+            env: env(conf),
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by_key(|v| ordinal(&v.version));
+    Ok(versions)
+}
+
+/// Each version paired with the changes made since the version before it
+pub fn chain(versions: &[Version]) -> Vec<(&Version, Vec<Change>)> {
+    let empty = Env::default();
+    versions
+        .iter()
+        .scan(&empty, |previous, version| {
+            let changes = diff(previous, &version.env);
+            *previous = &version.env;
+            Some((version, changes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(
+        version: &str,
+        vars: &[(&str, &str)],
+    ) -> Version {
+        Version {
+            version: version.into(),
+            last_modified: String::new(),
+            env: vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ordinal_places_latest_last() {
+        assert!(ordinal("2") < ordinal("10"));
+        assert!(ordinal("10") < ordinal("$LATEST"));
+    }
+
+    #[test]
+    fn chain_diffs_consecutive_versions() {
+        let versions = vec![
+            version("1", &[("A", "1")]),
+            version("2", &[("A", "2"), ("B", "1")]),
+        ];
+        let chained = chain(&versions)
+            .into_iter()
+            .map(|(v, changes)| (v.version.clone(), changes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chained,
+            vec![
+                ("1".to_string(), vec![Change::Added("A".into(), "1".into())]),
+                (
+                    "2".to_string(),
+                    vec![
+                        Change::Changed("A".into(), "1".into(), "2".into()),
+                        Change::Added("B".into(), "1".into()),
+                    ]
+                ),
+            ]
+        )
+    }
+}
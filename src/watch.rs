@@ -0,0 +1,195 @@
+use crate::{
+    aws::{self, LambdaClient},
+    configuration,
+    diff::diff,
+    env, journal,
+    notify::Notifier,
+    Env,
+};
+use chrono::Utc;
+use futures::{stream, Stream, StreamExt};
+use std::{fmt::Display, time::Duration};
+use tokio::time::{self, Instant};
+
+/// Most polls skipped in a row after repeated throttling
+const MAX_BACKOFF_POLLS: usize = 8;
+
+/// Delay before a function's first poll, spreading `count` functions
+/// evenly across one period so they don't poll in bursts
+fn stagger(
+    period: Duration,
+    index: usize,
+    count: usize,
+) -> Duration {
+    period / count.max(1) as u32 * index as u32
+}
+
+/// Number of polls to skip after `throttles` consecutive throttled polls
+fn backoff(throttles: u32) -> usize {
+    (2usize.pow(throttles.min(4)) - 1).min(MAX_BACKOFF_POLLS)
+}
+
+fn annotate<M>(
+    function: &str,
+    message: M,
+) -> String
+where
+    M: Display,
+{
+    format!(
+        "{} {} {}",
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        function,
+        message
+    )
+}
+
+/// What polling one function remembers from one poll to the next
+struct Poller {
+    lambda: LambdaClient,
+    function: String,
+    notifier: Option<Notifier>,
+    previous: Option<Env>,
+    throttles: u32,
+    skip: usize,
+}
+
+impl Poller {
+    /// Annotated lines describing what a poll turned up
+    async fn poll(&mut self) -> Vec<String> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return Vec::new();
+        }
+        let function = self.function.clone();
+        match configuration(self.lambda.clone(), function.clone()).await {
+            Ok(conf) => {
+                self.throttles = 0;
+                let revision = conf.revision_id.clone().unwrap_or_default();
+                let before = match self.previous.replace(env(conf)) {
+                    None => {
+                        let vars = self.previous.as_ref().map(Env::len).unwrap_or_default();
+                        return vec![annotate(&function, format!("watching {} vars", vars))];
+                    }
+                    Some(before) => before,
+                };
+                let changes = self
+                    .previous
+                    .as_ref()
+                    .map(|after| diff(&before, after))
+                    .unwrap_or_default();
+                if changes.is_empty() {
+                    return Vec::new();
+                }
+                let out_of_band = !journal::contains(&function, &revision);
+                if let (true, Some(notifier)) = (out_of_band, self.notifier.clone()) {
+                    let summary = changes
+                        .iter()
+                        .map(|change| change.summary())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let message = format!(
+                        "lev observed an out-of-band env change to {} (revision {})\n{}",
+                        function, revision, summary
+                    );
+                    tokio::spawn(async move {
+                        if let Err(err) = notifier.send(message).await {
+                            errln!("failed to send notification: {}", err)
+                        }
+                    });
+                }
+                let origin = if out_of_band {
+                    "out-of-band"
+                } else {
+                    "via lev"
+                };
+                changes
+                    .into_iter()
+                    .map(|change| annotate(&function, format!("{} ({})", change, origin)))
+                    .collect()
+            }
+            Err(ref err) if aws::is_throttling(err) => {
+                self.throttles += 1;
+                self.skip = backoff(self.throttles);
+                vec![annotate(
+                    &function,
+                    format!("throttled, skipping the next {} polls", self.skip),
+                )]
+            }
+            Err(err) => vec![annotate(&function, format!("error: {}", err))],
+        }
+    }
+}
+
+/// Annotated lines describing changes to one function's env over time.
+/// Changes to revisions lev didn't produce are flagged as out-of-band and,
+/// given a notifier, announced
+fn changes(
+    lambda: LambdaClient,
+    function: String,
+    period: Duration,
+    offset: Duration,
+    notifier: Option<Notifier>,
+) -> impl Stream<Item = String> + Send {
+    let interval = time::interval_at(Instant::now() + offset, period);
+    let poller = Poller {
+        lambda,
+        function,
+        notifier,
+        previous: None,
+        throttles: 0,
+        skip: 0,
+    };
+    stream::unfold(
+        (interval, poller),
+        |(mut interval, mut poller)| async move {
+            interval.tick().await;
+            let lines = poller.poll().await;
+            Some((stream::iter(lines), (interval, poller)))
+        },
+    )
+    .flatten()
+}
+
+/// Polls every function on a staggered schedule, merging their change
+/// streams into one stream of annotated lines
+pub fn watch(
+    lambda: LambdaClient,
+    functions: Vec<String>,
+    period: Duration,
+    notifier: Option<Notifier>,
+) -> impl Stream<Item = String> + Send {
+    let period = period.max(Duration::from_secs(1));
+    let count = functions.len();
+    stream::select_all(functions.into_iter().enumerate().map(|(index, function)| {
+        let offset = stagger(period, index, count);
+        Box::pin(changes(
+            lambda.clone(),
+            function,
+            period,
+            offset,
+            notifier.clone(),
+        ))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stagger_spreads_polls_across_period() {
+        let period = Duration::from_secs(30);
+        assert_eq!(stagger(period, 0, 3), Duration::from_secs(0));
+        assert_eq!(stagger(period, 1, 3), Duration::from_secs(10));
+        assert_eq!(stagger(period, 2, 3), Duration::from_secs(20));
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        assert_eq!(backoff(1), 1);
+        assert_eq!(backoff(2), 3);
+        assert_eq!(backoff(3), 7);
+        assert_eq!(backoff(10), MAX_BACKOFF_POLLS);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{
+    aws::{self, StsClient},
+    error::Error,
+};
+use std::env;
+
+/// Who requests are made as, and where, as lev resolved them
+#[derive(Debug, PartialEq)]
+pub struct Identity {
+    pub account: String,
+    pub arn: String,
+    pub region: String,
+    /// Profile of the AWS config settings are read from
+    pub profile: String,
+}
+
+/// The profile of the AWS config in effect, `default` unless `AWS_PROFILE`
+/// names another
+pub fn profile() -> String {
+    env::var("AWS_PROFILE")
+        .ok()
+        .filter(|profile| !profile.is_empty())
+        .unwrap_or_else(|| "default".into())
+}
+
+/// Asks sts who the credentials lev resolved belong to
+pub async fn whoami(sts: StsClient) -> Result<Identity, Error> {
+    let request = sts.get_caller_identity();
+    let identity = aws::retry(|| request.clone().send()).await?;
+    Ok(Identity {
+        account: identity.account.unwrap_or_default(),
+        arn: identity.arn.unwrap_or_default(),
+        region: aws::region().await.to_string(),
+        profile: profile(),
+    })
+}